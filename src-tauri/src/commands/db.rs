@@ -1,17 +1,36 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use tauri::{Runtime, Window};
-use taurpc;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Runtime, Window};
+use taurpc::{self, Windows};
+use tokio::sync::RwLock;
 
-use crate::db::types::{DbEntity, QueryResult};
+use crate::db::client::{test_connection_string, BatchSink, DatabaseClient};
+use crate::db::errors::DbError;
+use crate::db::script::{may_change_schema, split_statements};
+use crate::db::types::{
+    BindValue, ColumnDefinition, ConnectionTest, DbCapabilities, DbEntity, EntityChanges,
+    ForeignKey, QueryPlan, QueryResult, RelationDetails, RowBatch, ScriptErrorMode, ServerInfo,
+    StatementDescription, TransactionState,
+};
 use crate::errors::AppError;
-use crate::state::get_window_client;
+use crate::state::{get_client_by_label, get_window_client, get_window_project};
 
-#[taurpc::procedures(path = "db", export_to = "../src/lib/taurpc.ts")]
+/// How often each window checks whether its schema changed outside the app
+const SCHEMA_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long testing a connection string waits for the server
+const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[taurpc::procedures(path = "db", export_to = "../src/lib/taurpc.ts", event_trigger = DbEventTrigger)]
 pub trait DbApi {
-    // Test connection with raw connection string
-    // TODO: Implement this w/o a current connection
-    // async fn test_connection_string(conn_string: String) -> Result<String, AppError>;
+    // Sent to a window when its schema changed, with the new schema fingerprint
+    #[taurpc(event)]
+    async fn schema_changed(fingerprint: String);
+
+    // Test a connection string without opening a project, e.g. from the launcher
+    async fn test_connection_string(conn_string: String) -> Result<ConnectionTest, AppError>;
 
     // Checks if the current client is connected
     async fn is_connected(window: Window<impl Runtime>) -> Result<bool, AppError>;
@@ -22,16 +41,184 @@ pub trait DbApi {
     // Disconnect from database
     async fn disconnect(window: Window<impl Runtime>) -> Result<(), AppError>;
 
-    // Execute a single query
+    // Execute a single query, query_id is chosen by the caller so it can be cancelled
     async fn execute_query(
         window: Window<impl Runtime>,
+        query_id: String,
         query: String,
     ) -> Result<QueryResult, AppError>;
 
+    // Execute a query with $1..$n placeholders, binding params in order
+    async fn execute_query_with_params(
+        window: Window<impl Runtime>,
+        query_id: String,
+        query: String,
+        params: Vec<BindValue>,
+    ) -> Result<QueryResult, AppError>;
+
+    // Get the parameter and column types of a query without running it
+    async fn describe_statement(
+        window: Window<impl Runtime>,
+        query: String,
+    ) -> Result<StatementDescription, AppError>;
+
+    // Get a query's plan, running it (inside a rolled back transaction) if analyze is set
+    async fn explain_query(
+        window: Window<impl Runtime>,
+        query: String,
+        analyze: bool,
+        buffers: bool,
+    ) -> Result<QueryPlan, AppError>;
+
+    // Execute a script of one or more statements, returning a result per statement
+    async fn execute_script(
+        window: Window<impl Runtime>,
+        query_id: String,
+        script: String,
+        on_error: ScriptErrorMode,
+    ) -> Result<Vec<QueryResult>, AppError>;
+
+    // Cancel a running query or script, returns false if it already finished
+    async fn cancel_query(window: Window<impl Runtime>, query_id: String)
+        -> Result<bool, AppError>;
+
+    // Stream a query's rows through a cursor. The first page is sent to on_batch
+    // straight away, the rest are requested page by page with fetch_more.
+    async fn stream_query(
+        window: Window<impl Runtime>,
+        query_id: String,
+        query: String,
+        page_size: u32,
+        on_batch: Channel<RowBatch>,
+    ) -> Result<Vec<ColumnDefinition>, AppError>;
+
+    // Send the next page of a streamed query, returns false once there are no more rows
+    async fn fetch_more(window: Window<impl Runtime>, query_id: String) -> Result<bool, AppError>;
+
+    // Stop streaming a query and release its connection
+    async fn close_stream(window: Window<impl Runtime>, query_id: String) -> Result<(), AppError>;
+
+    // Open a transaction, pinning a connection so every query runs inside it
+    async fn begin_transaction(window: Window<impl Runtime>) -> Result<(), AppError>;
+
+    // Commit the open transaction
+    async fn commit(window: Window<impl Runtime>) -> Result<(), AppError>;
+
+    // Roll back the open transaction, or only to a savepoint if one is given
+    async fn rollback(
+        window: Window<impl Runtime>,
+        savepoint: Option<String>,
+    ) -> Result<(), AppError>;
+
+    // Create a savepoint in the open transaction
+    async fn savepoint(window: Window<impl Runtime>, name: String) -> Result<(), AppError>;
+
+    // Get the state of the transaction session, e.g. for the status bar
+    async fn get_transaction_state(
+        window: Window<impl Runtime>,
+    ) -> Result<TransactionState, AppError>;
+
+    // Get what the window's database supports, so the editor and grid can adapt to it
+    async fn get_capabilities(window: Window<impl Runtime>) -> Result<DbCapabilities, AppError>;
+
+    // Get the server version, login and whether the connection is encrypted, e.g. for the status bar
+    async fn get_server_info(window: Window<impl Runtime>) -> Result<ServerInfo, AppError>;
+
     // Get all entities including schemas as a flat list
     async fn get_all_entities(
         window: Window<impl Runtime>,
     ) -> Result<HashMap<String, DbEntity>, AppError>;
+
+    // Get only the entities that changed since the schema had the given fingerprint,
+    // or every entity if it's not given or no longer known
+    async fn get_entity_changes(
+        window: Window<impl Runtime>,
+        since: Option<String>,
+    ) -> Result<EntityChanges, AppError>;
+
+    // Get the DDL script that creates an entity, ready to copy into a migration
+    async fn get_entity_ddl(window: Window<impl Runtime>, id: String) -> Result<String, AppError>;
+
+    // Get the structure of a table or other relation by its entity ID
+    async fn describe_relation(
+        window: Window<impl Runtime>,
+        id: String,
+    ) -> Result<RelationDetails, AppError>;
+
+    // Get every foreign key in the database, for navigating between relations
+    async fn get_foreign_keys(window: Window<impl Runtime>) -> Result<Vec<ForeignKey>, AppError>;
+
+    // Get the row(s) referenced by a foreign key value, e.g. when clicking an FK cell
+    async fn follow_reference(
+        window: Window<impl Runtime>,
+        table_id: String,
+        column: String,
+        value: String,
+    ) -> Result<QueryResult, AppError>;
+}
+
+/// Connects the client if it isn't already. Only takes a write lock when it has to,
+/// so running queries aren't blocked.
+async fn ensure_connected(client: &RwLock<dyn DatabaseClient>) -> Result<(), AppError> {
+    if !client.read().await.is_connected().await? {
+        client.write().await.connect().await?;
+    }
+    Ok(())
+}
+
+fn emit_schema_changed<R: Runtime>(app: &AppHandle<R>, label: &str, fingerprint: String) {
+    let trigger = DbEventTrigger::new(app.clone()).send_to(Windows::One(label.to_string()));
+    if let Err(e) = trigger.schema_changed(fingerprint) {
+        log::warn!("Failed to send schema change to {}: {}", label, e);
+    }
+}
+
+/// Checks for schema changes right away when the user ran something that may
+/// have changed it, instead of waiting for the next poll
+async fn notify_schema_change<R: Runtime>(
+    window: &Window<R>,
+    client: &RwLock<dyn DatabaseClient>,
+    sql: &str,
+) {
+    if !split_statements(sql).into_iter().any(may_change_schema) {
+        return;
+    }
+
+    match client.read().await.check_schema_changed().await {
+        Ok(Some(fingerprint)) => {
+            emit_schema_changed(window.app_handle(), window.label(), fingerprint)
+        }
+        Ok(None) => {}
+        Err(e) => log::debug!("Failed to check for schema changes: {}", e),
+    }
+}
+
+/// Polls a window's schema fingerprint in the background so changes made outside
+/// the app (e.g. by a migration) show up. Stops once the window is closed.
+pub fn watch_schema<R: Runtime>(app: &AppHandle<R>, window_label: &str) {
+    let app = app.clone();
+    let label = window_label.to_string();
+
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEMA_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let Ok(client) = get_client_by_label(&app, &label) else {
+                break;
+            };
+            let guard = client.read().await;
+            if !guard.is_connected().await.unwrap_or(false) {
+                continue;
+            }
+
+            match guard.check_schema_changed().await {
+                Ok(Some(fingerprint)) => emit_schema_changed(&app, &label, fingerprint),
+                Ok(None) => {}
+                Err(e) => log::debug!("Failed to check for schema changes: {}", e),
+            }
+        }
+    });
 }
 
 #[derive(Clone)]
@@ -39,37 +226,215 @@ pub struct DbApiImpl;
 
 #[taurpc::resolvers]
 impl DbApi for DbApiImpl {
+    async fn test_connection_string(self, conn_string: String) -> Result<ConnectionTest, AppError> {
+        Ok(test_connection_string(&conn_string, CONNECTION_TEST_TIMEOUT).await)
+    }
+
     async fn is_connected(self, window: Window<impl Runtime>) -> Result<bool, AppError> {
         let client = get_window_client(&window)?;
-        let guard = client.lock().await;
+        let guard = client.read().await;
         Ok(guard.is_connected().await?)
     }
 
     async fn connect(self, window: Window<impl Runtime>) -> Result<(), AppError> {
         let client = get_window_client(&window)?;
-        let mut guard = client.lock().await;
+        let mut guard = client.write().await;
         Ok(guard.connect().await?)
     }
 
     async fn disconnect(self, window: Window<impl Runtime>) -> Result<(), AppError> {
         let client = get_window_client(&window)?;
-        let mut guard = client.lock().await;
+        let mut guard = client.write().await;
         Ok(guard.disconnect().await?)
     }
 
     async fn execute_query(
         self,
         window: Window<impl Runtime>,
+        query_id: String,
         query: String,
     ) -> Result<QueryResult, AppError> {
         let client = get_window_client(&window)?;
-        let mut guard = client.lock().await;
+        ensure_connected(&client).await?;
 
-        if !guard.is_connected().await? {
-            guard.connect().await?;
-        }
+        let result = client.read().await.execute_query(&query_id, &query).await?;
+        notify_schema_change(&window, &client, &query).await;
+        Ok(result)
+    }
+
+    async fn execute_query_with_params(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+        query: String,
+        params: Vec<BindValue>,
+    ) -> Result<QueryResult, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let result = client
+            .read()
+            .await
+            .execute_query_with_params(&query_id, &query, &params)
+            .await?;
+        notify_schema_change(&window, &client, &query).await;
+        Ok(result)
+    }
+
+    async fn describe_statement(
+        self,
+        window: Window<impl Runtime>,
+        query: String,
+    ) -> Result<StatementDescription, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.describe_statement(&query).await?)
+    }
+
+    async fn explain_query(
+        self,
+        window: Window<impl Runtime>,
+        query: String,
+        analyze: bool,
+        buffers: bool,
+    ) -> Result<QueryPlan, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.explain_query(&query, analyze, buffers).await?)
+    }
+
+    async fn execute_script(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+        script: String,
+        on_error: ScriptErrorMode,
+    ) -> Result<Vec<QueryResult>, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let results = client
+            .read()
+            .await
+            .execute_script(&query_id, &script, on_error)
+            .await?;
+        notify_schema_change(&window, &client, &script).await;
+        Ok(results)
+    }
+
+    async fn cancel_query(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+    ) -> Result<bool, AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.cancel_query(&query_id).await?)
+    }
+
+    async fn stream_query(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+        query: String,
+        page_size: u32,
+        on_batch: Channel<RowBatch>,
+    ) -> Result<Vec<ColumnDefinition>, AppError> {
+        let max_rows = get_window_project(&window)?.max_rows;
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let sink: BatchSink = Box::new(move |batch| {
+            on_batch
+                .send(batch)
+                .map_err(|e| DbError::Other(format!("Failed to send rows: {}", e)))
+        });
+
+        let guard = client.read().await;
+        Ok(guard
+            .open_cursor(&query_id, &query, page_size.into(), max_rows, sink)
+            .await?)
+    }
+
+    async fn fetch_more(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+    ) -> Result<bool, AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.fetch_cursor(&query_id).await?)
+    }
+
+    async fn close_stream(
+        self,
+        window: Window<impl Runtime>,
+        query_id: String,
+    ) -> Result<(), AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.close_cursor(&query_id).await?)
+    }
+
+    async fn begin_transaction(self, window: Window<impl Runtime>) -> Result<(), AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.begin_transaction().await?)
+    }
+
+    async fn commit(self, window: Window<impl Runtime>) -> Result<(), AppError> {
+        let client = get_window_client(&window)?;
+        client.read().await.commit().await?;
+        notify_schema_change(&window, &client, "COMMIT").await;
+        Ok(())
+    }
+
+    async fn rollback(
+        self,
+        window: Window<impl Runtime>,
+        savepoint: Option<String>,
+    ) -> Result<(), AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.rollback(savepoint.as_deref()).await?)
+    }
+
+    async fn savepoint(self, window: Window<impl Runtime>, name: String) -> Result<(), AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.savepoint(&name).await?)
+    }
+
+    async fn get_transaction_state(
+        self,
+        window: Window<impl Runtime>,
+    ) -> Result<TransactionState, AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.transaction_state())
+    }
 
-        Ok(guard.execute_query(&query).await?)
+    async fn get_capabilities(
+        self,
+        window: Window<impl Runtime>,
+    ) -> Result<DbCapabilities, AppError> {
+        let client = get_window_client(&window)?;
+        let guard = client.read().await;
+        Ok(guard.capabilities())
+    }
+
+    async fn get_server_info(self, window: Window<impl Runtime>) -> Result<ServerInfo, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let info = client.read().await.server_info().await?;
+        Ok(info)
     }
 
     async fn get_all_entities(
@@ -77,12 +442,70 @@ impl DbApi for DbApiImpl {
         window: Window<impl Runtime>,
     ) -> Result<HashMap<String, DbEntity>, AppError> {
         let client = get_window_client(&window)?;
-        let mut guard = client.lock().await;
-
-        if !guard.is_connected().await? {
-            guard.connect().await?;
-        }
+        ensure_connected(&client).await?;
 
+        let guard = client.read().await;
         Ok(guard.get_all_entities().await?)
     }
+
+    async fn get_entity_changes(
+        self,
+        window: Window<impl Runtime>,
+        since: Option<String>,
+    ) -> Result<EntityChanges, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.get_entity_changes(since.as_deref()).await?)
+    }
+
+    async fn get_entity_ddl(
+        self,
+        window: Window<impl Runtime>,
+        id: String,
+    ) -> Result<String, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.get_entity_ddl(&id).await?)
+    }
+
+    async fn describe_relation(
+        self,
+        window: Window<impl Runtime>,
+        id: String,
+    ) -> Result<RelationDetails, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.describe_relation(&id).await?)
+    }
+
+    async fn get_foreign_keys(
+        self,
+        window: Window<impl Runtime>,
+    ) -> Result<Vec<ForeignKey>, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.get_foreign_keys().await?)
+    }
+
+    async fn follow_reference(
+        self,
+        window: Window<impl Runtime>,
+        table_id: String,
+        column: String,
+        value: String,
+    ) -> Result<QueryResult, AppError> {
+        let client = get_window_client(&window)?;
+        ensure_connected(&client).await?;
+
+        let guard = client.read().await;
+        Ok(guard.follow_reference(&table_id, &column, &value).await?)
+    }
 }
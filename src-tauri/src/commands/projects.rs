@@ -2,12 +2,15 @@ use tauri::{Runtime, Window};
 use taurpc;
 
 use crate::errors::AppError;
-use crate::project::Project;
+use crate::project::{EnvDiagnostics, Project};
 use crate::state::get_window_project;
 
 #[taurpc::procedures(path = "projects", export_to = "../src/lib/taurpc.ts", event_trigger = ProjectEventTrigger)]
 pub trait ProjectsApi {
     async fn get_project(window: Window<impl Runtime>) -> Result<Project, AppError>;
+
+    // Get which env files the project loads and where its connection variables come from
+    async fn get_env_diagnostics(window: Window<impl Runtime>) -> Result<EnvDiagnostics, AppError>;
 }
 
 #[derive(Clone)]
@@ -19,4 +22,12 @@ impl ProjectsApi for ProjectsApiImpl {
         let project = get_window_project(&window)?;
         Ok((*project).clone())
     }
+
+    async fn get_env_diagnostics(
+        self,
+        window: Window<impl Runtime>,
+    ) -> Result<EnvDiagnostics, AppError> {
+        let project = get_window_project(&window)?;
+        project.env_diagnostics()
+    }
 }
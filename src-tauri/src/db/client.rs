@@ -1,24 +1,55 @@
 #![allow(dead_code)]
 
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use tokio::sync::RwLock;
 use url::Url;
 
 use crate::db::errors::{DbError, DbResult};
-use crate::db::types::{DbEntity, QueryResult};
+use crate::db::pool::PoolSettings;
+use crate::db::tls::TlsSettings;
+use crate::db::tunnel::SshSettings;
+use crate::db::types::{
+    BindValue, ColumnDefinition, ConnectionErrorKind, ConnectionTest, DbCapabilities, DbEntity,
+    Dialect, EntityChanges, ForeignKey, QueryPlan, QueryResult, RelationDetails, RowBatch,
+    ScriptErrorMode, ServerInfo, StatementDescription, TransactionState,
+};
+
+/// Receives the batches of rows streamed from a cursor
+pub type BatchSink = Box<dyn Fn(RowBatch) -> DbResult<()> + Send + Sync>;
 
 /// Core database client interface for all database operations
 #[async_trait]
 pub trait DatabaseClient: Send + Sync {
     fn get_connection_string(&self) -> String;
 
+    /// SQL dialect the database speaks
+    fn dialect(&self) -> Dialect;
+
+    /// What the connection supports, e.g. to hide transaction controls
+    fn capabilities(&self) -> DbCapabilities {
+        self.dialect().capabilities()
+    }
+
     /// Check if the database is connected
     async fn is_connected(&self) -> DbResult<bool>;
 
     /// Test the database connection
     async fn test_connection(&self) -> DbResult<()>;
 
+    /// Open a single connection outside the pool and report the server it reached.
+    /// This works without `connect`, so a connection string can be checked before
+    /// a project uses it.
+    async fn probe_server(&self) -> DbResult<ServerInfo>;
+
+    /// Report the server the client is connected to, e.g. whether it's using TLS
+    async fn server_info(&self) -> DbResult<ServerInfo> {
+        self.probe_server().await
+    }
+
     /// Connect to the database
     async fn connect(&mut self) -> DbResult<()>;
 
@@ -31,25 +62,254 @@ pub trait DatabaseClient: Send + Sync {
     /// Update the connection string & attempt to reconnect
     async fn reconnect_with_string(&mut self, connection_string: &str) -> DbResult<()>;
 
-    /// Execute a raw SQL query
-    async fn execute_query(&self, sql: &str) -> DbResult<QueryResult>;
+    /// Execute a raw SQL query, tracked by `query_id` so it can be cancelled
+    async fn execute_query(&self, query_id: &str, sql: &str) -> DbResult<QueryResult>;
+
+    /// Execute a query with placeholders (`$1..$n`, or `?` for MySQL), binding `params` in order
+    async fn execute_query_with_params(
+        &self,
+        _query_id: &str,
+        _sql: &str,
+        _params: &[BindValue],
+    ) -> DbResult<QueryResult> {
+        unsupported("query parameters")
+    }
+
+    /// Prepare a query without running it, to get its parameter and column types
+    async fn describe_statement(&self, _sql: &str) -> DbResult<StatementDescription> {
+        unsupported("describing statements")
+    }
+
+    /// Capture a query's plan with `EXPLAIN`. With `analyze` the query is run
+    /// (and rolled back) to get actual row counts and timings.
+    async fn explain_query(
+        &self,
+        _sql: &str,
+        _analyze: bool,
+        _buffers: bool,
+    ) -> DbResult<QueryPlan> {
+        unsupported("query plans")
+    }
+
+    /// Execute a script of one or more statements sequentially on a single connection
+    async fn execute_script(
+        &self,
+        query_id: &str,
+        sql: &str,
+        on_error: ScriptErrorMode,
+    ) -> DbResult<Vec<QueryResult>>;
+
+    /// Cancel a running query or script, returns false if it wasn't running
+    async fn cancel_query(&self, _query_id: &str) -> DbResult<bool> {
+        unsupported("cancelling queries")
+    }
+
+    /// Open a cursor for a query and send its first page of rows to `sink`.
+    /// At most `max_rows` rows will ever be fetched. Returns the cursor's columns.
+    async fn open_cursor(
+        &self,
+        _query_id: &str,
+        _sql: &str,
+        _page_size: u64,
+        _max_rows: u64,
+        _sink: BatchSink,
+    ) -> DbResult<Vec<ColumnDefinition>> {
+        unsupported("cursors")
+    }
+
+    /// Send the next page of a cursor to its sink, returns false once it has no more rows
+    async fn fetch_cursor(&self, _query_id: &str) -> DbResult<bool> {
+        unsupported("cursors")
+    }
+
+    /// Close a cursor and release its connection
+    async fn close_cursor(&self, _query_id: &str) -> DbResult<()> {
+        unsupported("cursors")
+    }
+
+    /// Open a transaction on a dedicated connection. Every query runs on that
+    /// connection until the transaction is committed or rolled back.
+    async fn begin_transaction(&self) -> DbResult<()> {
+        unsupported("transaction sessions")
+    }
+
+    /// Commit the open transaction and release its connection
+    async fn commit(&self) -> DbResult<()> {
+        unsupported("transaction sessions")
+    }
+
+    /// Roll back the open transaction and release its connection, or only roll
+    /// back to `savepoint` and keep the transaction open
+    async fn rollback(&self, _savepoint: Option<&str>) -> DbResult<()> {
+        unsupported("transaction sessions")
+    }
+
+    /// Create a savepoint in the open transaction
+    async fn savepoint(&self, _name: &str) -> DbResult<()> {
+        unsupported("transaction sessions")
+    }
+
+    /// Current state of the transaction session, readable without awaiting
+    fn transaction_state(&self) -> TransactionState {
+        TransactionState::default()
+    }
 
     /// Get a flat list of all entities including schemas
     async fn get_all_entities(&self) -> DbResult<HashMap<String, DbEntity>>;
+
+    /// Compute a fingerprint of the schema, which changes whenever any entity does
+    async fn schema_fingerprint(&self) -> DbResult<String>;
+
+    /// Get the entities that changed since the schema had the `since` fingerprint.
+    /// If `since` is unknown (or not given) every entity is returned.
+    async fn get_entity_changes(&self, since: Option<&str>) -> DbResult<EntityChanges>;
+
+    /// Returns the new fingerprint if the schema changed since the last call
+    async fn check_schema_changed(&self) -> DbResult<Option<String>>;
+
+    /// Reconstruct the DDL script that creates an entity, e.g. its `CREATE TABLE`
+    async fn get_entity_ddl(&self, _id: &str) -> DbResult<String> {
+        unsupported("entity DDL")
+    }
+
+    /// Describe the columns, constraints and foreign keys of a relation by its entity ID
+    async fn describe_relation(&self, _id: &str) -> DbResult<RelationDetails> {
+        unsupported("describing relations")
+    }
+
+    /// Get every foreign key in the database, forming a graph between table entity IDs
+    async fn get_foreign_keys(&self) -> DbResult<Vec<ForeignKey>> {
+        unsupported("foreign keys")
+    }
+
+    /// Fetch the row(s) a foreign key `column` of a table points to for `value`
+    async fn follow_reference(
+        &self,
+        _table_id: &str,
+        _column: &str,
+        _value: &str,
+    ) -> DbResult<QueryResult> {
+        unsupported("following references")
+    }
+}
+
+/// Default for the optional parts of `DatabaseClient` a backend doesn't implement
+fn unsupported<T>(operation: &str) -> DbResult<T> {
+    Err(DbError::Unsupported(format!(
+        "this database doesn't support {}",
+        operation
+    )))
 }
 
-/// Creates a database client based on connection info without establishing a connection
-pub fn create_client(url: &Url) -> DbResult<impl DatabaseClient> {
+/// Creates a database client based on connection info without establishing a connection.
+/// The client is wrapped so it can be shared between commands.
+pub fn create_client(
+    url: &Url,
+    pool_settings: &PoolSettings,
+    ssh: Option<&SshSettings>,
+    tls: Option<&TlsSettings>,
+) -> DbResult<Arc<RwLock<dyn DatabaseClient>>> {
+    use crate::db::mysql::MySqlClient;
     use crate::db::postgres::PostgresClient;
+    use crate::db::sqlite::SqliteClient;
+
+    let is_postgres = matches!(url.scheme(), "postgres" | "postgresql");
+    if ssh.is_some() && !is_postgres {
+        return Err(DbError::Unsupported(
+            "SSH tunnels are only supported for Postgres".to_string(),
+        ));
+    }
+    let is_mysql = matches!(url.scheme(), "mysql" | "mariadb");
+    if tls.is_some() && !is_postgres && !is_mysql {
+        return Err(DbError::Unsupported(
+            "TLS settings are only supported for Postgres and MySQL".to_string(),
+        ));
+    }
 
     match url.scheme() {
         "postgres" | "postgresql" => {
-            let client = PostgresClient::new(url.to_string().as_str())?;
-            Ok(client)
+            let client = PostgresClient::new(
+                url.to_string().as_str(),
+                pool_settings.clone(),
+                ssh.cloned(),
+                tls.cloned(),
+            )?;
+            Ok(Arc::new(RwLock::new(client)))
+        }
+        "mysql" | "mariadb" => {
+            let client = MySqlClient::new(
+                url.to_string().as_str(),
+                pool_settings.clone(),
+                tls.cloned(),
+            )?;
+            Ok(Arc::new(RwLock::new(client)))
         }
+        "sqlite" => {
+            let client = SqliteClient::new(url.to_string().as_str())?;
+            Ok(Arc::new(RwLock::new(client)))
+        }
+        #[cfg(feature = "duckdb")]
+        "duckdb" => {
+            let client = crate::db::duckdb::DuckDbClient::new(url.to_string().as_str())?;
+            Ok(Arc::new(RwLock::new(client)))
+        }
+        #[cfg(not(feature = "duckdb"))]
+        "duckdb" => Err(DbError::Unsupported(
+            "DuckDB support isn't enabled in this build".to_string(),
+        )),
         _ => Err(DbError::Unsupported(format!(
             "Unsupported database type: {}",
             url.scheme()
         ))),
     }
 }
+
+/// Checks a connection string without opening a project, giving up after `timeout`.
+/// Failures are reported in the result with their cause, rather than as an error.
+pub async fn test_connection_string(connection_string: &str, timeout: Duration) -> ConnectionTest {
+    let started = Instant::now();
+    let mut test = ConnectionTest {
+        dialect: None,
+        latency_ms: 0,
+        server: None,
+        error_kind: None,
+        error: None,
+    };
+
+    let probed: DbResult<ServerInfo> = async {
+        let client = create_client(
+            &Url::parse(connection_string)?,
+            &PoolSettings::default(),
+            None,
+            None,
+        )?;
+        let client = client.read().await;
+        test.dialect = Some(client.dialect());
+
+        tokio::time::timeout(timeout, client.probe_server())
+            .await
+            .unwrap_or_else(|_| {
+                Err(DbError::ConnectionFailed(
+                    ConnectionErrorKind::Timeout,
+                    format!("No response within {} seconds", timeout.as_secs()),
+                ))
+            })
+    }
+    .await;
+
+    test.latency_ms = started.elapsed().as_millis() as u64;
+    match probed {
+        Ok(server) => test.server = Some(server),
+        Err(e) => {
+            test.error_kind = Some(match &e {
+                DbError::ConnectionFailed(kind, _) => *kind,
+                DbError::Config(_) => ConnectionErrorKind::InvalidUrl,
+                DbError::Unsupported(_) => ConnectionErrorKind::Unsupported,
+                _ => ConnectionErrorKind::Other,
+            });
+            test.error = Some(e.to_string());
+        }
+    }
+
+    test
+}
@@ -0,0 +1,221 @@
+use crate::db::types::{DbCapabilities, Dialect, PlaceholderStyle};
+
+/// Keywords shared by every dialect
+const COMMON_KEYWORDS: &[&str] = &[
+    "ADD",
+    "ALL",
+    "ALTER",
+    "AND",
+    "AS",
+    "ASC",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLUMN",
+    "COMMIT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "DEFAULT",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DROP",
+    "ELSE",
+    "END",
+    "EXISTS",
+    "EXPLAIN",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSERT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "KEY",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PRIMARY",
+    "REFERENCES",
+    "RIGHT",
+    "ROLLBACK",
+    "SAVEPOINT",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "THEN",
+    "TRIGGER",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VALUES",
+    "VIEW",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+];
+
+const POSTGRES_KEYWORDS: &[&str] = &[
+    "ANALYZE",
+    "ARRAY",
+    "CONFLICT",
+    "COPY",
+    "DEFERRABLE",
+    "DO",
+    "DOMAIN",
+    "EXTENSION",
+    "FETCH",
+    "FILTER",
+    "FUNCTION",
+    "GRANT",
+    "ILIKE",
+    "LATERAL",
+    "LISTEN",
+    "MATERIALIZED",
+    "NOTIFY",
+    "NOTHING",
+    "PROCEDURE",
+    "RECURSIVE",
+    "REFRESH",
+    "RETURNING",
+    "REVOKE",
+    "SCHEMA",
+    "SEQUENCE",
+    "SIMILAR",
+    "TRUNCATE",
+    "TYPE",
+    "VACUUM",
+];
+
+const MYSQL_KEYWORDS: &[&str] = &[
+    "AUTO_INCREMENT",
+    "CHANGE",
+    "DATABASE",
+    "DATABASES",
+    "DELIMITER",
+    "DESCRIBE",
+    "DUPLICATE",
+    "ENGINE",
+    "FUNCTION",
+    "IGNORE",
+    "MODIFY",
+    "PROCEDURE",
+    "RECURSIVE",
+    "REGEXP",
+    "RENAME",
+    "REPLACE",
+    "SCHEMA",
+    "SHOW",
+    "STRAIGHT_JOIN",
+    "TABLES",
+    "TRUNCATE",
+    "UNSIGNED",
+    "USE",
+];
+
+const SQLITE_KEYWORDS: &[&str] = &[
+    "ATTACH",
+    "AUTOINCREMENT",
+    "CONFLICT",
+    "DETACH",
+    "DO",
+    "GLOB",
+    "IGNORE",
+    "NOTHING",
+    "PRAGMA",
+    "RECURSIVE",
+    "REINDEX",
+    "REPLACE",
+    "RETURNING",
+    "ROWID",
+    "STRICT",
+    "VACUUM",
+    "WITHOUT",
+];
+
+const DUCKDB_KEYWORDS: &[&str] = &[
+    "ANALYZE",
+    "ATTACH",
+    "CONFLICT",
+    "COPY",
+    "DESCRIBE",
+    "DETACH",
+    "EXCLUDE",
+    "EXPORT",
+    "FILTER",
+    "ILIKE",
+    "IMPORT",
+    "INSTALL",
+    "LOAD",
+    "MACRO",
+    "PIVOT",
+    "PRAGMA",
+    "QUALIFY",
+    "RECURSIVE",
+    "REPLACE",
+    "RETURNING",
+    "SAMPLE",
+    "SCHEMA",
+    "SEQUENCE",
+    "SUMMARIZE",
+    "TYPE",
+    "UNPIVOT",
+];
+
+impl Dialect {
+    /// What the dialect supports, which is the same for every connection of a
+    /// backend. Features a client has to implement are all off.
+    pub fn capabilities(self) -> DbCapabilities {
+        let (extra_keywords, quote, placeholders) = match self {
+            Dialect::Postgres => (POSTGRES_KEYWORDS, '"', PlaceholderStyle::Numbered),
+            Dialect::MySql => (MYSQL_KEYWORDS, '`', PlaceholderStyle::QuestionMark),
+            Dialect::Sqlite => (SQLITE_KEYWORDS, '"', PlaceholderStyle::QuestionMark),
+            Dialect::DuckDb => (DUCKDB_KEYWORDS, '"', PlaceholderStyle::QuestionMark),
+        };
+
+        let mut keywords: Vec<String> = COMMON_KEYWORDS
+            .iter()
+            .chain(extra_keywords)
+            .map(|keyword| keyword.to_string())
+            .collect();
+        keywords.sort();
+        keywords.dedup();
+
+        DbCapabilities {
+            dialect: self,
+            // Clients turn on the features they implement
+            transactions: false,
+            savepoints: false,
+            schemas: true,
+            cancellation: false,
+            query_plans: false,
+            analyzed_plans: false,
+            explain_formats: Vec::new(),
+            // MariaDB supports RETURNING on INSERT and DELETE, MySQL doesn't at all
+            returning: self != Dialect::MySql,
+            identifier_quote: quote,
+            placeholder_style: placeholders,
+            keywords,
+        }
+    }
+}
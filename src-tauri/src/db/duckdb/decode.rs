@@ -0,0 +1,105 @@
+use duckdb::types::{TimeUnit, Value};
+use sqlx::postgres::types::PgInterval;
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::db::{helpers::to_hex, postgres::decode::format_interval, types::CellValue};
+
+/// Days between the Julian day epoch and the Unix epoch, which DuckDB dates count from
+const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+
+/// Converts a DuckDB value into a cell.
+///
+/// Values come out of DuckDB already typed, so no column type is needed. `TIMESTAMPTZ`
+/// values are returned in UTC without their type, so they're shown as plain timestamps.
+pub(super) fn to_cell(value: Value) -> CellValue {
+    match value {
+        Value::Null => CellValue::Null,
+        Value::Boolean(v) => CellValue::Bool(v),
+        Value::TinyInt(v) => CellValue::Int(v.into()),
+        Value::SmallInt(v) => CellValue::Int(v.into()),
+        Value::Int(v) => CellValue::Int(v.into()),
+        Value::BigInt(v) => CellValue::Int(v),
+        Value::UTinyInt(v) => CellValue::Int(v.into()),
+        Value::USmallInt(v) => CellValue::Int(v.into()),
+        Value::UInt(v) => CellValue::Int(v.into()),
+        Value::UBigInt(v) => i64::try_from(v)
+            .map(CellValue::Int)
+            .unwrap_or_else(|_| CellValue::Numeric(v.to_string())),
+        Value::HugeInt(v) => i64::try_from(v)
+            .map(CellValue::Int)
+            .unwrap_or_else(|_| CellValue::Numeric(v.to_string())),
+        Value::Float(v) => CellValue::Float(v.into()),
+        Value::Double(v) => CellValue::Float(v),
+        Value::Decimal(v) => CellValue::Numeric(v.to_string()),
+        Value::Text(v) | Value::Enum(v) => CellValue::Text(v),
+        Value::Blob(v) => CellValue::Bytes(to_hex(&v)),
+        Value::Date32(days) => match Date::from_julian_day(UNIX_EPOCH_JULIAN_DAY + days) {
+            Ok(date) => CellValue::Date(date.to_string()),
+            Err(_) => to_raw(&Value::Date32(days)),
+        },
+        Value::Time64(unit, v) => {
+            let time = Time::MIDNIGHT + Duration::microseconds(unit.to_micros(v));
+            CellValue::Time(time.to_string())
+        }
+        Value::Timestamp(unit, v) => match timestamp(unit, v) {
+            Some(timestamp) => CellValue::Timestamp(timestamp.to_string()),
+            None => to_raw(&Value::Timestamp(unit, v)),
+        },
+        Value::Interval {
+            months,
+            days,
+            nanos,
+        } => CellValue::Interval(format_interval(&PgInterval {
+            months,
+            days,
+            microseconds: nanos / 1_000,
+        })),
+        Value::List(values) | Value::Array(values) => {
+            CellValue::Array(values.into_iter().map(to_cell).collect())
+        }
+        // Structs, maps and unions don't have a cell type of their own
+        other => to_raw(&other),
+    }
+}
+
+/// Fallback for values without a cell type of their own, or dates and timestamps
+/// outside the range they can be shown in, e.g. `Date32(-999999999)`
+fn to_raw(value: &Value) -> CellValue {
+    CellValue::Text(format!("{:?}", value))
+}
+
+fn timestamp(unit: TimeUnit, value: i64) -> Option<PrimitiveDateTime> {
+    let nanos = i128::from(unit.to_micros(value)) * 1_000;
+    let datetime = OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()?;
+    Some(PrimitiveDateTime::new(datetime.date(), datetime.time()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_dates_and_timestamps_in_range() {
+        assert_eq!(
+            to_cell(Value::Date32(19_782)),
+            CellValue::Date("2024-02-29".to_string())
+        );
+        assert_eq!(
+            to_cell(Value::Timestamp(TimeUnit::Millisecond, 1_709_208_000_500)),
+            CellValue::Timestamp("2024-02-29 12:00:00.5".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_out_of_range_values_instead_of_nulls() {
+        let far_future = 1_000_000_000_000_000;
+        assert_eq!(
+            to_cell(Value::Timestamp(TimeUnit::Millisecond, far_future)),
+            CellValue::Text(format!("Timestamp(Millisecond, {})", far_future))
+        );
+        assert_eq!(
+            to_cell(Value::Date32(100_000_000)),
+            CellValue::Text("Date32(100000000)".to_string())
+        );
+    }
+}
@@ -0,0 +1,569 @@
+mod decode;
+
+use async_trait::async_trait;
+use duckdb::{params, types::Value, Connection, Statement};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use url::Url;
+
+use crate::db::{
+    client::DatabaseClient,
+    errors::{DbError, DbResult},
+    helpers::{quote_ident, result_column},
+    schema::SchemaTracker,
+    script::{leading_keyword, split_statements},
+    types::{
+        CellValue, ColumnDefinition, ConnectionErrorKind, DbEntity, Dialect, EntityChanges,
+        QueryResult, SchemaEntity, SchemaLevelEntity, ScriptErrorMode, ServerInfo,
+        TableLevelEntity,
+    },
+};
+
+/// Files that are opened as tables in an in-memory database
+const FILE_EXTENSIONS: [&str; 3] = ["csv", "tsv", "parquet"];
+
+/// What a `duckdb:` URL points at
+enum Source {
+    /// A new in-memory database (`duckdb:` or `duckdb::memory:`)
+    Memory,
+    /// A DuckDB database file
+    Database(PathBuf),
+    /// A CSV or Parquet file, opened in an in-memory database next to the
+    /// other data files in its directory
+    DataFile(PathBuf),
+}
+
+impl Source {
+    fn from_connection_string(connection_string: &str) -> DbResult<Self> {
+        let url = Url::parse(connection_string)?;
+        if url.path().is_empty() || url.path() == "/" || url.path().ends_with(":memory:") {
+            return Ok(Source::Memory);
+        }
+
+        let path = url
+            .to_file_path()
+            .map_err(|_| DbError::Config(format!("Invalid DuckDB file path: {}", url.path())))?;
+        if is_data_file(&path) {
+            Ok(Source::DataFile(path))
+        } else {
+            Ok(Source::Database(path))
+        }
+    }
+
+    /// Opens the database. For data files this also creates a view for each
+    /// readable file in the directory and returns their names.
+    fn open(self) -> DbResult<(Connection, HashSet<String>)> {
+        match self {
+            Source::Memory => Ok((Connection::open_in_memory()?, HashSet::new())),
+            Source::Database(path) => Ok((Connection::open(path)?, HashSet::new())),
+            Source::DataFile(path) => {
+                let conn = Connection::open_in_memory()?;
+                let directory = path.parent().unwrap_or(Path::new("."));
+
+                // The opened file goes first so it keeps its name if another file shares it
+                let mut files = vec![path.clone()];
+                for entry in std::fs::read_dir(directory)? {
+                    let sibling = entry?.path();
+                    if sibling != path && is_data_file(&sibling) {
+                        files.push(sibling);
+                    }
+                }
+
+                let mut file_tables = HashSet::new();
+                for file in files {
+                    let Some(name) = file.file_stem().map(|s| s.to_string_lossy().to_string())
+                    else {
+                        continue;
+                    };
+                    if file_tables.contains(&name) {
+                        log::warn!(
+                            "Skipping {}, a file named {} is already open",
+                            file.display(),
+                            name
+                        );
+                        continue;
+                    }
+
+                    let created = conn.execute_batch(&format!(
+                        "CREATE VIEW {} AS SELECT * FROM {}",
+                        quote_ident(&name),
+                        scan_function(&file)
+                    ));
+                    match created {
+                        Ok(()) => {
+                            file_tables.insert(name);
+                        }
+                        // A neighbouring file that can't be read shouldn't keep the opened one closed
+                        Err(e) if file != path => {
+                            log::warn!("Skipping {}: {}", file.display(), e);
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+
+                Ok((conn, file_tables))
+            }
+        }
+    }
+}
+
+pub struct DuckDbClient {
+    connection_string: String,
+    /// DuckDB's API is blocking, queries run on the blocking thread pool
+    connection: Option<Arc<Mutex<Connection>>>,
+    /// Views created for CSV and Parquet files, which are listed as tables
+    file_tables: HashSet<String>,
+    schema_tracker: SchemaTracker,
+}
+
+impl DuckDbClient {
+    pub fn new(connection_string: &str) -> DbResult<Self> {
+        Ok(Self {
+            connection_string: connection_string.to_string(),
+            connection: None,
+            file_tables: HashSet::new(),
+            schema_tracker: SchemaTracker::default(),
+        })
+    }
+
+    // This function gets the connection or returns an error if not connected
+    fn get_connection(&self) -> DbResult<&Arc<Mutex<Connection>>> {
+        self.connection
+            .as_ref()
+            .ok_or_else(|| DbError::Connection("Database client is not connected".to_string()))
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool
+    async fn with_connection<T, F>(&self, f: F) -> DbResult<T>
+    where
+        F: FnOnce(&Connection) -> DbResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.get_connection()?.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = connection
+                .lock()
+                .map_err(|_| DbError::Connection("DuckDB connection was poisoned".to_string()))?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| DbError::Other(format!("DuckDB task failed: {}", e)))?
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for DuckDbClient {
+    fn get_connection_string(&self) -> String {
+        self.connection_string.clone()
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::DuckDb
+    }
+
+    async fn is_connected(&self) -> DbResult<bool> {
+        Ok(self.connection.is_some())
+    }
+
+    async fn test_connection(&self) -> DbResult<()> {
+        self.with_connection(|conn| {
+            conn.execute_batch("SELECT 1")?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn probe_server(&self) -> DbResult<ServerInfo> {
+        let source = Source::from_connection_string(&self.connection_string)?;
+        tokio::task::spawn_blocking(move || {
+            // Opening a database file would create it
+            if let Source::Database(path) = &source {
+                if !path.is_file() {
+                    return Err(DbError::ConnectionFailed(
+                        ConnectionErrorKind::DatabaseMissing,
+                        format!("{} doesn't exist", path.display()),
+                    ));
+                }
+            }
+
+            let (conn, _) = source.open()?;
+            server_info(&conn)
+        })
+        .await
+        .map_err(|e| DbError::Other(format!("DuckDB task failed: {}", e)))?
+    }
+
+    async fn server_info(&self) -> DbResult<ServerInfo> {
+        // A second connection to the same file would conflict with this one's lock
+        self.with_connection(server_info).await
+    }
+
+    async fn connect(&mut self) -> DbResult<()> {
+        // Check if already connected
+        if self.connection.is_some() {
+            return Ok(());
+        }
+
+        let source = Source::from_connection_string(&self.connection_string)?;
+        let (conn, file_tables) = tokio::task::spawn_blocking(move || source.open())
+            .await
+            .map_err(|e| DbError::Connection(format!("DuckDB task failed: {}", e)))??;
+
+        self.connection = Some(Arc::new(Mutex::new(conn)));
+        self.file_tables = file_tables;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> DbResult<()> {
+        // The database is closed once a running query lets go of the connection
+        self.connection = None;
+        self.file_tables.clear();
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connect().await
+    }
+
+    async fn reconnect_with_string(&mut self, connection_string: &str) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connection_string = connection_string.to_string();
+        self.connect().await
+    }
+
+    async fn execute_query(&self, _query_id: &str, sql: &str) -> DbResult<QueryResult> {
+        let sql = sql.to_string();
+        self.with_connection(move |conn| {
+            // DuckDB only prepares one statement at a time, the last result is returned
+            let statements = split_statements(&sql);
+            let Some((last, rest)) = statements.split_last() else {
+                return Ok(QueryResult::new(&sql, 0));
+            };
+            for statement in rest {
+                conn.execute_batch(statement)?;
+            }
+            run_query(conn, last, 0)
+        })
+        .await
+    }
+
+    async fn execute_script(
+        &self,
+        _query_id: &str,
+        sql: &str,
+        on_error: ScriptErrorMode,
+    ) -> DbResult<Vec<QueryResult>> {
+        let sql = sql.to_string();
+        self.with_connection(move |conn| {
+            let mut results = Vec::new();
+
+            for (i, statement) in split_statements(&sql).into_iter().enumerate() {
+                let started = Instant::now();
+                match run_query(conn, statement, i) {
+                    Ok(result) => results.push(result),
+                    Err(e) => {
+                        let mut result = QueryResult::new(statement, i);
+                        result.execution_time_ms = started.elapsed().as_millis() as u64;
+                        result.error = Some(e.to_string());
+                        results.push(result);
+
+                        if on_error == ScriptErrorMode::Stop {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            Ok(results)
+        })
+        .await
+    }
+
+    async fn get_all_entities(&self) -> DbResult<HashMap<String, DbEntity>> {
+        let file_tables = self.file_tables.clone();
+        self.with_connection(move |conn| all_entities(conn, &file_tables))
+            .await
+    }
+
+    async fn schema_fingerprint(&self) -> DbResult<String> {
+        // DuckDB has no schema version, so the stored definitions are hashed instead
+        let fingerprint_query = r#"
+            SELECT md5(coalesce(string_agg(definition, ';' ORDER BY definition), ''))
+            FROM (
+                SELECT 'schema ' || schema_name AS definition
+                FROM duckdb_schemas() WHERE database_name = current_database()
+                UNION ALL
+                SELECT coalesce(sql, table_name)
+                FROM duckdb_tables() WHERE database_name = current_database()
+                UNION ALL
+                SELECT coalesce(sql, view_name)
+                FROM duckdb_views() WHERE database_name = current_database()
+                UNION ALL
+                SELECT coalesce(sql, index_name)
+                FROM duckdb_indexes() WHERE database_name = current_database()
+                UNION ALL
+                SELECT coalesce(sql, sequence_name)
+                FROM duckdb_sequences() WHERE database_name = current_database()
+            )
+        "#;
+
+        self.with_connection(|conn| {
+            Ok(conn.query_row(fingerprint_query, [], |row| row.get::<_, String>(0))?)
+        })
+        .await
+    }
+
+    async fn get_entity_changes(&self, since: Option<&str>) -> DbResult<EntityChanges> {
+        let fingerprint = self.schema_fingerprint().await?;
+        if let Some(changes) = SchemaTracker::unchanged(&fingerprint, since) {
+            return Ok(changes);
+        }
+
+        let entities = self.get_all_entities().await?;
+        Ok(self.schema_tracker.changes(fingerprint, since, entities))
+    }
+
+    async fn check_schema_changed(&self) -> DbResult<Option<String>> {
+        let fingerprint = self.schema_fingerprint().await?;
+        Ok(self.schema_tracker.check(fingerprint))
+    }
+
+    async fn get_entity_ddl(&self, id: &str) -> DbResult<String> {
+        let id = id.to_string();
+        self.with_connection(move |conn| entity_ddl(conn, &id))
+            .await
+    }
+}
+
+fn server_info(conn: &Connection) -> DbResult<ServerInfo> {
+    let (version, database): (String, String) =
+        conn.query_row("SELECT version(), current_database()", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+
+    Ok(ServerInfo {
+        version,
+        user: None,
+        database: Some(database),
+        tls: false,
+        tls_version: None,
+        tls_cipher: None,
+    })
+}
+
+fn run_query(conn: &Connection, sql: &str, result_index: usize) -> DbResult<QueryResult> {
+    let mut result = QueryResult::new(sql, result_index);
+    result.command = leading_keyword(sql);
+
+    let started = Instant::now();
+    let mut statement = conn.prepare(sql)?;
+    {
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let column_count = row.as_ref().column_count();
+            let mut values = Vec::with_capacity(column_count);
+            for i in 0..column_count {
+                values.push(decode::to_cell(row.get::<_, Value>(i)?));
+            }
+            result.rows.push(values);
+        }
+    }
+    result.execution_time_ms = started.elapsed().as_millis() as u64;
+    result.columns = column_definitions(&statement);
+
+    // DML statements return their row count as a single `Count` row
+    let is_dml = matches!(
+        result.command.as_deref(),
+        Some("INSERT" | "UPDATE" | "DELETE")
+    );
+    if is_dml && result.columns.len() == 1 && result.columns[0].name == "Count" {
+        if let Some(CellValue::Int(count)) = result.rows.first().and_then(|row| row.first()) {
+            result.rows_affected = Some(*count as u64);
+        }
+        result.columns.clear();
+        result.rows.clear();
+    } else {
+        result.rows_affected = Some(result.rows.len() as u64);
+    }
+
+    Ok(result)
+}
+
+fn column_definitions(statement: &Statement) -> Vec<ColumnDefinition> {
+    statement
+        .column_names()
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| result_column(name, statement.column_type(i).to_string()))
+        .collect()
+}
+
+fn all_entities(
+    conn: &Connection,
+    file_tables: &HashSet<String>,
+) -> DbResult<HashMap<String, DbEntity>> {
+    let mut entities = HashMap::new();
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+
+    // Relations and sequences, views of data files are shown as tables
+    let relation_query = r#"
+        SELECT 'table' AS kind, schema_name, table_name AS name, internal
+        FROM duckdb_tables() WHERE database_name = current_database()
+        UNION ALL
+        SELECT 'view', schema_name, view_name, internal
+        FROM duckdb_views() WHERE database_name = current_database()
+        UNION ALL
+        SELECT 'sequence', schema_name, sequence_name, false
+        FROM duckdb_sequences() WHERE database_name = current_database()
+        ORDER BY name
+    "#;
+
+    let mut statement = conn.prepare(relation_query)?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let kind: String = row.get(0)?;
+        let schema: String = row.get(1)?;
+        let name: String = row.get(2)?;
+        let is_system: bool = row.get(3)?;
+        let is_file = kind == "view" && schema == "main" && file_tables.contains(&name);
+
+        let id = match kind.as_str() {
+            "sequence" => format!("sequence:{}", relation_id(&schema, &name)),
+            _ => relation_id(&schema, &name),
+        };
+        let schema_level = SchemaLevelEntity {
+            id: id.clone(),
+            name,
+            is_system,
+            schema_id: schema.clone(),
+            extension_name: None,
+        };
+        let entity = match kind.as_str() {
+            "table" => DbEntity::Table(schema_level),
+            "view" if is_file => DbEntity::Table(schema_level),
+            "view" => DbEntity::View(schema_level),
+            _ => DbEntity::Sequence(schema_level),
+        };
+
+        children.entry(schema).or_default().push(id.clone());
+        entities.insert(id, entity);
+    }
+
+    let index_query = r#"
+        SELECT schema_name, index_name, table_name
+        FROM duckdb_indexes() WHERE database_name = current_database()
+        ORDER BY index_name
+    "#;
+
+    let mut statement = conn.prepare(index_query)?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let schema: String = row.get(0)?;
+        let name: String = row.get(1)?;
+        let table: String = row.get(2)?;
+        let id = format!("index:{}", relation_id(&schema, &name));
+
+        entities.insert(
+            id.clone(),
+            DbEntity::Index(TableLevelEntity {
+                id,
+                name,
+                is_system: false,
+                table_id: relation_id(&schema, &table),
+            }),
+        );
+    }
+
+    let schema_query = r#"
+        SELECT schema_name, internal
+        FROM duckdb_schemas() WHERE database_name = current_database()
+    "#;
+
+    let mut statement = conn.prepare(schema_query)?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let is_system: bool = row.get(1)?;
+
+        entities.insert(
+            name.clone(),
+            DbEntity::Schema(SchemaEntity {
+                id: name.clone(),
+                children: children.remove(&name).unwrap_or_default(),
+                name,
+                is_system,
+                extension_name: None,
+            }),
+        );
+    }
+
+    Ok(entities)
+}
+
+/// Returns the `CREATE` statement DuckDB stored for an entity
+fn entity_ddl(conn: &Connection, id: &str) -> DbResult<String> {
+    let (kind, relation) = match id.split_once(':') {
+        Some((kind @ ("index" | "sequence"), relation)) => (kind, relation),
+        _ => ("relation", id),
+    };
+    let (schema, name) = relation
+        .split_once('.')
+        .ok_or_else(|| DbError::NotFound(format!("Invalid entity ID: {}", id)))?;
+
+    let ddl_query = r#"
+        SELECT sql
+        FROM (
+            SELECT 'relation' AS kind, schema_name, table_name AS name, sql
+            FROM duckdb_tables() WHERE database_name = current_database()
+            UNION ALL
+            SELECT 'relation', schema_name, view_name, sql
+            FROM duckdb_views() WHERE database_name = current_database()
+            UNION ALL
+            SELECT 'index', schema_name, index_name, sql
+            FROM duckdb_indexes() WHERE database_name = current_database()
+            UNION ALL
+            SELECT 'sequence', schema_name, sequence_name, sql
+            FROM duckdb_sequences() WHERE database_name = current_database()
+        )
+        WHERE kind = ? AND schema_name = ? AND name = ? AND sql IS NOT NULL
+    "#;
+
+    conn.query_row(ddl_query, params![kind, schema, name], |row| {
+        row.get::<_, String>(0)
+    })
+    .map_err(|e| match e {
+        duckdb::Error::QueryReturnedNoRows => DbError::NotFound(format!("Entity {} not found", id)),
+        e => e.into(),
+    })
+}
+
+/// Relation IDs are the schema and object name, like SQLite's
+fn relation_id(schema: &str, name: &str) -> String {
+    format!("{}.{}", schema, name)
+}
+
+fn is_data_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| FILE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// The table function that reads a data file, with the format picked from its extension
+fn scan_function(path: &Path) -> String {
+    let literal = format!("'{}'", path.to_string_lossy().replace('\'', "''"));
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("parquet") => format!("read_parquet({})", literal),
+        Some("tsv") => format!("read_csv_auto({}, delim = '\\t')", literal),
+        _ => format!("read_csv_auto({})", literal),
+    }
+}
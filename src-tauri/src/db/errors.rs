@@ -1,11 +1,15 @@
-use sqlx::Error as SqlxError;
+use sqlx::{error::DatabaseError, mysql::MySqlDatabaseError, Error as SqlxError};
 use std::fmt;
 
+use crate::db::types::ConnectionErrorKind;
+
 /// Database error types
 #[derive(Debug)]
 pub enum DbError {
     /// Error connecting to the database
     Connection(String),
+    /// Error reaching or logging in to the server, classified so the cause can be shown
+    ConnectionFailed(ConnectionErrorKind, String),
     /// Error executing a query
     Query(String),
     /// Error parsing or preparing a query
@@ -20,6 +24,8 @@ pub enum DbError {
     Unsupported(String),
     /// Transaction error
     // Transaction(String),
+    /// Query was cancelled by the user
+    Cancelled,
     /// SQL parsing error
     Parsing(String),
     /// Other error
@@ -29,7 +35,9 @@ pub enum DbError {
 impl fmt::Display for DbError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DbError::Connection(msg) => write!(f, "Database connection error: {}", msg),
+            DbError::Connection(msg) | DbError::ConnectionFailed(_, msg) => {
+                write!(f, "Database connection error: {}", msg)
+            }
             DbError::Query(msg) => write!(f, "Database query error: {}", msg),
             // DbError::Parse(msg) => write!(f, "SQL parse error: {}", msg),
             DbError::Config(msg) => write!(f, "Database configuration error: {}", msg),
@@ -37,6 +45,7 @@ impl fmt::Display for DbError {
             // DbError::Auth(msg) => write!(f, "Authentication error: {}", msg),
             DbError::Unsupported(msg) => write!(f, "Operation not supported: {}", msg),
             // DbError::Transaction(msg) => write!(f, "Transaction error: {}", msg),
+            DbError::Cancelled => write!(f, "Query was cancelled by the user"),
             DbError::Parsing(msg) => write!(f, "SQL parsing error: {}", msg),
             DbError::Other(msg) => write!(f, "Database error: {}", msg),
         }
@@ -49,6 +58,9 @@ impl From<SqlxError> for DbError {
     fn from(error: SqlxError) -> Self {
         match error {
             SqlxError::Database(e) => DbError::Query(e.to_string()),
+            SqlxError::Io(e) => DbError::Connection(e.to_string()),
+            SqlxError::Tls(e) => DbError::Connection(e.to_string()),
+            SqlxError::Configuration(e) => DbError::Config(e.to_string()),
             SqlxError::RowNotFound => DbError::NotFound("Row not found".to_string()),
             SqlxError::PoolTimedOut => DbError::Connection("Connection pool timeout".to_string()),
             SqlxError::PoolClosed => DbError::Connection("Connection pool closed".to_string()),
@@ -58,6 +70,93 @@ impl From<SqlxError> for DbError {
     }
 }
 
+/// Converts an error from opening a connection, classifying why it failed.
+/// Only errors from connecting are classified, a query failing with e.g. a
+/// permission error isn't a failed login.
+pub fn connection_error(error: SqlxError) -> DbError {
+    match error {
+        SqlxError::Database(e) => match database_error_kind(e.as_ref()) {
+            Some(kind) => DbError::ConnectionFailed(kind, e.to_string()),
+            None => DbError::Query(e.to_string()),
+        },
+        SqlxError::Io(e) => DbError::ConnectionFailed(io_error_kind(&e), e.to_string()),
+        SqlxError::Tls(e) => DbError::ConnectionFailed(ConnectionErrorKind::Tls, e.to_string()),
+        SqlxError::PoolTimedOut => DbError::ConnectionFailed(
+            ConnectionErrorKind::Timeout,
+            "Connection pool timeout".to_string(),
+        ),
+        error => error.into(),
+    }
+}
+
+#[cfg(feature = "duckdb")]
+impl From<duckdb::Error> for DbError {
+    fn from(error: duckdb::Error) -> Self {
+        match error {
+            duckdb::Error::QueryReturnedNoRows => DbError::NotFound("Row not found".to_string()),
+            _ => DbError::Query(error.to_string()),
+        }
+    }
+}
+
+/// Classifies the errors a server returns when logging in
+fn database_error_kind(error: &dyn DatabaseError) -> Option<ConnectionErrorKind> {
+    // MySQL uses the same SQLSTATE for many errors, so its error numbers are checked
+    match error.try_downcast_ref::<MySqlDatabaseError>() {
+        Some(error) => mysql_error_kind(error.number()),
+        None => error.code().as_deref().and_then(sqlstate_error_kind),
+    }
+}
+
+fn mysql_error_kind(number: u16) -> Option<ConnectionErrorKind> {
+    match number {
+        // ER_DBACCESS_DENIED_ERROR, ER_ACCESS_DENIED_ERROR, ER_ACCESS_DENIED_NO_PASSWORD_ERROR
+        1044 | 1045 | 1698 => Some(ConnectionErrorKind::AuthFailed),
+        // ER_BAD_DB_ERROR
+        1049 => Some(ConnectionErrorKind::DatabaseMissing),
+        _ => None,
+    }
+}
+
+fn sqlstate_error_kind(code: &str) -> Option<ConnectionErrorKind> {
+    match code {
+        // invalid_authorization_specification, invalid_password
+        "28000" | "28P01" => Some(ConnectionErrorKind::AuthFailed),
+        // invalid_catalog_name, or SQLITE_CANTOPEN for a missing database file
+        "3D000" | "14" => Some(ConnectionErrorKind::DatabaseMissing),
+        _ => None,
+    }
+}
+
+/// Classifies an error from reaching the server by its kind, OS messages are
+/// translated so they can't be matched on
+fn io_error_kind(error: &std::io::Error) -> ConnectionErrorKind {
+    use std::io::ErrorKind;
+
+    match error.kind() {
+        // A missing Unix socket means nothing is listening either
+        ErrorKind::ConnectionRefused | ErrorKind::NotFound => ConnectionErrorKind::Refused,
+        ErrorKind::TimedOut => ConnectionErrorKind::Timeout,
+        // rustls reports a failed handshake or certificate check as invalid data
+        ErrorKind::InvalidData => ConnectionErrorKind::Tls,
+        _ if is_failed_lookup(error) => ConnectionErrorKind::Dns,
+        _ => ConnectionErrorKind::Other,
+    }
+}
+
+/// Failed lookups have no kind of their own. Windows reports them with their
+/// own error codes, elsewhere the standard library (not the OS) prefixes the
+/// resolver's message with the same English text.
+fn is_failed_lookup(error: &std::io::Error) -> bool {
+    // WSAHOST_NOT_FOUND, WSATRY_AGAIN, WSANO_DATA
+    if cfg!(windows) && matches!(error.raw_os_error(), Some(11001 | 11002 | 11004)) {
+        return true;
+    }
+    error
+        .to_string()
+        .starts_with("failed to lookup address information")
+}
+
 impl From<url::ParseError> for DbError {
     fn from(error: url::ParseError) -> Self {
         DbError::Config(format!("Invalid connection URL: {}", error))
@@ -72,3 +171,88 @@ impl From<std::io::Error> for DbError {
 
 /// Result type for database operations
 pub type DbResult<T> = Result<T, DbError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error as IoError, ErrorKind};
+    use std::net::ToSocketAddrs;
+
+    #[test]
+    fn classifies_login_errors() {
+        for (number, kind) in [
+            (1044, Some(ConnectionErrorKind::AuthFailed)),
+            (1045, Some(ConnectionErrorKind::AuthFailed)),
+            (1698, Some(ConnectionErrorKind::AuthFailed)),
+            (1049, Some(ConnectionErrorKind::DatabaseMissing)),
+            (1146, None),
+        ] {
+            assert_eq!(mysql_error_kind(number), kind, "{}", number);
+        }
+
+        for (code, kind) in [
+            ("28000", Some(ConnectionErrorKind::AuthFailed)),
+            ("28P01", Some(ConnectionErrorKind::AuthFailed)),
+            ("3D000", Some(ConnectionErrorKind::DatabaseMissing)),
+            ("14", Some(ConnectionErrorKind::DatabaseMissing)),
+            ("42501", None),
+            ("1", None),
+        ] {
+            assert_eq!(sqlstate_error_kind(code), kind, "{}", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn classifies_a_missing_sqlite_file() {
+        use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions};
+
+        let error = SqliteConnectOptions::new()
+            .filename("/nonexistent/sqratch.db")
+            .connect()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            connection_error(error),
+            DbError::ConnectionFailed(ConnectionErrorKind::DatabaseMissing, _)
+        ));
+    }
+
+    #[test]
+    fn classifies_io_errors_by_kind() {
+        for (error, kind) in [
+            (
+                IoError::from(ErrorKind::ConnectionRefused),
+                ConnectionErrorKind::Refused,
+            ),
+            (
+                IoError::from(ErrorKind::NotFound),
+                ConnectionErrorKind::Refused,
+            ),
+            (
+                IoError::from(ErrorKind::TimedOut),
+                ConnectionErrorKind::Timeout,
+            ),
+            (
+                IoError::new(ErrorKind::InvalidData, "invalid peer certificate"),
+                ConnectionErrorKind::Tls,
+            ),
+            (
+                IoError::other("failed to lookup address information: Nome sconosciuto"),
+                ConnectionErrorKind::Dns,
+            ),
+            // Localized OS messages don't decide the kind
+            (
+                IoError::other("Verbindungsaufbau abgelehnt"),
+                ConnectionErrorKind::Other,
+            ),
+        ] {
+            assert_eq!(io_error_kind(&error), kind, "{}", error);
+        }
+    }
+
+    #[test]
+    fn classifies_failed_lookups() {
+        let error = "sqratch.invalid:5432".to_socket_addrs().unwrap_err();
+        assert_eq!(io_error_kind(&error), ConnectionErrorKind::Dns, "{}", error);
+    }
+}
@@ -0,0 +1,50 @@
+use sqlx::Column;
+
+use crate::db::types::ColumnDefinition;
+
+/// Most rows returned when following a reference
+pub const FOLLOW_REFERENCE_LIMIT: u32 = 1000;
+
+/// Full scans of a table reading at least this many rows are flagged in query plans
+pub const LARGE_SCAN_ROWS: f64 = 10_000.0;
+
+/// Quotes an identifier (e.g. a table or savepoint name) with double quotes,
+/// escaping embedded ones
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quotes an identifier with backticks like MySQL does, escaping embedded ones
+pub fn quote_backtick_ident(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Formats binary data as hex the way Postgres outputs `bytea`, e.g. `\x0a1b`
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!(
+        "\\x{}",
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    )
+}
+
+/// Definition of a result column, of which only the name and type are known
+pub fn result_column(name: String, data_type: String) -> ColumnDefinition {
+    ColumnDefinition {
+        name,
+        data_type,
+        nullable: true,      // Default to true since we can't easily determine
+        primary_key: false,  // Cannot determine from result alone
+        default_value: None, // Cannot determine from result alone
+    }
+}
+
+/// Definitions of the columns in a query result
+pub fn column_definitions<C: Column>(columns: &[C]) -> Vec<ColumnDefinition> {
+    columns
+        .iter()
+        .map(|col| result_column(col.name().to_string(), col.type_info().to_string()))
+        .collect()
+}
@@ -1,5 +1,17 @@
 // Define modules in the database module - only visible within this module
 pub mod client;
+pub mod dialect;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
 pub mod errors;
+pub mod helpers;
+pub mod mysql;
+pub mod pool;
 pub mod postgres;
+pub mod schema;
+pub mod script;
+pub mod session;
+pub mod sqlite;
+pub mod tls;
+pub mod tunnel;
 pub mod types;
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use futures_util::TryStreamExt;
+use sqlx::{
+    mysql::{MySqlConnection, MySqlRow},
+    pool::PoolConnection,
+    Executor, MySql, Row, Statement,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::db::{
+    client::BatchSink,
+    errors::DbResult,
+    helpers::column_definitions,
+    session::QueryConnection,
+    types::{ColumnDefinition, RowBatch},
+};
+
+use super::decode;
+
+/// A query whose rows are sent to a sink a page at a time.
+///
+/// MySQL only has cursors inside stored programs, so the result is streamed
+/// instead: a task reads it on a connection of its own and hands over rows as
+/// pages are fetched, which keeps the server from sending more than is read.
+pub(super) struct Cursor {
+    query_id: String,
+    rows: Rows,
+    columns: Vec<ColumnDefinition>,
+    sink: BatchSink,
+    page_size: u64,
+    max_rows: u64,
+    rows_fetched: u64,
+}
+
+enum Rows {
+    /// Read by a task that owns the query's connection
+    Streamed {
+        receiver: mpsc::Receiver<DbResult<MySqlRow>>,
+        task: JoinHandle<()>,
+    },
+    /// Read up front, for queries in the session's open transaction whose
+    /// connection can't be held on to between pages
+    Buffered(VecDeque<MySqlRow>),
+}
+
+impl Cursor {
+    /// Starts reading the result of `sql`. At most `max_rows` rows are read.
+    pub(super) async fn open(
+        conn: QueryConnection<'_, MySql>,
+        query_id: &str,
+        sql: &str,
+        page_size: u64,
+        max_rows: u64,
+        sink: BatchSink,
+    ) -> DbResult<Self> {
+        let page_size = page_size.max(1);
+
+        let (columns, rows) = match conn {
+            QueryConnection::Session(mut session) => {
+                let conn = session
+                    .as_mut()
+                    .expect("session connection is only locked while a transaction is open");
+                let columns = describe(conn, sql).await;
+
+                let mut rows = VecDeque::new();
+                let mut stream = conn.fetch(sql);
+                while (rows.len() as u64) < max_rows {
+                    match stream.try_next().await? {
+                        Some(row) => rows.push_back(row),
+                        None => break,
+                    }
+                }
+                (columns, Rows::Buffered(rows))
+            }
+            QueryConnection::Pooled(mut conn) => {
+                let columns = describe(&mut conn, sql).await;
+
+                // Holds about a page, the connection waits for the rest to be read
+                let (sender, receiver) = mpsc::channel(page_size.min(1024) as usize);
+                let task = tokio::spawn(stream_rows(conn, sql.to_string(), max_rows, sender));
+                (columns, Rows::Streamed { receiver, task })
+            }
+        };
+
+        Ok(Self {
+            query_id: query_id.to_string(),
+            rows,
+            columns,
+            sink,
+            page_size,
+            max_rows,
+            rows_fetched: 0,
+        })
+    }
+
+    /// Sends the next page of rows to the sink.
+    /// Returns the query's columns and whether there are no more rows.
+    pub(super) async fn fetch_page(&mut self) -> DbResult<(Vec<ColumnDefinition>, bool)> {
+        let count = self
+            .page_size
+            .min(self.max_rows.saturating_sub(self.rows_fetched));
+
+        let started = Instant::now();
+        let mut rows = Vec::new();
+        while (rows.len() as u64) < count {
+            let row = match &mut self.rows {
+                Rows::Streamed { receiver, .. } => receiver.recv().await.transpose()?,
+                Rows::Buffered(buffered) => buffered.pop_front(),
+            };
+            let Some(row) = row else {
+                break;
+            };
+
+            // Statements that can't be prepared only have columns once they return rows
+            if self.columns.is_empty() {
+                self.columns = column_definitions(row.columns());
+            }
+
+            let mut values = Vec::with_capacity(row.len());
+            for i in 0..row.len() {
+                values.push(decode::to_cell(row.try_get_raw(i)?));
+            }
+            rows.push(values);
+        }
+        let execution_time_ms = started.elapsed().as_millis() as u64;
+
+        self.rows_fetched += rows.len() as u64;
+        let capped = self.rows_fetched >= self.max_rows;
+        let done = capped || (rows.len() as u64) < count;
+
+        (self.sink)(RowBatch {
+            query_id: self.query_id.clone(),
+            rows,
+            rows_fetched: self.rows_fetched,
+            execution_time_ms,
+            done,
+            capped,
+        })?;
+
+        Ok((self.columns.clone(), done))
+    }
+
+    /// Stops reading the result and waits for its connection to be released
+    pub(super) async fn close(&mut self) -> DbResult<()> {
+        match &mut self.rows {
+            Rows::Streamed { receiver, task } => {
+                receiver.close();
+                if let Err(e) = task.await {
+                    log::warn!("Streaming query {} failed: {}", self.query_id, e);
+                }
+            }
+            Rows::Buffered(buffered) => buffered.clear(),
+        }
+        Ok(())
+    }
+}
+
+/// The columns of a query's result, if it can be prepared
+async fn describe(conn: &mut MySqlConnection, sql: &str) -> Vec<ColumnDefinition> {
+    match conn.prepare(sql).await {
+        Ok(statement) => column_definitions(statement.columns()),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Reads the result of `sql` into `sender` until it's read in full, `max_rows`
+/// rows were sent or the cursor is closed
+async fn stream_rows(
+    mut conn: PoolConnection<MySql>,
+    sql: String,
+    max_rows: u64,
+    sender: mpsc::Sender<DbResult<MySqlRow>>,
+) {
+    let finished = {
+        let mut stream = (&mut *conn).fetch(sql.as_str());
+        let mut sent = 0;
+        loop {
+            if sent >= max_rows {
+                break false;
+            }
+
+            let row = tokio::select! {
+                _ = sender.closed() => break false,
+                row = stream.try_next() => row,
+            };
+            match row {
+                Ok(Some(row)) => {
+                    if sender.send(Ok(row)).await.is_err() {
+                        break false;
+                    }
+                    sent += 1;
+                }
+                Ok(None) => break true,
+                Err(e) => {
+                    let _ = sender.send(Err(e.into())).await;
+                    break true;
+                }
+            }
+        }
+    };
+
+    // The rest of an unread result would have to be read before the connection
+    // can be used again, closing it is quicker
+    if !finished {
+        conn.close_on_drop();
+    }
+}
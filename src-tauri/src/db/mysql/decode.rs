@@ -0,0 +1,82 @@
+use serde_json::Value as JsonValue;
+use sqlx::{
+    mysql::{types::MySqlTime, MySql, MySqlValue, MySqlValueRef},
+    types::BigDecimal,
+    Decode, TypeInfo, Value, ValueRef,
+};
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+use crate::db::{helpers::to_hex, types::CellValue};
+
+/// Decodes a single MySQL value into a cell, picking a decoder based on its `MySqlTypeInfo`.
+/// Types we don't know how to decode fall back to their text representation.
+pub(crate) fn to_cell(v: MySqlValueRef) -> CellValue {
+    if v.is_null() {
+        return CellValue::Null;
+    }
+
+    let type_info = v.type_info();
+    let value = ValueRef::to_owned(&v);
+    decode_scalar(type_info.name(), &value).unwrap_or_else(|| to_text(&value))
+}
+
+fn decode_scalar(type_name: &str, value: &MySqlValue) -> Option<CellValue> {
+    match type_name {
+        "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM" | "SET" => {
+            decode::<String>(value).map(CellValue::Text)
+        }
+        "BOOLEAN" => decode::<bool>(value).map(CellValue::Bool),
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" => {
+            decode::<i64>(value).map(CellValue::Int)
+        }
+        "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED"
+        | "YEAR" => decode::<u32>(value).map(|v| CellValue::Int(v.into())),
+        // Anything past i64::MAX is kept exact as a numeric
+        "BIGINT UNSIGNED" | "BIT" => decode::<u64>(value).map(|v| match i64::try_from(v) {
+            Ok(v) => CellValue::Int(v),
+            Err(_) => CellValue::Numeric(v.to_string()),
+        }),
+        "FLOAT" => decode::<f32>(value).map(|v| CellValue::Float(v.into())),
+        "DOUBLE" => decode::<f64>(value).map(CellValue::Float),
+        "DECIMAL" => decode::<BigDecimal>(value).map(|v| CellValue::Numeric(v.to_string())),
+        "DATE" => decode::<Date>(value).map(|v| CellValue::Date(v.to_string())),
+        // TIME is a duration in MySQL, it can be negative or past 24 hours
+        "TIME" => decode::<MySqlTime>(value).map(|v| CellValue::Time(v.to_string())),
+        "DATETIME" => {
+            decode::<PrimitiveDateTime>(value).map(|v| CellValue::Timestamp(v.to_string()))
+        }
+        // TIMESTAMP values are converted to UTC by the session time zone
+        "TIMESTAMP" => {
+            decode::<OffsetDateTime>(value).map(|v| CellValue::TimestampTz(v.to_string()))
+        }
+        "JSON" => decode::<JsonValue>(value).map(CellValue::Json),
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "GEOMETRY" => {
+            decode::<Vec<u8>>(value).map(|v| CellValue::Bytes(to_hex(&v)))
+        }
+        "NULL" => Some(CellValue::Null),
+        _ => None,
+    }
+}
+
+/// Decodes a value without checking type compatibility, since the type name has
+/// already been matched
+fn decode<T>(value: &MySqlValue) -> Option<T>
+where
+    T: for<'r> Decode<'r, MySql>,
+{
+    value.try_decode_unchecked().ok()
+}
+
+/// Fallback for values that failed to decode (e.g. zero dates like `0000-00-00`).
+/// Values that are valid UTF-8 are returned as text, anything else as bytes.
+fn to_text(value: &MySqlValue) -> CellValue {
+    let Some(bytes) = decode::<Vec<u8>>(value) else {
+        return CellValue::Null;
+    };
+
+    match String::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(|c| c.is_control() && !c.is_whitespace()) => CellValue::Text(s),
+        Ok(s) => CellValue::Bytes(to_hex(s.as_bytes())),
+        Err(e) => CellValue::Bytes(to_hex(e.as_bytes())),
+    }
+}
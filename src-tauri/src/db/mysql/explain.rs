@@ -0,0 +1,316 @@
+use serde_json::Value as JsonValue;
+
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::LARGE_SCAN_ROWS,
+    types::{PlanNode, PlanWarning, PlanWarningKind, QueryPlan},
+};
+
+/// Operations a query block can wrap around its tables, and the node type they're shown as
+const OPERATIONS: [(&str, &str); 4] = [
+    ("ordering_operation", "Order"),
+    ("grouping_operation", "Group"),
+    ("duplicates_removal", "Distinct"),
+    ("windowing", "Window"),
+];
+
+/// Builds the `EXPLAIN` statement for a query
+pub(super) fn explain_sql(sql: &str) -> String {
+    format!("EXPLAIN FORMAT=JSON {}", sql.trim().trim_end_matches(';'))
+}
+
+/// Parses the output of `EXPLAIN FORMAT=JSON`, which is an object holding the
+/// outermost query block. MySQL and MariaDB nest blocks, operations and tables
+/// differently, so any key that isn't recognized is skipped.
+pub(super) fn parse_plan(sql: &str, output: &JsonValue) -> DbResult<QueryPlan> {
+    let block = output
+        .get("query_block")
+        .ok_or_else(|| DbError::Parsing("EXPLAIN output has no query block".to_string()))?;
+
+    Ok(QueryPlan {
+        query: sql.to_string(),
+        analyzed: false,
+        planning_time_ms: None,
+        execution_time_ms: None,
+        root: parse_block(block),
+    })
+}
+
+/// A `SELECT`, with the operations and tables that make it up as children
+fn parse_block(block: &JsonValue) -> PlanNode {
+    let node_type = match block.get("select_id") {
+        Some(id) => format!("Select #{}", id),
+        None => "Select".to_string(),
+    };
+
+    let mut node = plan_node(node_type);
+    node.total_cost = cost(block, "query_cost").unwrap_or_default();
+    node.children = parse_contents(block);
+    node
+}
+
+/// Nodes for the operations, tables, joins and unions inside a block or operation
+fn parse_contents(value: &JsonValue) -> Vec<PlanNode> {
+    let mut nodes = Vec::new();
+
+    for (key, node_type) in OPERATIONS {
+        if let Some(operation) = value.get(key) {
+            let mut node = plan_node(node_type.to_string());
+            node.children = parse_contents(operation);
+            nodes.push(node);
+        }
+    }
+
+    if let Some(table) = value.get("table") {
+        nodes.push(parse_table(table));
+    }
+
+    if let Some(tables) = value.get("nested_loop").and_then(JsonValue::as_array) {
+        let mut node = plan_node("Nested Loop".to_string());
+        node.children = tables
+            .iter()
+            .filter_map(|table| table.get("table"))
+            .map(parse_table)
+            .collect();
+        nodes.push(node);
+    }
+
+    if let Some(union) = value.get("union_result") {
+        let mut node = plan_node("Union".to_string());
+        node.relation_name = text(union, "table_name");
+        node.children = blocks(union, "query_specifications");
+        nodes.push(node);
+    }
+
+    nodes
+}
+
+fn parse_table(table: &JsonValue) -> PlanNode {
+    let access_type = text(table, "access_type").unwrap_or_default();
+    let node_type = match access_type.as_str() {
+        "ALL" => "Full Table Scan",
+        "index" => "Full Index Scan",
+        "range" => "Index Range Scan",
+        "ref" | "eq_ref" | "ref_or_null" | "fulltext" => "Index Lookup",
+        "const" | "system" => "Constant Lookup",
+        "index_merge" => "Index Merge",
+        _ => "Table",
+    };
+
+    // MariaDB reports the rows examined as `rows`
+    let examined = float(table, "rows_examined_per_scan")
+        .or_else(|| float(table, "rows"))
+        .unwrap_or_default();
+    let filtered = float(table, "filtered").unwrap_or(100.0);
+
+    let mut node = plan_node(node_type.to_string());
+    node.relation_name = text(table, "table_name");
+    node.index_name = text(table, "key");
+    node.condition = text(table, "attached_condition");
+    node.plan_rows = examined * filtered / 100.0;
+    // The prefix cost includes the tables joined before this one
+    node.total_cost = cost(table, "prefix_cost")
+        .or_else(|| float(table, "cost"))
+        .unwrap_or_default();
+
+    node.children = table
+        .get("materialized_from_subquery")
+        .and_then(|subquery| subquery.get("query_block"))
+        .map(parse_block)
+        .into_iter()
+        .chain(blocks(table, "attached_subqueries"))
+        .collect();
+
+    if access_type == "ALL" && examined >= LARGE_SCAN_ROWS {
+        node.warnings.push(PlanWarning {
+            kind: PlanWarningKind::SeqScanOnLargeTable,
+            message: format!(
+                "Full table scan on {} reads about {} rows, consider adding an index",
+                node.relation_name.as_deref().unwrap_or("a table"),
+                examined.round()
+            ),
+        });
+    }
+
+    node
+}
+
+/// The query blocks in an array of subqueries or union parts
+fn blocks(value: &JsonValue, key: &str) -> Vec<PlanNode> {
+    value
+        .get(key)
+        .and_then(JsonValue::as_array)
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|part| part.get("query_block"))
+                .map(parse_block)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn plan_node(node_type: String) -> PlanNode {
+    PlanNode {
+        node_type,
+        relation_name: None,
+        schema: None,
+        alias: None,
+        index_name: None,
+        join_type: None,
+        condition: None,
+        startup_cost: 0.0,
+        total_cost: 0.0,
+        plan_rows: 0.0,
+        actual_rows: None,
+        actual_loops: None,
+        actual_startup_time_ms: None,
+        actual_total_time_ms: None,
+        rows_removed_by_filter: None,
+        buffers: None,
+        warnings: Vec::new(),
+        children: Vec::new(),
+    }
+}
+
+fn text(node: &JsonValue, key: &str) -> Option<String> {
+    node.get(key)?.as_str().map(str::to_string)
+}
+
+/// MySQL writes most numbers as strings, e.g. `"filtered": "100.00"`
+fn float(node: &JsonValue, key: &str) -> Option<f64> {
+    match node.get(key)? {
+        JsonValue::String(value) => value.parse().ok(),
+        value => value.as_f64(),
+    }
+}
+
+fn cost(node: &JsonValue, key: &str) -> Option<f64> {
+    float(node.get("cost_info")?, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_joins_with_costs_and_rows() {
+        let output = json!({
+            "query_block": {
+                "select_id": 1,
+                "cost_info": {"query_cost": "12.50"},
+                "nested_loop": [
+                    {"table": {
+                        "table_name": "a",
+                        "access_type": "ALL",
+                        "rows_examined_per_scan": 20,
+                        "filtered": "50.00",
+                        "attached_condition": "(`db`.`a`.`x` > 1)",
+                        "cost_info": {"prefix_cost": "2.25"}
+                    }},
+                    {"table": {
+                        "table_name": "b",
+                        "access_type": "eq_ref",
+                        "key": "PRIMARY",
+                        "rows_examined_per_scan": 1,
+                        "filtered": "100.00",
+                        "cost_info": {"prefix_cost": "12.50"}
+                    }}
+                ]
+            }
+        });
+
+        let plan = parse_plan("SELECT", &output).unwrap();
+        assert!(!plan.analyzed);
+        assert_eq!(plan.root.node_type, "Select #1");
+        assert_eq!(plan.root.total_cost, 12.5);
+
+        let join = &plan.root.children[0];
+        assert_eq!(join.node_type, "Nested Loop");
+        assert_eq!(join.children[0].node_type, "Full Table Scan");
+        assert_eq!(join.children[0].plan_rows, 10.0);
+        assert_eq!(join.children[0].total_cost, 2.25);
+        assert_eq!(
+            join.children[0].condition.as_deref(),
+            Some("(`db`.`a`.`x` > 1)")
+        );
+        assert_eq!(join.children[1].node_type, "Index Lookup");
+        assert_eq!(join.children[1].index_name.as_deref(), Some("PRIMARY"));
+    }
+
+    #[test]
+    fn nests_operations_unions_and_subqueries() {
+        let output = json!({
+            "query_block": {
+                "union_result": {
+                    "table_name": "<union1,2>",
+                    "query_specifications": [
+                        {"query_block": {"select_id": 1, "ordering_operation": {
+                            "using_filesort": true,
+                            "table": {"table_name": "a", "access_type": "index"}
+                        }}},
+                        {"query_block": {"select_id": 2, "table": {
+                            "table_name": "d",
+                            "access_type": "ALL",
+                            "materialized_from_subquery": {
+                                "query_block": {"select_id": 3, "table": {"table_name": "c"}}
+                            }
+                        }}}
+                    ]
+                }
+            }
+        });
+
+        let plan = parse_plan("SELECT", &output).unwrap();
+        let union = &plan.root.children[0];
+        assert_eq!(union.node_type, "Union");
+        assert_eq!(union.children[0].children[0].node_type, "Order");
+        assert_eq!(
+            union.children[0].children[0].children[0].node_type,
+            "Full Index Scan"
+        );
+
+        let derived = &union.children[1].children[0];
+        assert_eq!(derived.relation_name.as_deref(), Some("d"));
+        assert_eq!(derived.children[0].node_type, "Select #3");
+    }
+
+    #[test]
+    fn reads_mariadb_rows_and_costs() {
+        let output = json!({"query_block": {"select_id": 1, "table": {
+            "table_name": "t",
+            "access_type": "ALL",
+            "rows": 50000,
+            "filtered": 100,
+            "cost": 8.5
+        }}});
+
+        let table = &parse_plan("SELECT", &output).unwrap().root.children[0];
+        assert_eq!(table.plan_rows, 50000.0);
+        assert_eq!(table.total_cost, 8.5);
+        assert_eq!(table.warnings[0].kind, PlanWarningKind::SeqScanOnLargeTable);
+    }
+
+    #[test]
+    fn only_flags_large_full_table_scans() {
+        let small = json!({"query_block": {"table": {
+            "access_type": "ALL",
+            "rows_examined_per_scan": 100
+        }}});
+        let plan = parse_plan("SELECT", &small).unwrap();
+        assert!(plan.root.children[0].warnings.is_empty());
+
+        let indexed = json!({"query_block": {"table": {
+            "access_type": "range",
+            "rows_examined_per_scan": 100000
+        }}});
+        let plan = parse_plan("SELECT", &indexed).unwrap();
+        assert!(plan.root.children[0].warnings.is_empty());
+    }
+
+    #[test]
+    fn rejects_output_without_a_query_block() {
+        assert!(parse_plan("SELECT", &json!({})).is_err());
+    }
+}
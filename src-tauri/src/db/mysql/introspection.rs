@@ -0,0 +1,538 @@
+use sqlx::{
+    mysql::{MySqlConnection, MySqlRow},
+    MySql, Pool, Row,
+};
+
+use super::{relation_id, run_query_with};
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::{quote_backtick_ident as quote_ident, FOLLOW_REFERENCE_LIMIT},
+    types::{
+        ConstraintKind, ForeignKey, QueryResult, RelationColumn, RelationConstraint,
+        RelationDetails,
+    },
+};
+
+/// What an entity ID refers to, see `relation_id` and `object_id`
+enum EntityRef<'a> {
+    Schema(&'a str),
+    Relation(&'a str, &'a str),
+    Object(&'a str, &'a str, &'a str),
+}
+
+fn parse_id(id: &str) -> DbResult<EntityRef<'_>> {
+    let invalid = || DbError::NotFound(format!("Invalid entity ID: {}", id));
+
+    match id.split_once(':') {
+        Some((kind, rest)) => {
+            let (schema, name) = rest.split_once('.').ok_or_else(invalid)?;
+            Ok(EntityRef::Object(kind, schema, name))
+        }
+        None => match id.split_once('.') {
+            Some((schema, name)) => Ok(EntityRef::Relation(schema, name)),
+            None => Ok(EntityRef::Schema(id)),
+        },
+    }
+}
+
+/// Parses the ID of a table or view
+fn parse_relation_id(id: &str) -> DbResult<(&str, &str)> {
+    match parse_id(id)? {
+        EntityRef::Relation(schema, name) => Ok((schema, name)),
+        _ => Err(DbError::NotFound(format!("{} is not a relation", id))),
+    }
+}
+
+/// Reconstructs an entity's DDL with the server's `SHOW CREATE` statements.
+/// Indexes have no `SHOW CREATE` so theirs is built from `information_schema`.
+pub(super) async fn entity_ddl(pool: &Pool<MySql>, id: &str) -> DbResult<String> {
+    let not_found = || DbError::NotFound(format!("Entity {} not found", id));
+
+    // Each SHOW CREATE returns the statement in a different column
+    let (sql, column) = match parse_id(id)? {
+        EntityRef::Schema(schema) => (format!("SHOW CREATE DATABASE {}", quote_ident(schema)), 1),
+        EntityRef::Relation(schema, name) => {
+            let kind_query = r#"
+                SELECT CAST(TABLE_TYPE AS CHAR)
+                FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?
+            "#;
+
+            let kind: String = sqlx::query_scalar(kind_query)
+                .bind(schema)
+                .bind(name)
+                .fetch_optional(pool)
+                .await?
+                .ok_or_else(not_found)?;
+
+            let statement = match kind.as_str() {
+                "VIEW" | "SYSTEM VIEW" => "VIEW",
+                "SEQUENCE" => "SEQUENCE",
+                _ => "TABLE",
+            };
+            let target = format!("{}.{}", quote_ident(schema), quote_ident(name));
+            (format!("SHOW CREATE {} {}", statement, target), 1)
+        }
+        EntityRef::Object("index", schema, name) => {
+            let (table, index) = name.split_once('.').ok_or_else(not_found)?;
+            return index_ddl(pool, schema, table, index)
+                .await?
+                .ok_or_else(not_found);
+        }
+        EntityRef::Object(kind, schema, name) => {
+            let statement = match kind {
+                "procedure" => "PROCEDURE",
+                "function" => "FUNCTION",
+                "trigger" => "TRIGGER",
+                _ => return Err(not_found()),
+            };
+            let target = format!("{}.{}", quote_ident(schema), quote_ident(name));
+            (format!("SHOW CREATE {} {}", statement, target), 2)
+        }
+    };
+
+    // The statement is missing if the user can't see a routine's body
+    let row = sqlx::query(&sql).fetch_one(pool).await?;
+    let ddl: Option<String> = row.try_get_unchecked(column)?;
+    ddl.map(|ddl| format!("{};", ddl)).ok_or_else(not_found)
+}
+
+async fn index_ddl(
+    pool: &Pool<MySql>,
+    schema: &str,
+    table: &str,
+    index: &str,
+) -> DbResult<Option<String>> {
+    let index_query = r#"
+        SELECT
+            CAST(COLUMN_NAME AS CHAR) AS column_name,
+            CAST(SUB_PART AS SIGNED) AS sub_part,
+            CAST(NON_UNIQUE AS SIGNED) AS non_unique,
+            CAST(INDEX_TYPE AS CHAR) AS index_type
+        FROM information_schema.STATISTICS
+        WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? AND INDEX_NAME = ?
+        ORDER BY SEQ_IN_INDEX
+    "#;
+
+    let rows = sqlx::query(index_query)
+        .bind(schema)
+        .bind(table)
+        .bind(index)
+        .fetch_all(pool)
+        .await?;
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+
+    // Functional key parts (MySQL 8) have no column, MariaDB doesn't list their
+    // expression so they're left out
+    let parts: Vec<String> = rows
+        .iter()
+        .filter_map(|row| {
+            let column: String = row.get::<Option<String>, _>("column_name")?;
+            Some(match row.get::<Option<i64>, _>("sub_part") {
+                Some(length) => format!("{}({})", quote_ident(&column), length),
+                None => quote_ident(&column),
+            })
+        })
+        .collect();
+
+    let target = format!("{}.{}", quote_ident(schema), quote_ident(table));
+    let index_type: String = first.get("index_type");
+    let ddl = if index == "PRIMARY" {
+        format!(
+            "ALTER TABLE {} ADD PRIMARY KEY ({});",
+            target,
+            parts.join(", ")
+        )
+    } else {
+        let kind = match index_type.as_str() {
+            "FULLTEXT" => "FULLTEXT ",
+            "SPATIAL" => "SPATIAL ",
+            _ if first.get::<i64, _>("non_unique") == 0 => "UNIQUE ",
+            _ => "",
+        };
+        format!(
+            "CREATE {}INDEX {} ON {} ({});",
+            kind,
+            quote_ident(index),
+            target,
+            parts.join(", ")
+        )
+    };
+
+    Ok(Some(ddl))
+}
+
+/// Describes a table or view's columns, constraints and foreign keys from `information_schema`
+pub(super) async fn describe_relation(pool: &Pool<MySql>, id: &str) -> DbResult<RelationDetails> {
+    let (schema, name) = parse_relation_id(id)?;
+
+    let relation_query = r#"
+        SELECT
+            CAST(TABLE_TYPE AS CHAR) AS kind,
+            CAST(TABLE_COMMENT AS CHAR) AS comment
+        FROM information_schema.TABLES
+        WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?
+    "#;
+
+    let relation = sqlx::query(relation_query)
+        .bind(schema)
+        .bind(name)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("Relation {} not found", id)))?;
+
+    let kind: String = relation.get("kind");
+    let comment: Option<String> = relation.get("comment");
+
+    Ok(RelationDetails {
+        id: id.to_string(),
+        name: name.to_string(),
+        schema: schema.to_string(),
+        kind: relation_kind(&kind).to_string(),
+        // Views report `VIEW` as their comment
+        comment: comment.filter(|c| !c.is_empty() && kind != "VIEW"),
+        columns: fetch_columns(pool, schema, name).await?,
+        constraints: fetch_constraints(pool, schema, name).await?,
+        foreign_keys: fetch_foreign_keys(pool, Some((schema, name))).await?,
+    })
+}
+
+async fn fetch_columns(
+    pool: &Pool<MySql>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<RelationColumn>> {
+    let column_query = r#"
+        SELECT
+            CAST(ORDINAL_POSITION AS SIGNED) AS position,
+            CAST(COLUMN_NAME AS CHAR) AS name,
+            CAST(COLUMN_TYPE AS CHAR) AS data_type,
+            CAST(DATA_TYPE AS CHAR) AS base_type,
+            CAST(IS_NULLABLE = 'YES' AS SIGNED) AS nullable,
+            CAST(COLUMN_KEY = 'PRI' AS SIGNED) AS primary_key,
+            CAST(COLUMN_DEFAULT AS CHAR) AS default_value,
+            CAST(EXTRA AS CHAR) AS extra,
+            CAST(GENERATION_EXPRESSION AS CHAR) AS generation_expression,
+            CAST(COLUMN_COMMENT AS CHAR) AS comment
+        FROM information_schema.COLUMNS
+        WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?
+        ORDER BY ORDINAL_POSITION
+    "#;
+
+    let rows = sqlx::query(column_query)
+        .bind(schema)
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+
+    let columns = rows
+        .into_iter()
+        .map(|row| {
+            let data_type: String = row.get("data_type");
+            let base_type: String = row.get("base_type");
+            let extra: String = row.get::<Option<String>, _>("extra").unwrap_or_default();
+            let comment: Option<String> = row.get("comment");
+
+            // Generated columns have an expression, other columns an empty string
+            let generated = row
+                .get::<Option<String>, _>("generation_expression")
+                .filter(|expression| !expression.is_empty());
+            let default_value = match generated {
+                Some(_) => None,
+                None => row.get("default_value"),
+            };
+
+            RelationColumn {
+                position: row.get::<i64, _>("position") as i32,
+                name: row.get("name"),
+                element_type: None,
+                domain_base_type: None,
+                enum_values: (base_type == "enum").then(|| enum_values(&data_type)),
+                data_type,
+                nullable: row.get::<i64, _>("nullable") == 1,
+                primary_key: row.get::<i64, _>("primary_key") == 1,
+                default_value,
+                generated,
+                // AUTO_INCREMENT accepts explicit values, like a `BY DEFAULT` identity
+                identity: extra
+                    .contains("auto_increment")
+                    .then(|| "BY DEFAULT".to_string()),
+                comment: comment.filter(|c| !c.is_empty()),
+            }
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+async fn fetch_constraints(
+    pool: &Pool<MySql>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<RelationConstraint>> {
+    // Key columns are only listed for primary keys and unique constraints,
+    // check constraints only have their clause
+    let constraint_query = r#"
+        SELECT
+            CAST(tc.CONSTRAINT_NAME AS CHAR) AS name,
+            CAST(tc.CONSTRAINT_TYPE AS CHAR) AS kind,
+            CAST(GROUP_CONCAT(k.COLUMN_NAME ORDER BY k.ORDINAL_POSITION SEPARATOR '\n') AS CHAR)
+                AS columns,
+            CAST(MAX(cc.CHECK_CLAUSE) AS CHAR) AS check_clause
+        FROM information_schema.TABLE_CONSTRAINTS tc
+        LEFT JOIN information_schema.KEY_COLUMN_USAGE k
+            ON k.CONSTRAINT_SCHEMA = tc.CONSTRAINT_SCHEMA
+            AND k.CONSTRAINT_NAME = tc.CONSTRAINT_NAME
+            AND k.TABLE_NAME = tc.TABLE_NAME
+        LEFT JOIN information_schema.CHECK_CONSTRAINTS cc
+            ON cc.CONSTRAINT_SCHEMA = tc.CONSTRAINT_SCHEMA
+            AND cc.CONSTRAINT_NAME = tc.CONSTRAINT_NAME
+        WHERE tc.TABLE_SCHEMA = ?
+          AND tc.TABLE_NAME = ?
+          AND tc.CONSTRAINT_TYPE IN ('PRIMARY KEY', 'UNIQUE', 'CHECK')
+        GROUP BY tc.CONSTRAINT_NAME, tc.CONSTRAINT_TYPE
+        ORDER BY tc.CONSTRAINT_TYPE = 'PRIMARY KEY' DESC, tc.CONSTRAINT_NAME
+    "#;
+
+    let rows = sqlx::query(constraint_query)
+        .bind(schema)
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+
+    let mut constraints = Vec::with_capacity(rows.len());
+    for row in rows {
+        let kind: String = row.get("kind");
+        let columns = split_list(&row, "columns");
+        let quoted = columns
+            .iter()
+            .map(|c| quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let (kind, definition) = match kind.as_str() {
+            "PRIMARY KEY" => (
+                ConstraintKind::PrimaryKey,
+                format!("PRIMARY KEY ({})", quoted),
+            ),
+            "UNIQUE" => (ConstraintKind::Unique, format!("UNIQUE ({})", quoted)),
+            "CHECK" => {
+                let clause: Option<String> = row.get("check_clause");
+                (
+                    ConstraintKind::Check,
+                    format!("CHECK ({})", clause.unwrap_or_default()),
+                )
+            }
+            _ => continue,
+        };
+
+        constraints.push(RelationConstraint {
+            name: row.get("name"),
+            kind,
+            columns,
+            definition,
+        });
+    }
+
+    Ok(constraints)
+}
+
+/// Returns every foreign key on the server, which together form the graph of
+/// references between tables. Table IDs match the IDs of `DbEntity::Table`.
+pub(super) async fn foreign_key_graph(pool: &Pool<MySql>) -> DbResult<Vec<ForeignKey>> {
+    fetch_foreign_keys(pool, None).await
+}
+
+/// Fetches the foreign keys of one table, or of all tables if `table` is `None`
+async fn fetch_foreign_keys(
+    pool: &Pool<MySql>,
+    table: Option<(&str, &str)>,
+) -> DbResult<Vec<ForeignKey>> {
+    let foreign_key_query = r#"
+        SELECT
+            CAST(rc.CONSTRAINT_SCHEMA AS CHAR) AS schema_name,
+            CAST(rc.CONSTRAINT_NAME AS CHAR) AS name,
+            CAST(rc.TABLE_NAME AS CHAR) AS table_name,
+            CAST(GROUP_CONCAT(k.COLUMN_NAME ORDER BY k.ORDINAL_POSITION SEPARATOR '\n') AS CHAR)
+                AS columns,
+            CAST(rc.UNIQUE_CONSTRAINT_SCHEMA AS CHAR) AS referenced_schema,
+            CAST(rc.REFERENCED_TABLE_NAME AS CHAR) AS referenced_table,
+            CAST(GROUP_CONCAT(k.REFERENCED_COLUMN_NAME ORDER BY k.ORDINAL_POSITION SEPARATOR '\n')
+                AS CHAR) AS referenced_columns,
+            CAST(rc.UPDATE_RULE AS CHAR) AS on_update,
+            CAST(rc.DELETE_RULE AS CHAR) AS on_delete
+        FROM information_schema.REFERENTIAL_CONSTRAINTS rc
+        JOIN information_schema.KEY_COLUMN_USAGE k
+            ON k.CONSTRAINT_SCHEMA = rc.CONSTRAINT_SCHEMA
+            AND k.CONSTRAINT_NAME = rc.CONSTRAINT_NAME
+            AND k.TABLE_NAME = rc.TABLE_NAME
+        WHERE ? IS NULL OR (rc.CONSTRAINT_SCHEMA = ? AND rc.TABLE_NAME = ?)
+        GROUP BY rc.CONSTRAINT_SCHEMA, rc.CONSTRAINT_NAME, rc.TABLE_NAME,
+            rc.UNIQUE_CONSTRAINT_SCHEMA, rc.REFERENCED_TABLE_NAME, rc.UPDATE_RULE, rc.DELETE_RULE
+        ORDER BY rc.CONSTRAINT_NAME
+    "#;
+
+    let (schema, name) = table.unzip();
+    let rows = sqlx::query(foreign_key_query)
+        .bind(schema)
+        .bind(schema)
+        .bind(name)
+        .fetch_all(pool)
+        .await?;
+
+    let foreign_keys = rows
+        .into_iter()
+        .map(|row| {
+            let schema: String = row.get("schema_name");
+            let name: String = row.get("name");
+            let table_name: String = row.get("table_name");
+            let referenced_schema: String = row.get("referenced_schema");
+            let referenced_table: String = row.get("referenced_table");
+            let on_update: String = row.get("on_update");
+            let on_delete: String = row.get("on_delete");
+            let columns = split_list(&row, "columns");
+            let referenced_columns = split_list(&row, "referenced_columns");
+
+            let quote_all = |names: &[String]| {
+                names
+                    .iter()
+                    .map(|c| quote_ident(c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let definition = format!(
+                "FOREIGN KEY ({}) REFERENCES {}.{} ({}) ON UPDATE {} ON DELETE {}",
+                quote_all(&columns),
+                quote_ident(&referenced_schema),
+                quote_ident(&referenced_table),
+                quote_all(&referenced_columns),
+                on_update,
+                on_delete
+            );
+
+            // Constraint names are unique per table, or per database for InnoDB
+            ForeignKey {
+                id: format!("foreign_key:{}.{}.{}", schema, table_name, name),
+                name,
+                table_id: relation_id(&schema, &table_name),
+                columns,
+                referenced_table_id: relation_id(&referenced_schema, &referenced_table),
+                referenced_schema,
+                referenced_table,
+                referenced_columns,
+                on_update,
+                on_delete,
+                // MySQL checks foreign keys immediately
+                deferrable: false,
+                initially_deferred: false,
+                definition,
+            }
+        })
+        .collect();
+
+    Ok(foreign_keys)
+}
+
+/// Fetches the rows referenced by `value` in a foreign key `column` of a table.
+///
+/// If the column is part of a composite foreign key only that column is matched,
+/// so this can return more than one row.
+pub(super) async fn follow_reference(
+    conn: &mut MySqlConnection,
+    table_id: &str,
+    column: &str,
+    value: &str,
+) -> DbResult<QueryResult> {
+    let (schema, table) = parse_relation_id(table_id)?;
+
+    // Single column keys are preferred when a column is part of several
+    let reference_query = r#"
+        SELECT
+            CAST(k.REFERENCED_TABLE_SCHEMA AS CHAR) AS referenced_schema,
+            CAST(k.REFERENCED_TABLE_NAME AS CHAR) AS referenced_table,
+            CAST(k.REFERENCED_COLUMN_NAME AS CHAR) AS referenced_column
+        FROM information_schema.KEY_COLUMN_USAGE k
+        WHERE k.TABLE_SCHEMA = ?
+          AND k.TABLE_NAME = ?
+          AND k.COLUMN_NAME = ?
+          AND k.REFERENCED_TABLE_NAME IS NOT NULL
+        ORDER BY (
+            SELECT COUNT(*)
+            FROM information_schema.KEY_COLUMN_USAGE other
+            WHERE other.CONSTRAINT_SCHEMA = k.CONSTRAINT_SCHEMA
+              AND other.CONSTRAINT_NAME = k.CONSTRAINT_NAME
+              AND other.TABLE_NAME = k.TABLE_NAME
+        ), k.CONSTRAINT_NAME
+        LIMIT 1
+    "#;
+
+    let reference = sqlx::query(reference_query)
+        .bind(schema)
+        .bind(table)
+        .bind(column)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| {
+            DbError::NotFound(format!("Column {} doesn't reference another table", column))
+        })?;
+
+    // MySQL converts the text value to the column's type when comparing
+    let sql = format!(
+        "SELECT * FROM {}.{} WHERE {} = ? LIMIT {}",
+        quote_ident(reference.get("referenced_schema")),
+        quote_ident(reference.get("referenced_table")),
+        quote_ident(reference.get("referenced_column")),
+        FOLLOW_REFERENCE_LIMIT
+    );
+
+    run_query_with(conn, sqlx::query(&sql).bind(value), 0).await
+}
+
+/// Splits a `GROUP_CONCAT` of names, which are separated by newlines
+fn split_list(row: &MySqlRow, column: &str) -> Vec<String> {
+    row.get::<Option<String>, _>(column)
+        .map(|list| list.split('\n').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Parses the labels out of an enum column type, e.g. `enum('a','b')`
+fn enum_values(column_type: &str) -> Vec<String> {
+    let Some(labels) = column_type
+        .strip_prefix("enum(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    else {
+        return Vec::new();
+    };
+
+    // Labels are quoted with embedded quotes doubled, and may contain commas
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut in_label = false;
+    let mut chars = labels.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_label) {
+            ('\'', true) if chars.peek() == Some(&'\'') => {
+                chars.next();
+                current.push('\'');
+            }
+            ('\'', true) => {
+                values.push(std::mem::take(&mut current));
+                in_label = false;
+            }
+            ('\'', false) => in_label = true,
+            (c, true) => current.push(c),
+            _ => {}
+        }
+    }
+    values
+}
+
+fn relation_kind(table_type: &str) -> &'static str {
+    match table_type {
+        "VIEW" | "SYSTEM VIEW" => "view",
+        "SEQUENCE" => "sequence",
+        _ => "table",
+    }
+}
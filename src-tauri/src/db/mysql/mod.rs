@@ -0,0 +1,1075 @@
+mod cursor;
+mod decode;
+mod explain;
+mod introspection;
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use sqlx::{
+    mysql::{MySqlConnectOptions, MySqlConnection, MySqlSslMode},
+    Connection, Either, Execute, Executor, MySql, Pool, Row as SqlxRow, Statement,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
+
+use self::cursor::Cursor;
+use crate::db::{
+    client::{BatchSink, DatabaseClient},
+    errors::{connection_error, DbError, DbResult},
+    helpers::{column_definitions, quote_backtick_ident as quote_ident},
+    pool::PoolSettings,
+    schema::SchemaTracker,
+    script::{leading_keyword, split_statements},
+    session::{transaction_change, QueryConnection, Session, TransactionChange},
+    tls::{SslMode, TlsSettings},
+    types::{
+        BindValue, ColumnDefinition, DbCapabilities, DbEntity, Dialect, EntityChanges,
+        ExplainFormat, ForeignKey, ParameterDefinition, QueryPlan, QueryResult, RelationDetails,
+        SchemaEntity, SchemaLevelEntity, ScriptErrorMode, ServerInfo, StatementDescription,
+        TableLevelEntity, TransactionState,
+    },
+};
+
+/// Databases that ship with the server, listed as system schemas
+const SYSTEM_SCHEMAS: [&str; 4] = ["mysql", "information_schema", "performance_schema", "sys"];
+
+/// MySQL has no row versions to hash, so this combines a count and checksum of the
+/// catalog rows that make up each kind of entity. Any DDL touching an entity changes
+/// one of its rows (e.g. a column's type or a routine's `LAST_ALTERED`).
+const FINGERPRINT_QUERY: &str = r#"
+    SELECT CAST(CONCAT_WS('|',
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            SCHEMA_NAME, DEFAULT_CHARACTER_SET_NAME, DEFAULT_COLLATION_NAME))), 0))
+            FROM information_schema.SCHEMATA),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            TABLE_SCHEMA, TABLE_NAME, TABLE_TYPE, ENGINE, CREATE_TIME, TABLE_COMMENT))), 0))
+            FROM information_schema.TABLES),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            TABLE_SCHEMA, TABLE_NAME, COLUMN_NAME, ORDINAL_POSITION, COLUMN_TYPE,
+            IS_NULLABLE, COLUMN_DEFAULT, EXTRA, COLUMN_COMMENT))), 0))
+            FROM information_schema.COLUMNS),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            TABLE_SCHEMA, TABLE_NAME, INDEX_NAME, SEQ_IN_INDEX, COLUMN_NAME, NON_UNIQUE))), 0))
+            FROM information_schema.STATISTICS),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            CONSTRAINT_SCHEMA, CONSTRAINT_NAME, TABLE_NAME, REFERENCED_TABLE_NAME,
+            UPDATE_RULE, DELETE_RULE))), 0))
+            FROM information_schema.REFERENTIAL_CONSTRAINTS),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            ROUTINE_SCHEMA, ROUTINE_NAME, ROUTINE_TYPE, CREATED, LAST_ALTERED))), 0))
+            FROM information_schema.ROUTINES),
+        (SELECT CONCAT(COUNT(*), ':', COALESCE(SUM(CRC32(CONCAT_WS('.',
+            TRIGGER_SCHEMA, TRIGGER_NAME, EVENT_OBJECT_TABLE, CREATED))), 0))
+            FROM information_schema.TRIGGERS)
+    ) AS CHAR)
+"#;
+
+pub struct MySqlClient {
+    connection_string: String,
+    pool: Option<Pool<MySql>>,
+    /// Only the pool sizes and timeouts apply, the session settings are Postgres only
+    pool_settings: PoolSettings,
+    tls: Option<TlsSettings>,
+    /// Queries currently running, keyed by their query ID
+    running_queries: Mutex<HashMap<String, RunningQuery>>,
+    /// Open cursors, keyed by the query ID they were opened with
+    cursors: Mutex<HashMap<String, Arc<AsyncMutex<Cursor>>>>,
+    /// Connection pinned while an explicit transaction is open
+    session: Session<MySql>,
+    schema_tracker: SchemaTracker,
+}
+
+struct RunningQuery {
+    /// ID of the connection the query is running on
+    connection_id: u64,
+    /// Whether the user asked to cancel this query
+    cancelled: bool,
+}
+
+impl MySqlClient {
+    pub fn new(
+        connection_string: &str,
+        pool_settings: PoolSettings,
+        tls: Option<TlsSettings>,
+    ) -> DbResult<Self> {
+        Ok(Self {
+            connection_string: connection_string.to_string(),
+            pool: None,
+            pool_settings,
+            tls,
+            running_queries: Mutex::new(HashMap::new()),
+            cursors: Mutex::new(HashMap::new()),
+            session: Session::new(),
+            schema_tracker: SchemaTracker::default(),
+        })
+    }
+
+    /// Options for connecting to the server, with the project's TLS settings
+    /// applied over the connection string's
+    fn connect_options(&self) -> DbResult<MySqlConnectOptions> {
+        let mut options = MySqlConnectOptions::from_str(&self.connection_string)?;
+
+        let Some(tls) = &self.tls else {
+            return Ok(options);
+        };
+        if let Some(ssl_mode) = tls.ssl_mode {
+            // MySQL has no mode that only uses TLS when the server requires it
+            options = options.ssl_mode(match ssl_mode {
+                SslMode::Disable => MySqlSslMode::Disabled,
+                SslMode::Allow | SslMode::Prefer => MySqlSslMode::Preferred,
+                SslMode::Require => MySqlSslMode::Required,
+                SslMode::VerifyCa => MySqlSslMode::VerifyCa,
+                SslMode::VerifyFull => MySqlSslMode::VerifyIdentity,
+            });
+        }
+        if let Some(path) = &tls.root_cert_path {
+            options = options.ssl_ca(path);
+        }
+        if let Some(path) = &tls.client_cert_path {
+            options = options.ssl_client_cert(path);
+        }
+        if let Some(path) = &tls.client_key_path {
+            options = options.ssl_client_key(path);
+        }
+        Ok(options)
+    }
+
+    // This function gets the pool or returns an error if not connected
+    fn get_pool(&self) -> DbResult<&Pool<MySql>> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| DbError::Connection("Database client is not connected".to_string()))
+    }
+
+    /// Registers the connection a query is about to run on, so it can be cancelled
+    async fn start_query(&self, query_id: &str, conn: &mut MySqlConnection) -> DbResult<()> {
+        let connection_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+            .fetch_one(&mut *conn)
+            .await?;
+
+        self.running_queries.lock().unwrap().insert(
+            query_id.to_string(),
+            RunningQuery {
+                connection_id,
+                cancelled: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn is_cancelled(&self, query_id: &str) -> bool {
+        self.running_queries
+            .lock()
+            .unwrap()
+            .get(query_id)
+            .is_some_and(|query| query.cancelled)
+    }
+
+    fn finish_query(&self, query_id: &str) {
+        self.running_queries.lock().unwrap().remove(query_id);
+    }
+
+    /// Closes every open cursor so their connections go back to the pool
+    async fn close_cursors(&self) {
+        let cursors: Vec<_> = self.cursors.lock().unwrap().drain().collect();
+        for (query_id, cursor) in cursors {
+            self.finish_query(&query_id);
+            if let Err(e) = cursor.lock().await.close().await {
+                log::warn!("Failed to close cursor for query {}: {}", query_id, e);
+            }
+        }
+    }
+
+    /// Gets the session connection if a transaction is open, otherwise one from the pool
+    async fn connection(&self) -> DbResult<QueryConnection<'_, MySql>> {
+        self.session.connection(self.get_pool()?).await
+    }
+
+    /// Reports errors caused by the user cancelling a query as `DbError::Cancelled`
+    fn cancelled_or(&self, query_id: &str, error: DbError) -> DbError {
+        if self.is_cancelled(query_id) {
+            DbError::Cancelled
+        } else {
+            error
+        }
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for MySqlClient {
+    fn get_connection_string(&self) -> String {
+        self.connection_string.clone()
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::MySql
+    }
+
+    fn capabilities(&self) -> DbCapabilities {
+        DbCapabilities {
+            transactions: true,
+            savepoints: true,
+            cancellation: true,
+            query_plans: true,
+            // EXPLAIN ANALYZE only writes a text tree, which isn't parsed
+            analyzed_plans: false,
+            explain_formats: vec![ExplainFormat::Json],
+            ..self.dialect().capabilities()
+        }
+    }
+
+    async fn is_connected(&self) -> DbResult<bool> {
+        match self.get_pool() {
+            Ok(pool) => Ok(!pool.is_closed()),
+            Err(_) => Ok(false),
+        }
+    }
+
+    async fn test_connection(&self) -> DbResult<()> {
+        let pool = self.get_pool()?;
+        sqlx::query("SELECT 1").execute(pool).await?;
+        Ok(())
+    }
+
+    async fn probe_server(&self) -> DbResult<ServerInfo> {
+        let mut conn = MySqlConnection::connect_with(&self.connect_options()?)
+            .await
+            .map_err(connection_error)?;
+        let info = fetch_server_info(&mut conn).await;
+        let _ = conn.close().await;
+        info
+    }
+
+    async fn server_info(&self) -> DbResult<ServerInfo> {
+        let mut conn = self.get_pool()?.acquire().await?;
+        fetch_server_info(&mut conn).await
+    }
+
+    async fn connect(&mut self) -> DbResult<()> {
+        // Check if already connected
+        if let Ok(true) = self.is_connected().await {
+            return Ok(());
+        }
+
+        // Create a new pool
+        let pool = self
+            .pool_settings
+            .pool_options::<MySql>()
+            .connect_with(self.connect_options()?)
+            .await
+            .map_err(connection_error)?;
+
+        self.pool = Some(pool);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> DbResult<()> {
+        // The pool waits for every connection to be returned before closing,
+        // including the ones held by open cursors
+        self.close_cursors().await;
+        self.session.release();
+
+        if let Ok(true) = self.is_connected().await {
+            if let Some(pool) = self.pool.take() {
+                pool.close().await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connect().await
+    }
+
+    async fn reconnect_with_string(&mut self, connection_string: &str) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connection_string = connection_string.to_string();
+        self.connect().await
+    }
+
+    async fn execute_query(&self, query_id: &str, sql: &str) -> DbResult<QueryResult> {
+        single_statement(sql)?;
+        let mut conn = self.connection().await?;
+
+        self.start_query(query_id, &mut conn).await?;
+        let result = run_query(&mut conn, sql, 0)
+            .await
+            .map_err(|e| self.cancelled_or(query_id, e));
+        self.finish_query(query_id);
+
+        let open = match (&result, mysql_transaction_change(sql)) {
+            (Ok(_), Some(change)) => change == TransactionChange::Begin,
+            _ => conn.is_session(),
+        };
+        self.session.finish(conn, open).await;
+        result
+    }
+
+    async fn execute_query_with_params(
+        &self,
+        query_id: &str,
+        sql: &str,
+        params: &[BindValue],
+    ) -> DbResult<QueryResult> {
+        let mut conn = self.connection().await?;
+
+        self.start_query(query_id, &mut conn).await?;
+        let result = run_query_with_params(&mut conn, sql, params)
+            .await
+            .map_err(|e| self.cancelled_or(query_id, e));
+        self.finish_query(query_id);
+
+        let open = match (&result, mysql_transaction_change(sql)) {
+            (Ok(_), Some(change)) => change == TransactionChange::Begin,
+            _ => conn.is_session(),
+        };
+        self.session.finish(conn, open).await;
+        result
+    }
+
+    async fn describe_statement(&self, sql: &str) -> DbResult<StatementDescription> {
+        let mut conn = self.connection().await?;
+        let statement = (&mut *conn).prepare(sql).await?;
+
+        // MySQL only reports how many parameters a statement has, not their types
+        let parameters = match statement.parameters() {
+            Some(Either::Right(count)) => (1..=count)
+                .map(|index| ParameterDefinition {
+                    index,
+                    data_type: "unknown".to_string(),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(StatementDescription {
+            parameters,
+            columns: column_definitions(statement.columns()),
+        })
+    }
+
+    async fn explain_query(&self, sql: &str, analyze: bool, _buffers: bool) -> DbResult<QueryPlan> {
+        // MySQL only analyzes plans as a text tree, which isn't parsed. Buffer usage
+        // isn't reported at all, so that's ignored.
+        if analyze {
+            return Err(DbError::Unsupported(
+                "this database doesn't support analyzing query plans".to_string(),
+            ));
+        }
+
+        let mut conn = self.connection().await?;
+        let row = (&mut *conn)
+            .fetch_one(explain::explain_sql(sql).as_str())
+            .await?;
+        let output: String = row.try_get(0)?;
+        let output = serde_json::from_str(&output)
+            .map_err(|e| DbError::Parsing(format!("Invalid EXPLAIN output: {}", e)))?;
+
+        explain::parse_plan(sql, &output)
+    }
+
+    async fn execute_script(
+        &self,
+        query_id: &str,
+        sql: &str,
+        on_error: ScriptErrorMode,
+    ) -> DbResult<Vec<QueryResult>> {
+        // Run every statement on the same connection so session state carries over
+        let mut conn = self.connection().await?;
+        self.start_query(query_id, &mut conn).await?;
+        let mut open = conn.is_session();
+        let mut results = Vec::new();
+
+        for (i, statement) in split_statements(sql).into_iter().enumerate() {
+            // A cancel between statements finds the connection idle, so nothing
+            // interrupts the next statement unless it's checked for here
+            if self.is_cancelled(query_id) {
+                let mut result = QueryResult::new(statement, i);
+                result.error = Some(DbError::Cancelled.to_string());
+                results.push(result);
+                break;
+            }
+
+            let started = Instant::now();
+            match run_query(&mut conn, statement, i).await {
+                Ok(result) => {
+                    if let Some(change) = mysql_transaction_change(statement) {
+                        open = change == TransactionChange::Begin;
+                    }
+                    results.push(result);
+                }
+                Err(e) => {
+                    // A cancelled script never runs its remaining statements
+                    let cancelled = self.is_cancelled(query_id);
+                    let mut result = QueryResult::new(statement, i);
+                    result.execution_time_ms = started.elapsed().as_millis() as u64;
+                    result.error = Some(if cancelled {
+                        DbError::Cancelled.to_string()
+                    } else {
+                        e.to_string()
+                    });
+                    results.push(result);
+
+                    if cancelled || on_error == ScriptErrorMode::Stop {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.finish_query(query_id);
+        self.session.finish(conn, open).await;
+        Ok(results)
+    }
+
+    async fn cancel_query(&self, query_id: &str) -> DbResult<bool> {
+        let connection_id = match self.running_queries.lock().unwrap().get_mut(query_id) {
+            Some(query) => {
+                query.cancelled = true;
+                query.connection_id
+            }
+            None => return Ok(false),
+        };
+
+        // The query's own connection is busy and the pool may have no other one
+        // free, so the kill goes through a connection of its own. `KILL QUERY`
+        // stops the statement but keeps the query's connection open, and succeeds
+        // even when the connection is idle, so the process list tells whether a
+        // statement was running.
+        self.get_pool()?;
+        let mut conn = MySqlConnection::connect_with(&self.connect_options()?).await?;
+        let killed = kill_query(&mut conn, connection_id).await;
+        let _ = conn.close().await;
+        let cancelled = killed?;
+
+        // A streaming query may have finished on the server already, closing its
+        // cursor stops the rest of the rows from being read
+        let streaming = self.cursors.lock().unwrap().contains_key(query_id);
+        if streaming {
+            self.close_cursor(query_id).await?;
+            return Ok(true);
+        }
+        Ok(cancelled)
+    }
+
+    async fn open_cursor(
+        &self,
+        query_id: &str,
+        sql: &str,
+        page_size: u64,
+        max_rows: u64,
+        sink: BatchSink,
+    ) -> DbResult<Vec<ColumnDefinition>> {
+        single_statement(sql)?;
+        let mut conn = self.connection().await?;
+        self.start_query(query_id, &mut conn).await?;
+
+        let mut cursor = match Cursor::open(conn, query_id, sql, page_size, max_rows, sink).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                self.finish_query(query_id);
+                return Err(e);
+            }
+        };
+
+        match cursor.fetch_page().await {
+            Ok((columns, done)) => {
+                if done {
+                    self.finish_query(query_id);
+                    cursor.close().await?;
+                } else {
+                    self.cursors
+                        .lock()
+                        .unwrap()
+                        .insert(query_id.to_string(), Arc::new(AsyncMutex::new(cursor)));
+                }
+                Ok(columns)
+            }
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                self.finish_query(query_id);
+                let _ = cursor.close().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn fetch_cursor(&self, query_id: &str) -> DbResult<bool> {
+        let cursor = self
+            .cursors
+            .lock()
+            .unwrap()
+            .get(query_id)
+            .cloned()
+            .ok_or_else(|| DbError::NotFound(format!("No open cursor for query {}", query_id)))?;
+
+        let result = cursor.lock().await.fetch_page().await;
+        match result {
+            Ok((_, false)) => Ok(true),
+            Ok((_, true)) => {
+                self.close_cursor(query_id).await?;
+                Ok(false)
+            }
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                let _ = self.close_cursor(query_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn close_cursor(&self, query_id: &str) -> DbResult<()> {
+        let cursor = self.cursors.lock().unwrap().remove(query_id);
+        self.finish_query(query_id);
+
+        if let Some(cursor) = cursor {
+            cursor.lock().await.close().await?;
+        }
+        Ok(())
+    }
+
+    async fn begin_transaction(&self) -> DbResult<()> {
+        self.session.begin(self.get_pool()?, "BEGIN").await
+    }
+
+    async fn commit(&self) -> DbResult<()> {
+        self.session.end("COMMIT").await
+    }
+
+    async fn rollback(&self, savepoint: Option<&str>) -> DbResult<()> {
+        match savepoint {
+            Some(name) => {
+                let sql = format!("ROLLBACK TO SAVEPOINT {}", quote_ident(name));
+                self.session.execute(&sql).await
+            }
+            None => self.session.end("ROLLBACK").await,
+        }
+    }
+
+    async fn savepoint(&self, name: &str) -> DbResult<()> {
+        self.session
+            .execute(&format!("SAVEPOINT {}", quote_ident(name)))
+            .await
+    }
+
+    fn transaction_state(&self) -> TransactionState {
+        self.session.state()
+    }
+
+    async fn get_all_entities(&self) -> DbResult<HashMap<String, DbEntity>> {
+        let pool = self.get_pool()?;
+        let mut entities = HashMap::new();
+        let mut schema_children_map: HashMap<String, Vec<String>> = HashMap::new();
+
+        // Query 1: Get all databases, which are presented as schemas
+        let schema_query = r#"
+            SELECT SCHEMA_NAME AS schema_name
+            FROM information_schema.SCHEMATA
+            ORDER BY SCHEMA_NAME
+        "#;
+
+        let schema_rows = sqlx::query(schema_query).fetch_all(pool).await?;
+        for row in schema_rows {
+            let name: String = row.get("schema_name");
+
+            schema_children_map.insert(name.clone(), Vec::new());
+            entities.insert(
+                name.clone(),
+                DbEntity::Schema(SchemaEntity {
+                    id: name.clone(),
+                    is_system: is_system_schema(&name),
+                    name,
+                    extension_name: None,
+                    children: Vec::new(),
+                }),
+            );
+        }
+
+        // Query 2: Get tables, views and (on MariaDB) sequences
+        let table_query = r#"
+            SELECT
+                TABLE_SCHEMA AS schema_name,
+                TABLE_NAME AS name,
+                CAST(TABLE_TYPE AS CHAR) AS kind
+            FROM information_schema.TABLES
+            ORDER BY TABLE_SCHEMA, TABLE_NAME
+        "#;
+
+        let table_rows = sqlx::query(table_query).fetch_all(pool).await?;
+        for row in table_rows {
+            let schema_id: String = row.get("schema_name");
+            let name: String = row.get("name");
+            let kind: String = row.get("kind");
+            let id = relation_id(&schema_id, &name);
+
+            if let Some(children) = schema_children_map.get_mut(&schema_id) {
+                children.push(id.clone());
+            }
+
+            let schema_level = SchemaLevelEntity {
+                id: id.clone(),
+                name,
+                is_system: is_system_schema(&schema_id),
+                schema_id,
+                extension_name: None,
+            };
+
+            let entity = match kind.as_str() {
+                "BASE TABLE" | "SYSTEM VERSIONED" => DbEntity::Table(schema_level),
+                "VIEW" | "SYSTEM VIEW" => DbEntity::View(schema_level),
+                "SEQUENCE" => DbEntity::Sequence(schema_level),
+                _ => continue,
+            };
+
+            entities.insert(id, entity);
+        }
+
+        // Query 3: Get functions and procedures
+        let routine_query = r#"
+            SELECT
+                ROUTINE_SCHEMA AS schema_name,
+                ROUTINE_NAME AS name,
+                CAST(ROUTINE_TYPE AS CHAR) AS kind
+            FROM information_schema.ROUTINES
+            ORDER BY ROUTINE_SCHEMA, ROUTINE_NAME
+        "#;
+
+        let routine_rows = sqlx::query(routine_query).fetch_all(pool).await?;
+        for row in routine_rows {
+            let schema_id: String = row.get("schema_name");
+            let name: String = row.get("name");
+            let kind: String = row.get("kind");
+
+            // Functions and procedures have separate namespaces, so the kind is part of the ID
+            let is_procedure = kind == "PROCEDURE";
+            let id = object_id(
+                if is_procedure {
+                    "procedure"
+                } else {
+                    "function"
+                },
+                &schema_id,
+                &name,
+            );
+
+            if let Some(children) = schema_children_map.get_mut(&schema_id) {
+                children.push(id.clone());
+            }
+
+            let schema_level = SchemaLevelEntity {
+                id: id.clone(),
+                name,
+                is_system: is_system_schema(&schema_id),
+                schema_id,
+                extension_name: None,
+            };
+
+            let entity = if is_procedure {
+                DbEntity::Procedure(schema_level)
+            } else {
+                DbEntity::Function(schema_level)
+            };
+
+            entities.insert(id, entity);
+        }
+
+        // Query 4: Get indexes, one row per index rather than per indexed column
+        let index_query = r#"
+            SELECT DISTINCT
+                TABLE_SCHEMA AS schema_name,
+                TABLE_NAME AS table_name,
+                INDEX_NAME AS name
+            FROM information_schema.STATISTICS
+            ORDER BY INDEX_NAME
+        "#;
+
+        let index_rows = sqlx::query(index_query).fetch_all(pool).await?;
+        for row in index_rows {
+            let schema_name: String = row.get("schema_name");
+            let table_name: String = row.get("table_name");
+            let name: String = row.get("name");
+
+            // Index names are only unique per table
+            let id = object_id("index", &schema_name, &format!("{}.{}", table_name, name));
+
+            entities.insert(
+                id.clone(),
+                DbEntity::Index(TableLevelEntity {
+                    id,
+                    name,
+                    is_system: is_system_schema(&schema_name),
+                    table_id: relation_id(&schema_name, &table_name),
+                }),
+            );
+        }
+
+        // Query 5: Get triggers
+        let trigger_query = r#"
+            SELECT
+                TRIGGER_SCHEMA AS schema_name,
+                TRIGGER_NAME AS name,
+                EVENT_OBJECT_TABLE AS table_name
+            FROM information_schema.TRIGGERS
+            ORDER BY TRIGGER_NAME
+        "#;
+
+        let trigger_rows = sqlx::query(trigger_query).fetch_all(pool).await?;
+        for row in trigger_rows {
+            let schema_name: String = row.get("schema_name");
+            let name: String = row.get("name");
+            let table_name: String = row.get("table_name");
+            let id = object_id("trigger", &schema_name, &name);
+
+            entities.insert(
+                id.clone(),
+                DbEntity::Trigger(TableLevelEntity {
+                    id,
+                    name,
+                    is_system: is_system_schema(&schema_name),
+                    table_id: relation_id(&schema_name, &table_name),
+                }),
+            );
+        }
+
+        // Update schema entities with their children
+        for (schema_id, children) in schema_children_map {
+            if let Some(DbEntity::Schema(schema)) = entities.get_mut(&schema_id) {
+                schema.children = children;
+            }
+        }
+
+        Ok(entities)
+    }
+
+    async fn schema_fingerprint(&self) -> DbResult<String> {
+        let pool = self.get_pool()?;
+        Ok(sqlx::query_scalar(FINGERPRINT_QUERY)
+            .fetch_one(pool)
+            .await?)
+    }
+
+    async fn get_entity_changes(&self, since: Option<&str>) -> DbResult<EntityChanges> {
+        let fingerprint = self.schema_fingerprint().await?;
+        if let Some(changes) = SchemaTracker::unchanged(&fingerprint, since) {
+            return Ok(changes);
+        }
+
+        let entities = self.get_all_entities().await?;
+        Ok(self.schema_tracker.changes(fingerprint, since, entities))
+    }
+
+    async fn check_schema_changed(&self) -> DbResult<Option<String>> {
+        let fingerprint = self.schema_fingerprint().await?;
+        Ok(self.schema_tracker.check(fingerprint))
+    }
+
+    async fn get_entity_ddl(&self, id: &str) -> DbResult<String> {
+        introspection::entity_ddl(self.get_pool()?, id).await
+    }
+
+    async fn describe_relation(&self, id: &str) -> DbResult<RelationDetails> {
+        introspection::describe_relation(self.get_pool()?, id).await
+    }
+
+    async fn get_foreign_keys(&self) -> DbResult<Vec<ForeignKey>> {
+        introspection::foreign_key_graph(self.get_pool()?).await
+    }
+
+    async fn follow_reference(
+        &self,
+        table_id: &str,
+        column: &str,
+        value: &str,
+    ) -> DbResult<QueryResult> {
+        let mut conn = self.connection().await?;
+        introspection::follow_reference(&mut conn, table_id, column, value).await
+    }
+}
+
+/// Reports the server, login and TLS state of a connection
+async fn fetch_server_info(conn: &mut MySqlConnection) -> DbResult<ServerInfo> {
+    let info_query = r#"
+        SELECT
+            CAST(VERSION() AS CHAR) AS version,
+            CAST(CURRENT_USER() AS CHAR) AS user,
+            CAST(DATABASE() AS CHAR) AS `database`
+    "#;
+    let row = sqlx::query(info_query).fetch_one(&mut *conn).await?;
+
+    // Both are empty unless the connection is encrypted
+    let mut tls_status = HashMap::new();
+    for status in (&mut *conn)
+        .fetch_all("SHOW SESSION STATUS WHERE Variable_name IN ('Ssl_version', 'Ssl_cipher')")
+        .await?
+    {
+        let name: String = status.try_get(0)?;
+        let value: String = status.try_get(1)?;
+        if !value.is_empty() {
+            tls_status.insert(name, value);
+        }
+    }
+
+    Ok(ServerInfo {
+        version: row.get("version"),
+        user: row.get("user"),
+        database: row.get("database"),
+        tls: tls_status.contains_key("Ssl_cipher"),
+        tls_version: tls_status.remove("Ssl_version"),
+        tls_cipher: tls_status.remove("Ssl_cipher"),
+    })
+}
+
+/// Stops the statement running on a connection, returning whether there was one
+async fn kill_query(conn: &mut MySqlConnection, connection_id: u64) -> DbResult<bool> {
+    let running: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM information_schema.PROCESSLIST \
+         WHERE ID = ? AND COMMAND = 'Query'",
+    )
+    .bind(connection_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if running > 0 {
+        sqlx::query(&format!("KILL QUERY {}", connection_id))
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(running > 0)
+}
+
+/// The text protocol runs several statements sent at once, whose results can't
+/// be shown as one. Scripts run them one at a time instead.
+fn single_statement(sql: &str) -> DbResult<()> {
+    if split_statements(sql).len() > 1 {
+        return Err(DbError::Query(
+            "Only one statement can run as a query, run several as a script".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs raw SQL over the text protocol, which allows statements that can't be
+/// prepared
+async fn run_query(
+    conn: &mut MySqlConnection,
+    sql: &str,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    run_query_with(conn, sql, result_index).await
+}
+
+/// Binds `params` to the statement's `?` placeholders in order. MySQL converts
+/// each value to the type it's compared with or stored as.
+async fn run_query_with_params(
+    conn: &mut MySqlConnection,
+    sql: &str,
+    params: &[BindValue],
+) -> DbResult<QueryResult> {
+    let expected = match (&mut *conn).prepare(sql).await?.parameters() {
+        Some(Either::Right(count)) => count,
+        _ => 0,
+    };
+
+    if expected != params.len() {
+        return Err(DbError::Query(format!(
+            "Query expects {} parameter(s) but {} were given",
+            expected,
+            params.len()
+        )));
+    }
+
+    let mut query = sqlx::query(sql);
+    for value in params {
+        query = match value {
+            BindValue::Null => query.bind(None::<String>),
+            BindValue::Bool(v) => query.bind(*v),
+            BindValue::Int(v) => query.bind(*v),
+            BindValue::Float(v) => query.bind(*v),
+            BindValue::Text(v) => query.bind(v.clone()),
+            BindValue::Json(v) => query.bind(v.to_string()),
+        };
+    }
+
+    run_query_with(conn, query, 0).await
+}
+
+async fn run_query_with<'q>(
+    conn: &mut MySqlConnection,
+    query: impl Execute<'q, MySql> + 'q,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    let sql = query.sql().to_string();
+    let mut result = QueryResult::new(&sql, result_index);
+    result.command = leading_keyword(&sql);
+
+    // Only time spent waiting on the server counts, not decoding
+    let mut elapsed = Duration::ZERO;
+    {
+        let mut stream = (&mut *conn).fetch_many(query);
+        // Stored procedures can return several result sets, only the first is shown
+        let mut result_set_done = false;
+        loop {
+            let polled = Instant::now();
+            let item = stream.try_next().await?;
+            elapsed += polled.elapsed();
+
+            match item {
+                Some(Either::Left(done)) => {
+                    result.rows_affected =
+                        Some(result.rows_affected.unwrap_or(0) + done.rows_affected());
+                    result_set_done = true;
+                }
+                Some(Either::Right(_)) if result_set_done => {
+                    return Err(DbError::Query(
+                        "The statement returned several result sets, only one can be shown"
+                            .to_string(),
+                    ));
+                }
+                Some(Either::Right(row)) => {
+                    if result.columns.is_empty() {
+                        result.columns = column_definitions(row.columns());
+                    }
+
+                    let mut values = Vec::with_capacity(row.len());
+                    for i in 0..row.len() {
+                        values.push(decode::to_cell(row.try_get_raw(i)?));
+                    }
+                    result.rows.push(values);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Queries that return no rows should still report their columns. Not every
+    // statement can be prepared (e.g. several at once), those just have none.
+    if result.columns.is_empty() {
+        if let Ok(statement) = (&mut *conn).prepare(&sql).await {
+            result.columns = column_definitions(statement.columns());
+        }
+    }
+
+    result.execution_time_ms = elapsed.as_millis() as u64;
+    Ok(result)
+}
+
+/// How statements change the transaction state, the last change wins. Besides the
+/// usual transaction statements, MySQL commits the open transaction before DDL,
+/// user management and most administrative statements.
+fn mysql_transaction_change(sql: &str) -> Option<TransactionChange> {
+    split_statements(sql)
+        .into_iter()
+        .rev()
+        .find_map(|statement| {
+            transaction_change(statement)
+                .or_else(|| commits_implicitly(statement).then_some(TransactionChange::End))
+        })
+}
+
+/// Whether a statement implicitly commits the open transaction, see
+/// https://dev.mysql.com/doc/refman/8.0/en/implicit-commit.html
+fn commits_implicitly(sql: &str) -> bool {
+    let upper = sql.to_ascii_uppercase();
+    let has_word = |word: &str| upper.split_whitespace().any(|w| w == word);
+
+    match leading_keyword(sql).as_deref() {
+        // Temporary tables are the exception
+        Some("CREATE" | "DROP") => !has_word("TEMPORARY"),
+        Some(
+            "ALTER" | "RENAME" | "TRUNCATE" | "GRANT" | "REVOKE" | "LOCK" | "UNLOCK" | "FLUSH"
+            | "RESET" | "INSTALL" | "UNINSTALL" | "CACHE" | "CHANGE" | "STOP",
+        ) => true,
+        // START TRANSACTION is a transaction statement, START REPLICA commits
+        Some("START") => true,
+        Some("ANALYZE" | "CHECK" | "OPTIMIZE" | "REPAIR") => has_word("TABLE"),
+        Some("LOAD") => has_word("INDEX"),
+        Some("SET") => has_word("PASSWORD"),
+        _ => false,
+    }
+}
+
+fn is_system_schema(name: &str) -> bool {
+    SYSTEM_SCHEMAS.contains(&name.to_lowercase().as_str())
+}
+
+/// Entity ID of a table or view, which share a namespace within a database
+fn relation_id(schema: &str, name: &str) -> String {
+    format!("{}.{}", schema, name)
+}
+
+/// Entity ID of anything other than a schema or relation, prefixed with its kind
+fn object_id(kind: &str, schema: &str, name: &str) -> String {
+    format!("{}:{}.{}", kind, schema, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_the_transaction_on_implicit_commits() {
+        for sql in [
+            "CREATE TABLE t (id INT)",
+            "alter table t add column x int",
+            "DROP VIEW v",
+            "TRUNCATE t",
+            "START REPLICA",
+            "LOCK TABLES t WRITE",
+            "ANALYZE TABLE t",
+        ] {
+            assert_eq!(
+                mysql_transaction_change(sql),
+                Some(TransactionChange::End),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_the_transaction_for_other_statements() {
+        for sql in [
+            "CREATE TEMPORARY TABLE t (id INT)",
+            "DROP TEMPORARY TABLE t",
+            "INSERT INTO t VALUES (1)",
+            "SET @x = 1",
+            "SAVEPOINT a",
+        ] {
+            assert_eq!(mysql_transaction_change(sql), None, "{}", sql);
+        }
+        assert_eq!(
+            mysql_transaction_change("START TRANSACTION"),
+            Some(TransactionChange::Begin)
+        );
+    }
+
+    #[test]
+    fn checks_every_statement_for_transaction_changes() {
+        assert_eq!(
+            mysql_transaction_change("SELECT 1; START TRANSACTION"),
+            Some(TransactionChange::Begin)
+        );
+        assert_eq!(
+            mysql_transaction_change("START TRANSACTION; CREATE TABLE t (id INT);"),
+            Some(TransactionChange::End)
+        );
+        assert_eq!(
+            mysql_transaction_change("BEGIN; INSERT INTO t VALUES (';COMMIT')"),
+            Some(TransactionChange::Begin)
+        );
+    }
+
+    #[test]
+    fn rejects_several_statements_as_a_query() {
+        assert!(single_statement("SELECT 1;").is_ok());
+        assert!(single_statement("SELECT ';'; -- ;").is_ok());
+        assert!(matches!(
+            single_statement("SELECT 1; SELECT 2"),
+            Err(DbError::Query(_))
+        ));
+    }
+}
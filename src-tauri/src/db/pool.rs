@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use sqlx::{pool::PoolOptions, Database};
+
+/// Connections a pool opens at most when a project doesn't set `maxConnections`
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+/// Most connections a pool may open. Servers accept far fewer by default
+/// (Postgres 100, MySQL 151), so a bigger pool is most likely a typo.
+const MAX_POOL_CONNECTIONS: u32 = 1000;
+
+/// Longest pool timeout, a day. Longer ones are most likely a typo, and large
+/// enough ones overflow when the deadline is computed.
+const MAX_POOL_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Postgres keeps session timeouts in a 32-bit integer and refuses larger ones
+const MAX_SESSION_TIMEOUT_MS: u64 = i32::MAX as u64;
+
+/// Name sessions are tagged with so they can be told apart on the server
+pub const DEFAULT_APPLICATION_NAME: &str = "sqratch";
+
+/// Connection pool and session settings from a project's config.
+/// Every setting is optional, unset ones keep the driver's (or server's) default.
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Default)]
+pub struct PoolSettings {
+    /// Most connections kept open at once (default 10)
+    pub max_connections: Option<u32>,
+    /// Connections kept open even when idle
+    pub min_connections: Option<u32>,
+    /// Seconds to wait for a free connection before giving up
+    pub acquire_timeout_secs: Option<u64>,
+    /// Seconds an idle connection is kept before it's closed
+    pub idle_timeout_secs: Option<u64>,
+    /// Milliseconds a statement may run before the server cancels it (Postgres only)
+    pub statement_timeout_ms: Option<u64>,
+    /// Milliseconds a statement may wait for a lock (Postgres only)
+    pub lock_timeout_ms: Option<u64>,
+    /// Name sessions show up as in `pg_stat_activity`, defaults to `sqratch` unless
+    /// the connection string sets one (Postgres only)
+    pub application_name: Option<String>,
+    /// Schemas to look up unqualified names in, e.g. `"app, public"` (Postgres only)
+    pub search_path: Option<String>,
+    /// Role to switch to after logging in, like `SET ROLE` (Postgres only)
+    pub role: Option<String>,
+}
+
+impl PoolSettings {
+    /// Checks settings that would make every connection attempt fail
+    pub fn validate(&self) -> Result<(), String> {
+        let max_connections = self.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            return Err("pool.maxConnections must be at least 1".to_string());
+        }
+        if max_connections > MAX_POOL_CONNECTIONS {
+            return Err(format!(
+                "pool.maxConnections can't be more than {}",
+                MAX_POOL_CONNECTIONS
+            ));
+        }
+        if self.min_connections.unwrap_or(0) > max_connections {
+            return Err(format!(
+                "pool.minConnections can't be more than pool.maxConnections ({})",
+                max_connections
+            ));
+        }
+
+        for (name, secs) in [
+            ("acquireTimeoutSecs", self.acquire_timeout_secs),
+            ("idleTimeoutSecs", self.idle_timeout_secs),
+        ] {
+            match secs {
+                Some(0) => return Err(format!("pool.{} must be at least 1", name)),
+                Some(secs) if secs > MAX_POOL_TIMEOUT_SECS => {
+                    return Err(format!(
+                        "pool.{} can't be more than {} (a day)",
+                        name, MAX_POOL_TIMEOUT_SECS
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        // 0 turns the server's timeout off, so only the upper bound is checked
+        for (name, ms) in [
+            ("statementTimeoutMs", self.statement_timeout_ms),
+            ("lockTimeoutMs", self.lock_timeout_ms),
+        ] {
+            if ms.is_some_and(|ms| ms > MAX_SESSION_TIMEOUT_MS) {
+                return Err(format!(
+                    "pool.{} can't be more than {}",
+                    name, MAX_SESSION_TIMEOUT_MS
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Options for a pool with these settings. Session settings are applied by
+    /// each backend, they depend on the dialect.
+    pub fn pool_options<DB: Database>(&self) -> PoolOptions<DB> {
+        let mut options = PoolOptions::new()
+            .max_connections(self.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS));
+
+        if let Some(min_connections) = self.min_connections {
+            options = options.min_connections(min_connections);
+        }
+        if let Some(secs) = self.acquire_timeout_secs {
+            options = options.acquire_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.idle_timeout_secs {
+            options = options.idle_timeout(Duration::from_secs(secs));
+        }
+
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_default_and_boundary_settings() {
+        assert_eq!(PoolSettings::default().validate(), Ok(()));
+        let settings = PoolSettings {
+            max_connections: Some(MAX_POOL_CONNECTIONS),
+            min_connections: Some(MAX_POOL_CONNECTIONS),
+            acquire_timeout_secs: Some(MAX_POOL_TIMEOUT_SECS),
+            idle_timeout_secs: Some(1),
+            statement_timeout_ms: Some(0),
+            lock_timeout_ms: Some(MAX_SESSION_TIMEOUT_MS),
+            ..Default::default()
+        };
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_settings_every_connection_would_fail_with() {
+        for (settings, error) in [
+            (
+                PoolSettings {
+                    max_connections: Some(0),
+                    ..Default::default()
+                },
+                "pool.maxConnections must be at least 1",
+            ),
+            (
+                PoolSettings {
+                    max_connections: Some(MAX_POOL_CONNECTIONS + 1),
+                    ..Default::default()
+                },
+                "pool.maxConnections can't be more than 1000",
+            ),
+            (
+                PoolSettings {
+                    max_connections: Some(2),
+                    min_connections: Some(3),
+                    ..Default::default()
+                },
+                "pool.minConnections can't be more than pool.maxConnections (2)",
+            ),
+            (
+                PoolSettings {
+                    min_connections: Some(11),
+                    ..Default::default()
+                },
+                "pool.minConnections can't be more than pool.maxConnections (10)",
+            ),
+            (
+                PoolSettings {
+                    acquire_timeout_secs: Some(0),
+                    ..Default::default()
+                },
+                "pool.acquireTimeoutSecs must be at least 1",
+            ),
+            (
+                PoolSettings {
+                    idle_timeout_secs: Some(u64::MAX),
+                    ..Default::default()
+                },
+                "pool.idleTimeoutSecs can't be more than 86400 (a day)",
+            ),
+            (
+                PoolSettings {
+                    statement_timeout_ms: Some(MAX_SESSION_TIMEOUT_MS + 1),
+                    ..Default::default()
+                },
+                "pool.statementTimeoutMs can't be more than 2147483647",
+            ),
+        ] {
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+    }
+}
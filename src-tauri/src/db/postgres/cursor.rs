@@ -0,0 +1,146 @@
+use sqlx::{pool::PoolConnection, Executor, PgConnection, Postgres};
+
+use crate::db::{
+    client::BatchSink,
+    errors::{DbError, DbResult},
+    helpers::quote_ident,
+    session::{QueryConnection, Session},
+    types::{ColumnDefinition, RowBatch},
+};
+
+use super::run_query_with;
+
+/// Cursor names are this followed by the query ID
+const CURSOR_NAME: &str = "sqratch_cursor";
+
+/// A server-side cursor, pinned to the connection holding its transaction
+pub(super) struct Cursor {
+    query_id: String,
+    name: String,
+    /// The connection whose transaction the cursor was declared in, or `None`
+    /// if it was declared in the session's open transaction
+    conn: Option<PoolConnection<Postgres>>,
+    sink: BatchSink,
+    page_size: u64,
+    max_rows: u64,
+    rows_fetched: u64,
+}
+
+impl Cursor {
+    /// Declares a cursor for `sql` in the session's open transaction, or inside
+    /// a new transaction on a pooled connection
+    pub(super) async fn declare(
+        conn: QueryConnection<'_, Postgres>,
+        query_id: &str,
+        sql: &str,
+        page_size: u64,
+        max_rows: u64,
+        sink: BatchSink,
+    ) -> DbResult<Self> {
+        let sql = sql.trim().trim_end_matches(';');
+
+        // Several cursors can be open in the session, so each needs its own name
+        let name = quote_ident(&format!("{}_{}", CURSOR_NAME, query_id));
+
+        let conn = match conn {
+            QueryConnection::Session(mut session) => {
+                let conn = session
+                    .as_mut()
+                    .expect("session connection is only locked while a transaction is open");
+                declare_cursor(conn, &name, sql).await?;
+                None
+            }
+            QueryConnection::Pooled(mut conn) => {
+                (&mut *conn).execute("BEGIN").await?;
+                if let Err(e) = declare_cursor(&mut conn, &name, sql).await {
+                    let _ = (&mut *conn).execute("ROLLBACK").await;
+                    return Err(e);
+                }
+                Some(conn)
+            }
+        };
+
+        Ok(Self {
+            query_id: query_id.to_string(),
+            name,
+            conn,
+            sink,
+            page_size: page_size.max(1),
+            max_rows,
+            rows_fetched: 0,
+        })
+    }
+
+    /// Fetches the next page of rows and sends it to the sink.
+    /// Returns the cursor's columns and whether there are no more rows.
+    pub(super) async fn fetch_page(
+        &mut self,
+        session: &Session<Postgres>,
+    ) -> DbResult<(Vec<ColumnDefinition>, bool)> {
+        let count = self
+            .page_size
+            .min(self.max_rows.saturating_sub(self.rows_fetched));
+
+        let (columns, rows, execution_time_ms) = if count > 0 {
+            // Not prepared, a prepared FETCH would keep the columns of the first
+            // cursor it ran for and decode the rows of later ones with them
+            let fetch = format!("FETCH FORWARD {} FROM {}", count, self.name);
+            let fetch = || sqlx::query(&fetch).persistent(false);
+            let result = match &mut self.conn {
+                Some(conn) => run_query_with(conn, fetch(), 0).await?,
+                None => {
+                    let mut conn = session.transaction().await.map_err(|_| {
+                        DbError::Query(
+                            "The transaction the cursor was declared in has ended".to_string(),
+                        )
+                    })?;
+                    run_query_with(&mut conn, fetch(), 0).await?
+                }
+            };
+            (result.columns, result.rows, result.execution_time_ms)
+        } else {
+            (Vec::new(), Vec::new(), 0)
+        };
+
+        self.rows_fetched += rows.len() as u64;
+        let capped = self.rows_fetched >= self.max_rows;
+        let done = capped || (rows.len() as u64) < count;
+
+        (self.sink)(RowBatch {
+            query_id: self.query_id.clone(),
+            rows,
+            rows_fetched: self.rows_fetched,
+            execution_time_ms,
+            done,
+            capped,
+        })?;
+
+        Ok((columns, done))
+    }
+
+    /// Ends the cursor's transaction so the connection can go back to the pool.
+    /// If the transaction was aborted, Postgres turns the commit into a rollback.
+    /// A cursor in the session's transaction is closed on its own instead, and
+    /// is already gone if that transaction has ended.
+    pub(super) async fn close(&mut self, session: &Session<Postgres>) -> DbResult<()> {
+        match &mut self.conn {
+            Some(conn) => {
+                (&mut **conn).execute("COMMIT").await?;
+            }
+            None => {
+                if let Ok(mut conn) = session.transaction().await {
+                    (&mut *conn)
+                        .execute(format!("CLOSE {}", self.name).as_str())
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn declare_cursor(conn: &mut PgConnection, name: &str, sql: &str) -> DbResult<()> {
+    let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", name, sql);
+    conn.execute(declare.as_str()).await?;
+    Ok(())
+}
@@ -0,0 +1,599 @@
+use sqlx::{postgres::types::Oid, Pool, Postgres, Row};
+
+use super::introspection::parse_oid;
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::quote_ident,
+};
+
+/// Reconstructs the DDL for any entity, as a script that can be copied into a migration
+pub(super) async fn entity_ddl(pool: &Pool<Postgres>, id: &str) -> DbResult<String> {
+    let oid = parse_oid(id)?;
+
+    // Entity IDs are OIDs, so find out which catalog this one lives in
+    let catalog_query = r#"
+        SELECT 'class' AS catalog, relkind::TEXT AS kind FROM pg_class WHERE oid = $1
+        UNION ALL SELECT 'proc', prokind::TEXT FROM pg_proc WHERE oid = $1
+        UNION ALL SELECT 'type', typtype::TEXT FROM pg_type WHERE oid = $1
+        UNION ALL SELECT 'trigger', '' FROM pg_trigger WHERE oid = $1
+        UNION ALL SELECT 'schema', '' FROM pg_namespace WHERE oid = $1
+        UNION ALL SELECT 'extension', '' FROM pg_extension WHERE oid = $1
+        UNION ALL SELECT 'event_trigger', '' FROM pg_event_trigger WHERE oid = $1
+        LIMIT 1
+    "#;
+
+    let row = sqlx::query(catalog_query)
+        .bind(oid)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("Entity {} not found", id)))?;
+
+    let catalog: String = row.get("catalog");
+    let kind: String = row.get("kind");
+
+    match (catalog.as_str(), kind.as_str()) {
+        ("class", "r" | "p" | "f") => table_ddl(pool, oid).await,
+        ("class", "v" | "m") => view_ddl(pool, oid).await,
+        ("class", "S") => sequence_ddl(pool, oid).await,
+        ("class", "i" | "I") => single_ddl(pool, "SELECT pg_get_indexdef($1) || ';'", oid).await,
+        ("proc", "a") => Err(DbError::Unsupported(
+            "Showing the definition of aggregate functions".to_string(),
+        )),
+        ("proc", _) => single_ddl(pool, "SELECT pg_get_functiondef($1) || ';'", oid).await,
+        ("type", _) => type_ddl(pool, oid, &kind).await,
+        ("trigger", _) => single_ddl(pool, "SELECT pg_get_triggerdef($1, true) || ';'", oid).await,
+        ("schema", _) => {
+            let sql = r#"
+                SELECT format('CREATE SCHEMA %I AUTHORIZATION %I;', nspname, pg_get_userbyid(nspowner))
+                FROM pg_namespace WHERE oid = $1
+            "#;
+            single_ddl(pool, sql, oid).await
+        }
+        ("extension", _) => {
+            let sql = r#"
+                SELECT format(
+                    'CREATE EXTENSION IF NOT EXISTS %I WITH SCHEMA %I VERSION %L;',
+                    e.extname, n.nspname, e.extversion
+                )
+                FROM pg_extension e
+                JOIN pg_namespace n ON e.extnamespace = n.oid
+                WHERE e.oid = $1
+            "#;
+            single_ddl(pool, sql, oid).await
+        }
+        ("event_trigger", _) => event_trigger_ddl(pool, oid).await,
+        _ => Err(DbError::Unsupported(format!(
+            "Showing the definition of {} entities of kind {}",
+            catalog, kind
+        ))),
+    }
+}
+
+/// Runs a query returning a single DDL statement
+async fn single_ddl(pool: &Pool<Postgres>, sql: &str, oid: Oid) -> DbResult<String> {
+    Ok(sqlx::query_scalar(sql).bind(oid).fetch_one(pool).await?)
+}
+
+/// Schema qualified, quoted name of a relation
+async fn relation_name(pool: &Pool<Postgres>, oid: Oid) -> DbResult<String> {
+    let sql = r#"
+        SELECT format('%I.%I', n.nspname, c.relname)
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        WHERE c.oid = $1
+    "#;
+    single_ddl(pool, sql, oid).await
+}
+
+async fn table_ddl(pool: &Pool<Postgres>, oid: Oid) -> DbResult<String> {
+    let table_query = r#"
+        SELECT
+            format('%I.%I', n.nspname, c.relname) AS name,
+            c.relkind::TEXT AS kind,
+            c.relispartition AS is_partition,
+            (
+                SELECT format('%I.%I', pn.nspname, pc.relname)
+                FROM pg_inherits i
+                JOIN pg_class pc ON pc.oid = i.inhparent
+                JOIN pg_namespace pn ON pc.relnamespace = pn.oid
+                WHERE i.inhrelid = c.oid
+                LIMIT 1
+            ) AS parent,
+            pg_get_expr(c.relpartbound, c.oid) AS partition_bound,
+            CASE WHEN c.relkind = 'p' THEN pg_get_partkeydef(c.oid) END AS partition_key,
+            (
+                SELECT format('SERVER %I', s.srvname)
+                    || COALESCE(
+                        ' OPTIONS (' || (
+                            SELECT string_agg(format('%I %L', split_part(o, '=', 1),
+                                substr(o, strpos(o, '=') + 1)), ', ')
+                            FROM unnest(ft.ftoptions) AS o
+                        ) || ')',
+                        ''
+                    )
+                FROM pg_foreign_table ft
+                JOIN pg_foreign_server s ON s.oid = ft.ftserver
+                WHERE ft.ftrelid = c.oid
+            ) AS foreign_server,
+            pg_get_userbyid(c.relowner) AS owner,
+            c.relrowsecurity AS row_security,
+            c.relforcerowsecurity AS force_row_security
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        WHERE c.oid = $1
+    "#;
+
+    let table = sqlx::query(table_query).bind(oid).fetch_one(pool).await?;
+    let name: String = table.get("name");
+    let kind: String = table.get("kind");
+    let is_partition: bool = table.get("is_partition");
+    let parent: Option<String> = table.get("parent");
+    let object = if kind == "f" {
+        "FOREIGN TABLE"
+    } else {
+        "TABLE"
+    };
+
+    let mut statements = Vec::new();
+
+    // Partitions take their columns from the parent table
+    let mut create = match (is_partition, parent) {
+        (true, Some(parent)) => {
+            let bound: Option<String> = table.get("partition_bound");
+            format!(
+                "CREATE {} {} PARTITION OF {}\n    {}",
+                object,
+                name,
+                parent,
+                bound.unwrap_or_default()
+            )
+        }
+        _ => {
+            let mut definitions = column_definitions(pool, oid).await?;
+            definitions.extend(constraint_definitions(pool, oid).await?.into_iter().map(
+                |(name, definition)| format!("CONSTRAINT {} {}", quote_ident(&name), definition),
+            ));
+
+            format!(
+                "CREATE {} {} (\n    {}\n)",
+                object,
+                name,
+                definitions.join(",\n    ")
+            )
+        }
+    };
+
+    if let Some(partition_key) = table.get::<Option<String>, _>("partition_key") {
+        create.push_str(&format!("\nPARTITION BY {}", partition_key));
+    }
+    if let Some(server) = table.get::<Option<String>, _>("foreign_server") {
+        create.push_str(&format!("\n{}", server));
+    }
+    statements.push(format!("{};", create));
+
+    // Indexes that back a constraint are created by the constraint
+    let index_query = r#"
+        SELECT pg_get_indexdef(i.indexrelid) || ';'
+        FROM pg_index i
+        JOIN pg_class ic ON ic.oid = i.indexrelid
+        WHERE i.indrelid = $1
+          AND NOT EXISTS (
+              SELECT 1 FROM pg_constraint con
+              WHERE con.conindid = i.indexrelid AND con.contype IN ('p', 'u', 'x')
+          )
+          -- Indexes attached to a parent's index are created along with it
+          AND NOT EXISTS (SELECT 1 FROM pg_inherits inh WHERE inh.inhrelid = i.indexrelid)
+        ORDER BY ic.relname
+    "#;
+    statements.push(join_scalars(pool, index_query, oid).await?);
+
+    let trigger_query = r#"
+        SELECT pg_get_triggerdef(t.oid, true) || ';'
+        FROM pg_trigger t
+        WHERE t.tgrelid = $1
+          AND NOT t.tgisinternal
+          AND t.tgparentid = 0
+        ORDER BY t.tgname
+    "#;
+    statements.push(join_scalars(pool, trigger_query, oid).await?);
+    statements.push(comment_statements(pool, oid, object).await?);
+
+    statements.push(format!(
+        "ALTER {} {} OWNER TO {};",
+        object,
+        name,
+        quote_ident(table.get("owner"))
+    ));
+
+    if table.get::<bool, _>("row_security") {
+        let mut rls = format!("ALTER TABLE {} ENABLE ROW LEVEL SECURITY;", name);
+        if table.get::<bool, _>("force_row_security") {
+            rls.push_str(&format!("\nALTER TABLE {} FORCE ROW LEVEL SECURITY;", name));
+        }
+        statements.push(rls);
+    }
+
+    let policy_query = r#"
+        SELECT format(
+            'CREATE POLICY %I ON %I.%I AS %s FOR %s TO %s',
+            p.policyname, p.schemaname, p.tablename, p.permissive, p.cmd,
+            (SELECT string_agg(CASE WHEN r = 'public' THEN r ELSE quote_ident(r) END, ', ')
+             FROM unnest(p.roles::TEXT[]) AS r)
+        )
+            || COALESCE(E'\n    USING (' || p.qual || ')', '')
+            || COALESCE(E'\n    WITH CHECK (' || p.with_check || ')', '')
+            || ';'
+        FROM pg_policies p
+        JOIN pg_class c ON c.relname = p.tablename
+        JOIN pg_namespace n ON c.relnamespace = n.oid AND n.nspname = p.schemaname
+        WHERE c.oid = $1
+        ORDER BY p.policyname
+    "#;
+    statements.push(join_scalars(pool, policy_query, oid).await?);
+
+    Ok(join_statements(statements))
+}
+
+/// Column lines of a `CREATE TABLE`, e.g. `id integer GENERATED ALWAYS AS IDENTITY`
+async fn column_definitions(pool: &Pool<Postgres>, oid: Oid) -> DbResult<Vec<String>> {
+    let column_query = r#"
+        SELECT
+            a.attname AS name,
+            format_type(a.atttypid, a.atttypmod) AS data_type,
+            (
+                SELECT format('%I.%I', cn.nspname, co.collname)
+                FROM pg_collation co
+                JOIN pg_namespace cn ON co.collnamespace = cn.oid
+                WHERE co.oid = a.attcollation AND a.attcollation <> t.typcollation
+            ) AS collation,
+            a.attnotnull AS not_null,
+            pg_get_expr(ad.adbin, ad.adrelid) AS default_expr,
+            a.attgenerated::TEXT AS generated,
+            a.attidentity::TEXT AS identity
+        FROM pg_attribute a
+        JOIN pg_type t ON t.oid = a.atttypid
+        LEFT JOIN pg_attrdef ad ON ad.adrelid = a.attrelid AND ad.adnum = a.attnum
+        WHERE a.attrelid = $1
+          AND a.attnum > 0
+          AND NOT a.attisdropped
+        ORDER BY a.attnum
+    "#;
+
+    let rows = sqlx::query(column_query).bind(oid).fetch_all(pool).await?;
+    let columns = rows
+        .into_iter()
+        .map(|row| {
+            let name: String = row.get("name");
+            let data_type: String = row.get("data_type");
+            let mut column = format!("{} {}", quote_ident(&name), data_type);
+
+            if let Some(collation) = row.get::<Option<String>, _>("collation") {
+                column.push_str(&format!(" COLLATE {}", collation));
+            }
+
+            let default_expr: Option<String> = row.get("default_expr");
+            let generated: String = row.get("generated");
+            let identity: String = row.get("identity");
+            match (generated.as_str(), identity.as_str(), default_expr) {
+                ("s", _, Some(expr)) => {
+                    column.push_str(&format!(" GENERATED ALWAYS AS ({}) STORED", expr))
+                }
+                (_, "a", _) => column.push_str(" GENERATED ALWAYS AS IDENTITY"),
+                (_, "d", _) => column.push_str(" GENERATED BY DEFAULT AS IDENTITY"),
+                (_, _, Some(expr)) => column.push_str(&format!(" DEFAULT {}", expr)),
+                _ => {}
+            }
+
+            if row.get::<bool, _>("not_null") {
+                column.push_str(" NOT NULL");
+            }
+            column
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+/// Names and definitions of a table's own constraints, primary key first
+async fn constraint_definitions(
+    pool: &Pool<Postgres>,
+    oid: Oid,
+) -> DbResult<Vec<(String, String)>> {
+    let constraint_query = r#"
+        SELECT con.conname AS name, pg_get_constraintdef(con.oid) AS definition
+        FROM pg_constraint con
+        WHERE con.conrelid = $1
+          AND con.contype IN ('p', 'u', 'c', 'x', 'f')
+          AND con.conislocal
+        ORDER BY array_position(ARRAY['p', 'u', 'c', 'x', 'f'], con.contype::TEXT), con.conname
+    "#;
+
+    let rows = sqlx::query(constraint_query)
+        .bind(oid)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("name"), row.get("definition")))
+        .collect())
+}
+
+/// `COMMENT ON` statements for a relation and its columns
+async fn comment_statements(pool: &Pool<Postgres>, oid: Oid, object: &str) -> DbResult<String> {
+    let comment_query = r#"
+        SELECT format('COMMENT ON %s %I.%I IS %L;', $2::TEXT, n.nspname, c.relname, d.description)
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        JOIN pg_description d ON d.objoid = c.oid
+            AND d.classoid = 'pg_class'::regclass AND d.objsubid = 0
+        WHERE c.oid = $1
+        UNION ALL
+        SELECT format('COMMENT ON COLUMN %I.%I.%I IS %L;', n.nspname, c.relname, a.attname, d.description)
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        JOIN pg_attribute a ON a.attrelid = c.oid
+        JOIN pg_description d ON d.objoid = c.oid
+            AND d.classoid = 'pg_class'::regclass AND d.objsubid = a.attnum
+        WHERE c.oid = $1
+          AND a.attnum > 0
+          AND NOT a.attisdropped
+    "#;
+
+    let comments: Vec<String> = sqlx::query_scalar(comment_query)
+        .bind(oid)
+        .bind(object)
+        .fetch_all(pool)
+        .await?;
+    Ok(comments.join("\n"))
+}
+
+async fn view_ddl(pool: &Pool<Postgres>, oid: Oid) -> DbResult<String> {
+    let view_query = r#"
+        SELECT
+            format('%I.%I', n.nspname, c.relname) AS name,
+            c.relkind = 'm' AS materialized,
+            pg_get_viewdef(c.oid, true) AS definition,
+            pg_get_userbyid(c.relowner) AS owner
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        WHERE c.oid = $1
+    "#;
+
+    let view = sqlx::query(view_query).bind(oid).fetch_one(pool).await?;
+    let name: String = view.get("name");
+    let materialized: bool = view.get("materialized");
+    let definition: String = view.get("definition");
+    let definition = definition.trim().trim_end_matches(';');
+
+    let (create, object) = if materialized {
+        (
+            format!(
+                "CREATE MATERIALIZED VIEW {} AS\n{}\nWITH DATA;",
+                name, definition
+            ),
+            "MATERIALIZED VIEW",
+        )
+    } else {
+        (
+            format!("CREATE OR REPLACE VIEW {} AS\n{};", name, definition),
+            "VIEW",
+        )
+    };
+
+    let mut statements = vec![create];
+    if materialized {
+        let index_query = r#"
+            SELECT pg_get_indexdef(i.indexrelid) || ';'
+            FROM pg_index i
+            JOIN pg_class ic ON ic.oid = i.indexrelid
+            WHERE i.indrelid = $1
+            ORDER BY ic.relname
+        "#;
+        statements.push(join_scalars(pool, index_query, oid).await?);
+    }
+
+    statements.push(comment_statements(pool, oid, object).await?);
+    statements.push(format!(
+        "ALTER {} {} OWNER TO {};",
+        object,
+        name,
+        quote_ident(view.get("owner"))
+    ));
+
+    Ok(join_statements(statements))
+}
+
+async fn sequence_ddl(pool: &Pool<Postgres>, oid: Oid) -> DbResult<String> {
+    let sequence_query = r#"
+        SELECT format(
+            E'CREATE SEQUENCE %s\n    AS %s\n    INCREMENT BY %s\n    MINVALUE %s\n    MAXVALUE %s\n    START WITH %s\n    CACHE %s%s;',
+            $2::TEXT,
+            format_type(s.seqtypid, NULL),
+            s.seqincrement,
+            s.seqmin,
+            s.seqmax,
+            s.seqstart,
+            s.seqcache,
+            CASE WHEN s.seqcycle THEN E'\n    CYCLE' ELSE '' END
+        )
+        FROM pg_sequence s
+        WHERE s.seqrelid = $1
+    "#;
+
+    let name = relation_name(pool, oid).await?;
+    let create: String = sqlx::query_scalar(sequence_query)
+        .bind(oid)
+        .bind(&name)
+        .fetch_one(pool)
+        .await?;
+
+    // Sequences owned by a column are dropped along with it
+    let owned_by_query = r#"
+        SELECT format('ALTER SEQUENCE %s OWNED BY %I.%I.%I;', $2::TEXT, n.nspname, c.relname, a.attname)
+        FROM pg_depend d
+        JOIN pg_class c ON c.oid = d.refobjid
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        JOIN pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid
+        WHERE d.classid = 'pg_class'::regclass
+          AND d.objid = $1
+          AND d.refclassid = 'pg_class'::regclass
+          AND d.deptype = 'a'
+    "#;
+    let owned_by: Option<String> = sqlx::query_scalar(owned_by_query)
+        .bind(oid)
+        .bind(&name)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(join_statements(vec![
+        create,
+        owned_by.unwrap_or_default(),
+        comment_statements(pool, oid, "SEQUENCE").await?,
+    ]))
+}
+
+async fn type_ddl(pool: &Pool<Postgres>, oid: Oid, typtype: &str) -> DbResult<String> {
+    let name: String = sqlx::query_scalar(
+        r#"
+        SELECT format('%I.%I', n.nspname, t.typname)
+        FROM pg_type t
+        JOIN pg_namespace n ON t.typnamespace = n.oid
+        WHERE t.oid = $1
+    "#,
+    )
+    .bind(oid)
+    .fetch_one(pool)
+    .await?;
+
+    let create = match typtype {
+        "e" => {
+            let labels_query = r#"
+                SELECT string_agg(quote_literal(e.enumlabel), E',\n    ' ORDER BY e.enumsortorder)
+                FROM pg_enum e
+                WHERE e.enumtypid = $1
+            "#;
+            let labels: Option<String> = sqlx::query_scalar(labels_query)
+                .bind(oid)
+                .fetch_one(pool)
+                .await?;
+            format!(
+                "CREATE TYPE {} AS ENUM (\n    {}\n);",
+                name,
+                labels.unwrap_or_default()
+            )
+        }
+        "d" => {
+            let domain_query = r#"
+                SELECT
+                    format_type(t.typbasetype, t.typtypmod)
+                        || COALESCE(' DEFAULT ' || t.typdefault, '')
+                        || CASE WHEN t.typnotnull THEN ' NOT NULL' ELSE '' END
+                        || COALESCE((
+                            SELECT string_agg(
+                                format(E'\n    CONSTRAINT %I %s', con.conname, pg_get_constraintdef(con.oid)),
+                                '' ORDER BY con.conname
+                            )
+                            FROM pg_constraint con
+                            WHERE con.contypid = t.oid
+                        ), '')
+                FROM pg_type t
+                WHERE t.oid = $1
+            "#;
+            let definition: String = sqlx::query_scalar(domain_query)
+                .bind(oid)
+                .fetch_one(pool)
+                .await?;
+            format!("CREATE DOMAIN {} AS {};", name, definition)
+        }
+        "c" => {
+            let attributes_query = r#"
+                SELECT string_agg(
+                    format('%I %s', a.attname, format_type(a.atttypid, a.atttypmod)),
+                    E',\n    ' ORDER BY a.attnum
+                )
+                FROM pg_type t
+                JOIN pg_attribute a ON a.attrelid = t.typrelid
+                WHERE t.oid = $1
+                  AND a.attnum > 0
+                  AND NOT a.attisdropped
+            "#;
+            let attributes: Option<String> = sqlx::query_scalar(attributes_query)
+                .bind(oid)
+                .fetch_one(pool)
+                .await?;
+            format!(
+                "CREATE TYPE {} AS (\n    {}\n);",
+                name,
+                attributes.unwrap_or_default()
+            )
+        }
+        "r" => {
+            let range_query = r#"
+                SELECT format('SUBTYPE = %s', format_type(r.rngsubtype, NULL))
+                    || CASE
+                        WHEN r.rngsubdiff::OID <> 0 THEN ', SUBTYPE_DIFF = ' || r.rngsubdiff::TEXT
+                        ELSE ''
+                    END
+                FROM pg_range r
+                WHERE r.rngtypid = $1
+            "#;
+            let definition: String = sqlx::query_scalar(range_query)
+                .bind(oid)
+                .fetch_one(pool)
+                .await?;
+            format!("CREATE TYPE {} AS RANGE ({});", name, definition)
+        }
+        _ => {
+            return Err(DbError::Unsupported(format!(
+                "Showing the definition of {} types",
+                name
+            )))
+        }
+    };
+
+    let comment_query = r#"
+        SELECT format('COMMENT ON TYPE %s IS %L;', $2::TEXT, d.description)
+        FROM pg_description d
+        WHERE d.objoid = $1 AND d.classoid = 'pg_type'::regclass
+    "#;
+    let comment: Option<String> = sqlx::query_scalar(comment_query)
+        .bind(oid)
+        .bind(&name)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(join_statements(vec![create, comment.unwrap_or_default()]))
+}
+
+async fn event_trigger_ddl(pool: &Pool<Postgres>, oid: Oid) -> DbResult<String> {
+    let event_trigger_query = r#"
+        SELECT format('CREATE EVENT TRIGGER %I ON %s', t.evtname, t.evtevent)
+            || COALESCE(
+                E'\n    WHEN TAG IN (' || (
+                    SELECT string_agg(quote_literal(tag), ', ') FROM unnest(t.evttags) AS tag
+                ) || ')',
+                ''
+            )
+            || format(E'\n    EXECUTE FUNCTION %s();', t.evtfoid::regproc)
+        FROM pg_event_trigger t
+        WHERE t.oid = $1
+    "#;
+    single_ddl(pool, event_trigger_query, oid).await
+}
+
+/// Runs a query returning one statement per row, joined into a block
+async fn join_scalars(pool: &Pool<Postgres>, sql: &str, oid: Oid) -> DbResult<String> {
+    let statements: Vec<String> = sqlx::query_scalar(sql).bind(oid).fetch_all(pool).await?;
+    Ok(statements.join("\n"))
+}
+
+/// Joins blocks of statements with a blank line between them, skipping empty ones
+fn join_statements(statements: Vec<String>) -> String {
+    let mut script = statements
+        .into_iter()
+        .filter(|statement| !statement.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    script.push('\n');
+    script
+}
@@ -1,103 +1,308 @@
 use serde_json::Value as JsonValue;
-use sqlx::{postgres::PgValueRef, TypeInfo, Value, ValueRef};
-use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+use sqlx::{
+    postgres::{
+        types::{Oid, PgInterval, PgMoney, PgTimeTz},
+        PgTypeKind, PgValue, PgValueFormat, PgValueRef, Postgres,
+    },
+    types::{ipnetwork::IpNetwork, mac_address::MacAddress, BigDecimal, Uuid},
+    Decode, Type, TypeInfo, Value, ValueRef,
+};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-use crate::Error;
+use crate::db::{helpers::to_hex, types::CellValue};
 
-pub(crate) fn to_json(v: PgValueRef) -> Result<JsonValue, Error> {
+/// Decodes a single Postgres value into a cell, picking a decoder based on its `PgTypeInfo`.
+/// Types we don't know how to decode fall back to their raw value, see `to_raw`.
+pub(crate) fn to_cell(v: PgValueRef) -> CellValue {
     if v.is_null() {
-        return Ok(JsonValue::Null);
+        return CellValue::Null;
     }
 
-    let res = match v.type_info().name() {
-        "CHAR" | "VARCHAR" | "TEXT" | "NAME" | "UUID" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::String(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "FLOAT4" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<f32>() {
-                JsonValue::from(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "FLOAT8" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<f64>() {
-                JsonValue::from(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "INT2" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i16>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "INT4" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i32>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "INT8" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i64>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
+    // Domains are decoded as their base type
+    let type_info = v.type_info();
+    let value = ValueRef::to_owned(&v);
+    let res = match type_info.kind() {
+        PgTypeKind::Domain(base) => decode_scalar(base.name(), &value),
+        PgTypeKind::Array(elem) => decode_array(elem.name(), &value),
+        _ => decode_scalar(type_info.name(), &value),
+    };
+
+    res.unwrap_or_else(|| to_raw(&v))
+}
+
+fn decode_scalar(type_name: &str, value: &PgValue) -> Option<CellValue> {
+    match type_name {
+        "CHAR" | "VARCHAR" | "TEXT" | "NAME" | "UNKNOWN" => {
+            decode::<String>(value).map(CellValue::Text)
         }
-        "BOOL" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::Bool(v)
-            } else {
-                JsonValue::Null
-            }
+        "UUID" => decode::<Uuid>(value).map(|v| CellValue::Uuid(v.to_string())),
+        "FLOAT4" => decode::<f32>(value).map(|v| CellValue::Float(v.into())),
+        "FLOAT8" => decode::<f64>(value).map(CellValue::Float),
+        "INT2" => decode::<i16>(value).map(|v| CellValue::Int(v.into())),
+        "INT4" => decode::<i32>(value).map(|v| CellValue::Int(v.into())),
+        "INT8" => decode::<i64>(value).map(CellValue::Int),
+        "OID" => decode::<Oid>(value).map(|v| CellValue::Int(v.0.into())),
+        "NUMERIC" => decode::<BigDecimal>(value).map(|v| {
+            let v = match display_scale(value) {
+                Some(scale) => v.with_scale(scale),
+                None => v,
+            };
+            CellValue::Numeric(v.to_string())
+        }),
+        "MONEY" => {
+            decode::<PgMoney>(value).map(|v| CellValue::Numeric(v.to_bigdecimal(2).to_string()))
         }
-        "DATE" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Date>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+        "BOOL" => decode::<bool>(value).map(CellValue::Bool),
+        "DATE" => decode::<Date>(value).map(|v| CellValue::Date(v.to_string())),
+        "TIME" => decode::<Time>(value).map(|v| CellValue::Time(v.to_string())),
+        "TIMETZ" => decode::<PgTimeTz<Time, UtcOffset>>(value)
+            .map(|v| CellValue::Time(format!("{}{}", v.time, v.offset))),
+        "TIMESTAMP" => {
+            decode::<PrimitiveDateTime>(value).map(|v| CellValue::Timestamp(v.to_string()))
         }
-        "TIME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Time>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+        "TIMESTAMPTZ" => {
+            decode::<OffsetDateTime>(value).map(|v| CellValue::TimestampTz(v.to_string()))
         }
+        "INTERVAL" => decode::<PgInterval>(value).map(|v| CellValue::Interval(format_interval(&v))),
+        "INET" | "CIDR" => decode::<IpNetwork>(value).map(|v| CellValue::Text(v.to_string())),
+        "MACADDR" => decode::<MacAddress>(value).map(|v| CellValue::Text(v.to_string())),
+        "JSON" | "JSONB" => decode::<JsonValue>(value).map(CellValue::Json),
+        "BYTEA" => decode::<Vec<u8>>(value).map(|v| CellValue::Bytes(to_hex(&v))),
+        "VOID" => Some(CellValue::Null),
+        _ => None,
+    }
+}
+
+/// Only one-dimensional arrays of the more common element types are decoded,
+/// everything else uses the raw fallback.
+fn decode_array(elem_name: &str, value: &PgValue) -> Option<CellValue> {
+    match elem_name {
+        "CHAR" | "VARCHAR" | "TEXT" | "NAME" => decode_vec::<String>(value, CellValue::Text),
+        "UUID" => decode_vec::<Uuid>(value, |v| CellValue::Uuid(v.to_string())),
+        "FLOAT4" => decode_vec::<f32>(value, |v| CellValue::Float(v.into())),
+        "FLOAT8" => decode_vec::<f64>(value, CellValue::Float),
+        "INT2" => decode_vec::<i16>(value, |v| CellValue::Int(v.into())),
+        "INT4" => decode_vec::<i32>(value, |v| CellValue::Int(v.into())),
+        "INT8" => decode_vec::<i64>(value, CellValue::Int),
+        "NUMERIC" => decode_vec::<BigDecimal>(value, |v| CellValue::Numeric(v.to_string())),
+        "BOOL" => decode_vec::<bool>(value, CellValue::Bool),
+        "DATE" => decode_vec::<Date>(value, |v| CellValue::Date(v.to_string())),
         "TIMESTAMP" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+            decode_vec::<PrimitiveDateTime>(value, |v| CellValue::Timestamp(v.to_string()))
         }
         "TIMESTAMPTZ" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+            decode_vec::<OffsetDateTime>(value, |v| CellValue::TimestampTz(v.to_string()))
         }
-        "JSON" | "JSONB" => ValueRef::to_owned(&v).try_decode().unwrap_or_default(),
-        "BYTEA" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
-                JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "VOID" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+        "JSON" | "JSONB" => decode_vec::<JsonValue>(value, CellValue::Json),
+        _ => None,
+    }
+}
+
+/// Decodes a value without checking type compatibility, since the type name has
+/// already been matched (and may be a domain over the decoded type).
+fn decode<T>(value: &PgValue) -> Option<T>
+where
+    T: for<'r> Decode<'r, Postgres>,
+{
+    value.try_decode_unchecked().ok()
+}
+
+/// Digits after the decimal point a binary `numeric` is shown with, e.g. 2 for
+/// `1.50`. Decoding only keeps the digits Postgres sends, which come in groups of 4.
+fn display_scale(value: &PgValue) -> Option<i64> {
+    let value = value.as_ref();
+    if value.format() != PgValueFormat::Binary {
+        return None;
+    }
+    let bytes = value.as_bytes().ok()?;
+    let scale = bytes.get(6..8)?;
+    Some(u16::from_be_bytes([scale[0], scale[1]]).into())
+}
+
+fn decode_vec<T>(value: &PgValue, to_cell: impl Fn(T) -> CellValue) -> Option<CellValue>
+where
+    T: for<'r> Decode<'r, Postgres> + Type<Postgres>,
+{
+    let items = decode::<Vec<Option<T>>>(value)?;
+    Some(CellValue::Array(
+        items
+            .into_iter()
+            .map(|item| item.map_or(CellValue::Null, &to_cell))
+            .collect(),
+    ))
+}
+
+/// Fallback for types we don't explicitly support (enums, extension types, etc).
+///
+/// Values are in text format only when the query ran through the simple protocol,
+/// otherwise they're in the type's binary encoding. That's the text for enums and
+/// a few types like `xml` and `citext`, which are returned as text. Any other
+/// binary value (points, ranges, `tsvector`, ...) is returned as its raw bytes.
+fn to_raw(v: &PgValueRef) -> CellValue {
+    let Ok(bytes) = v.as_bytes() else {
+        return CellValue::Null;
+    };
+
+    let type_info = v.type_info();
+    let type_info = match type_info.kind() {
+        PgTypeKind::Domain(base) => base,
+        _ => &*type_info,
     };
+    let is_text = v.format() == PgValueFormat::Text
+        || matches!(type_info.kind(), PgTypeKind::Enum(_))
+        || matches!(type_info.name(), "xml" | "citext");
 
-    Ok(res)
+    match std::str::from_utf8(bytes) {
+        Ok(s) if is_text => CellValue::Text(s.to_string()),
+        _ => CellValue::Bytes(to_hex(bytes)),
+    }
+}
+
+/// Formats an interval the same way Postgres does with `IntervalStyle = postgres`.
+/// Only exactly one unit is singular, `-1 mons` is plural like in Postgres.
+pub(crate) fn format_interval(interval: &PgInterval) -> String {
+    let mut parts = Vec::new();
+
+    let years = interval.months / 12;
+    let months = interval.months % 12;
+    if years != 0 {
+        parts.push(format!(
+            "{} year{}",
+            years,
+            if years == 1 { "" } else { "s" }
+        ));
+    }
+    if months != 0 {
+        parts.push(format!(
+            "{} mon{}",
+            months,
+            if months == 1 { "" } else { "s" }
+        ));
+    }
+    if interval.days != 0 {
+        let days = interval.days;
+        parts.push(format!("{} day{}", days, if days == 1 { "" } else { "s" }));
+    }
+
+    let micros = interval.microseconds;
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let micros = micros.unsigned_abs();
+        let secs = micros / 1_000_000;
+        let frac = micros % 1_000_000;
+        let mut time = format!(
+            "{}{:02}:{:02}:{:02}",
+            sign,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60
+        );
+        if frac != 0 {
+            time.push_str(format!(".{:06}", frac).trim_end_matches('0'));
+        }
+        parts.push(time);
+    }
+
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::postgres::testing;
+    use sqlx::{postgres::PgRow, Row};
+
+    fn interval(months: i32, days: i32, microseconds: i64) -> String {
+        format_interval(&PgInterval {
+            months,
+            days,
+            microseconds,
+        })
+    }
+
+    #[test]
+    fn formats_intervals_like_postgres() {
+        assert_eq!(interval(0, 0, 0), "00:00:00");
+        assert_eq!(interval(1, 0, 0), "1 mon");
+        assert_eq!(interval(-1, 0, 0), "-1 mons");
+        assert_eq!(interval(-14, 0, 0), "-1 years -2 mons");
+        assert_eq!(interval(0, -1, 0), "-1 days");
+        assert_eq!(interval(0, 1, -3_600_000_000), "1 day -01:00:00");
+        assert_eq!(interval(0, 0, -1_500_000), "-00:00:01.5");
+        assert_eq!(interval(0, -3, -1), "-3 days -00:00:00.000001");
+        assert_eq!(interval(0, 0, 360_000_000_000), "100:00:00");
+        assert_eq!(
+            interval(14, 3, 3_723_500_000),
+            "1 year 2 mons 3 days 01:02:03.5"
+        );
+    }
+
+    fn cells(row: &PgRow) -> Vec<CellValue> {
+        (0..row.len())
+            .map(|i| to_cell(row.try_get_raw(i).unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn decodes_known_types() {
+        let Some(mut conn) = testing::connect().await else {
+            return;
+        };
+        let row = sqlx::query(
+            "SELECT NULL::int4, 42::int2, 9007199254740993::int8, 1.50::numeric, 'a'::text,
+                    true, '2024-02-29'::date, '2024-02-29 12:00:00+00'::timestamptz,
+                    '-1 day 02:00:00'::interval, '{\"a\": 1}'::jsonb, '\\xdead'::bytea,
+                    ARRAY[1, NULL]::int4[]",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            cells(&row),
+            vec![
+                CellValue::Null,
+                CellValue::Int(42),
+                CellValue::Int(9_007_199_254_740_993),
+                CellValue::Numeric("1.50".to_string()),
+                CellValue::Text("a".to_string()),
+                CellValue::Bool(true),
+                CellValue::Date("2024-02-29".to_string()),
+                CellValue::TimestampTz("2024-02-29 12:00:00.0 +00:00:00".to_string()),
+                CellValue::Interval("-1 days 02:00:00".to_string()),
+                CellValue::Json(serde_json::json!({"a": 1})),
+                CellValue::Bytes("\\xdead".to_string()),
+                CellValue::Array(vec![CellValue::Int(1), CellValue::Null]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_text_or_raw_bytes() {
+        let Some(mut conn) = testing::connect().await else {
+            return;
+        };
+        sqlx::raw_sql("CREATE TYPE pg_temp.mood AS ENUM ('happy', 'sad')")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let sql = "SELECT 'sad'::pg_temp.mood, point(1, 2), ARRAY[[1, 2], [3, 4]]";
+
+        // Binary results, only the enum's encoding is its text
+        let row = sqlx::query(sql).fetch_one(&mut conn).await.unwrap();
+        let binary = cells(&row);
+        assert_eq!(binary[0], CellValue::Text("sad".to_string()));
+        assert!(matches!(binary[1], CellValue::Bytes(_)));
+        assert!(matches!(binary[2], CellValue::Bytes(_)));
+
+        // The simple protocol returns text
+        let row = sqlx::raw_sql(sql).fetch_one(&mut conn).await.unwrap();
+        assert_eq!(
+            cells(&row)[1..],
+            [
+                CellValue::Text("(1,2)".to_string()),
+                CellValue::Text("{{1,2},{3,4}}".to_string()),
+            ]
+        );
+    }
 }
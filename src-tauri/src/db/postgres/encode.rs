@@ -0,0 +1,283 @@
+use std::str::FromStr;
+
+use serde_json::Value as JsonValue;
+use sqlx::{
+    postgres::{PgArguments, PgTypeInfo, PgTypeKind, Postgres},
+    query::Query,
+    types::{BigDecimal, Uuid},
+    TypeInfo,
+};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime,
+    PrimitiveDateTime, Time,
+};
+
+use crate::db::{
+    errors::{DbError, DbResult},
+    types::BindValue,
+};
+
+type PgQuery<'q> = Query<'q, Postgres, PgArguments>;
+
+/// Binds a value to the next placeholder of a prepared statement.
+///
+/// Values are sent in binary, so they have to be converted to exactly the type
+/// Postgres inferred for the parameter (e.g. a text value bound to a `uuid` parameter).
+pub(super) fn bind_param<'q>(
+    query: PgQuery<'q>,
+    index: usize,
+    value: &BindValue,
+    param_type: &PgTypeInfo,
+) -> DbResult<PgQuery<'q>> {
+    // Domains take their base type, enums are sent as their label
+    let type_name = match param_type.kind() {
+        PgTypeKind::Domain(base) => base.name(),
+        PgTypeKind::Enum(_) => "TEXT",
+        _ => param_type.name(),
+    };
+
+    let invalid = |detail: &str| {
+        DbError::Query(format!(
+            "Invalid value for parameter ${} of type {}: {}",
+            index, type_name, detail
+        ))
+    };
+
+    let query = match (type_name, value) {
+        (_, BindValue::Null) => query.bind(None::<String>),
+        ("BOOL", BindValue::Bool(v)) => query.bind(*v),
+        ("BOOL", BindValue::Text(v)) => query.bind(parse_bool(v).ok_or_else(|| invalid(v))?),
+        ("INT2", BindValue::Int(v)) => {
+            query.bind(i16::try_from(*v).map_err(|e| invalid(&e.to_string()))?)
+        }
+        ("INT4", BindValue::Int(v)) => {
+            query.bind(i32::try_from(*v).map_err(|e| invalid(&e.to_string()))?)
+        }
+        ("INT8", BindValue::Int(v)) => query.bind(*v),
+        ("INT2", BindValue::Text(v)) => query.bind(parse::<i16>(v).map_err(|e| invalid(&e))?),
+        ("INT4", BindValue::Text(v)) => query.bind(parse::<i32>(v).map_err(|e| invalid(&e))?),
+        ("INT8", BindValue::Text(v)) => query.bind(parse::<i64>(v).map_err(|e| invalid(&e))?),
+        ("FLOAT4", BindValue::Float(v)) => query.bind(*v as f32),
+        ("FLOAT4", BindValue::Int(v)) => query.bind(*v as f32),
+        ("FLOAT4", BindValue::Text(v)) => query.bind(parse::<f32>(v).map_err(|e| invalid(&e))?),
+        ("FLOAT8", BindValue::Float(v)) => query.bind(*v),
+        ("FLOAT8", BindValue::Int(v)) => query.bind(*v as f64),
+        ("FLOAT8", BindValue::Text(v)) => query.bind(parse::<f64>(v).map_err(|e| invalid(&e))?),
+        ("NUMERIC", BindValue::Int(v)) => query.bind(BigDecimal::from(*v)),
+        ("NUMERIC", BindValue::Float(v)) => {
+            query.bind(parse::<BigDecimal>(&v.to_string()).map_err(|e| invalid(&e))?)
+        }
+        ("NUMERIC", BindValue::Text(v)) => {
+            query.bind(parse::<BigDecimal>(v).map_err(|e| invalid(&e))?)
+        }
+        ("CHAR" | "VARCHAR" | "TEXT" | "NAME" | "UNKNOWN", value) => {
+            query.bind(to_text(value))
+        }
+        ("UUID", BindValue::Text(v)) => {
+            query.bind(Uuid::parse_str(v.trim()).map_err(|e| invalid(&e.to_string()))?)
+        }
+        ("JSON" | "JSONB", BindValue::Json(v)) => query.bind(v.clone()),
+        ("JSON" | "JSONB", BindValue::Text(v)) => query
+            .bind(serde_json::from_str::<JsonValue>(v).map_err(|e| invalid(&e.to_string()))?),
+        ("JSON" | "JSONB", BindValue::Bool(v)) => query.bind(JsonValue::from(*v)),
+        ("JSON" | "JSONB", BindValue::Int(v)) => query.bind(JsonValue::from(*v)),
+        ("JSON" | "JSONB", BindValue::Float(v)) => query.bind(JsonValue::from(*v)),
+        ("DATE", BindValue::Text(v)) => query.bind(
+            Date::parse(v.trim(), format_description!("[year]-[month]-[day]"))
+                .map_err(|e| invalid(&e.to_string()))?,
+        ),
+        ("TIME", BindValue::Text(v)) => query.bind(parse_time(v).map_err(|e| invalid(&e))?),
+        ("TIMESTAMP", BindValue::Text(v)) => {
+            query.bind(parse_timestamp(v).map_err(|e| invalid(&e))?)
+        }
+        ("TIMESTAMPTZ", BindValue::Text(v)) => {
+            query.bind(parse_timestamptz(v).map_err(|e| invalid(&e))?)
+        }
+        ("BYTEA", BindValue::Text(v)) => query.bind(parse_bytea(v).ok_or_else(|| invalid(v))?),
+        (_, value) => {
+            return Err(DbError::Unsupported(format!(
+                "Can't bind {} to parameter ${} of type {}, try casting the placeholder (e.g. ${}::text)",
+                value.kind(),
+                index,
+                param_type,
+                index
+            )))
+        }
+    };
+
+    Ok(query)
+}
+
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value.trim().parse::<T>().map_err(|e| e.to_string())
+}
+
+/// Accepts `HH:MM`, `HH:MM:SS` and `HH:MM:SS.ffffff`
+fn parse_time(value: &str) -> Result<Time, String> {
+    let formats = [
+        format_description!("[hour]:[minute]:[second].[subsecond]"),
+        format_description!("[hour]:[minute]:[second]"),
+        format_description!("[hour]:[minute]"),
+    ];
+
+    formats
+        .iter()
+        .find_map(|format| Time::parse(value.trim(), format).ok())
+        .ok_or_else(|| format!("invalid time `{}`", value))
+}
+
+/// Accepts a date and time separated by a space or `T`, e.g. `2024-01-01 10:00`
+fn parse_timestamp(value: &str) -> Result<PrimitiveDateTime, String> {
+    let (date, time) =
+        split_timestamp(value).ok_or_else(|| format!("invalid timestamp `{}`", value))?;
+    let date = Date::parse(date, format_description!("[year]-[month]-[day]"))
+        .map_err(|e| e.to_string())?;
+    Ok(PrimitiveDateTime::new(date, parse_time(time)?))
+}
+
+/// Accepts RFC 3339 timestamps, with a space allowed instead of `T`. Without an
+/// offset the timestamp is taken as UTC, like Postgres does in a UTC session.
+fn parse_timestamptz(value: &str) -> Result<OffsetDateTime, String> {
+    let value = value.trim();
+    match OffsetDateTime::parse(&value.replacen(' ', "T", 1), &Rfc3339) {
+        Ok(timestamp) => Ok(timestamp),
+        Err(e) => parse_timestamp(value)
+            .map(PrimitiveDateTime::assume_utc)
+            .map_err(|_| e.to_string()),
+    }
+}
+
+/// Splits a timestamp on either a space or `T` between the date and time
+fn split_timestamp(value: &str) -> Option<(&str, &str)> {
+    let value = value.trim();
+    value
+        .split_once('T')
+        .or_else(|| value.split_once(' '))
+        .map(|(date, time)| (date.trim(), time.trim()))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "t" | "true" | "y" | "yes" | "on" | "1" => Some(true),
+        "f" | "false" | "n" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Accepts Postgres hex format (`\xdeadbeef`), anything else is taken as raw bytes
+fn parse_bytea(value: &str) -> Option<Vec<u8>> {
+    let Some(hex) = value.strip_prefix("\\x") else {
+        return Some(value.as_bytes().to_vec());
+    };
+
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_text(value: &BindValue) -> String {
+    match value {
+        BindValue::Null => String::new(),
+        BindValue::Bool(v) => v.to_string(),
+        BindValue::Int(v) => v.to_string(),
+        BindValue::Float(v) => v.to_string(),
+        BindValue::Text(v) => v.clone(),
+        BindValue::Json(v) => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn parses_times_with_optional_seconds() {
+        assert_eq!(parse_time("10:30"), Ok(Time::from_hms(10, 30, 0).unwrap()));
+        assert_eq!(
+            parse_time(" 10:30:15 "),
+            Ok(Time::from_hms(10, 30, 15).unwrap())
+        );
+        assert_eq!(
+            parse_time("10:30:15.25"),
+            Ok(Time::from_hms_milli(10, 30, 15, 250).unwrap())
+        );
+        assert!(parse_time("10h30").is_err());
+    }
+
+    #[test]
+    fn parses_timestamps_with_space_or_t() {
+        assert_eq!(
+            parse_timestamp("2024-01-01 10:00"),
+            Ok(datetime!(2024-01-01 10:00))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01T10:00:05"),
+            Ok(datetime!(2024-01-01 10:00:05))
+        );
+        assert!(parse_timestamp("2024-01-01").is_err());
+    }
+
+    #[test]
+    fn parses_timestamptz_with_offset() {
+        assert_eq!(
+            parse_timestamptz("2024-01-01T10:00:00+02:00"),
+            Ok(datetime!(2024-01-01 10:00 +2))
+        );
+        assert_eq!(
+            parse_timestamptz("2024-01-01 10:00:00Z"),
+            Ok(datetime!(2024-01-01 10:00 UTC))
+        );
+    }
+
+    #[test]
+    fn parses_timestamptz_without_offset_as_utc() {
+        assert_eq!(
+            parse_timestamptz("2024-01-01 10:00"),
+            Ok(datetime!(2024-01-01 10:00 UTC))
+        );
+        assert!(parse_timestamptz("yesterday").is_err());
+    }
+
+    #[test]
+    fn parses_bools_like_postgres() {
+        for value in ["t", "TRUE", "yes", "on", "1"] {
+            assert_eq!(parse_bool(value), Some(true), "{}", value);
+        }
+        for value in ["f", "False", "no", "off", "0"] {
+            assert_eq!(parse_bool(value), Some(false), "{}", value);
+        }
+        assert_eq!(parse_bool("maybe"), None);
+    }
+
+    #[test]
+    fn parses_hex_and_raw_bytea() {
+        assert_eq!(
+            parse_bytea("\\xdeadBEEF"),
+            Some(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(parse_bytea("\\xabc"), None);
+        assert_eq!(parse_bytea("\\xzz"), None);
+        assert_eq!(parse_bytea("raw"), Some(b"raw".to_vec()));
+    }
+
+    #[test]
+    fn converts_values_to_text() {
+        assert_eq!(to_text(&BindValue::Int(42)), "42");
+        assert_eq!(to_text(&BindValue::Bool(true)), "true");
+        assert_eq!(to_text(&BindValue::Text("a".to_string())), "a");
+        assert_eq!(
+            to_text(&BindValue::Json(serde_json::json!({"a": 1}))),
+            r#"{"a":1}"#
+        );
+        assert_eq!(to_text(&BindValue::Null), "");
+    }
+}
@@ -0,0 +1,310 @@
+use serde_json::Value as JsonValue;
+
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::LARGE_SCAN_ROWS,
+    types::{PlanBuffers, PlanNode, PlanWarning, PlanWarningKind, QueryPlan},
+};
+
+/// Estimates off by at least this factor are flagged
+const ESTIMATE_MISMATCH_FACTOR: f64 = 10.0;
+
+/// Mismatches are ignored when both the estimate and actual rows are below this,
+/// as they don't affect the plan much
+const ESTIMATE_MISMATCH_MIN_ROWS: f64 = 100.0;
+
+/// Builds the `EXPLAIN` statement for a query
+pub(super) fn explain_sql(sql: &str, analyze: bool, buffers: bool) -> String {
+    let mut options = vec!["FORMAT JSON"];
+    if analyze {
+        options.push("ANALYZE");
+    }
+    if buffers {
+        options.push("BUFFERS");
+    }
+
+    format!(
+        "EXPLAIN ({}) {}",
+        options.join(", "),
+        sql.trim().trim_end_matches(';')
+    )
+}
+
+/// Parses the output of `EXPLAIN (FORMAT JSON)`, which is an array holding one
+/// object with the plan and, when analyzed, the planning and execution times
+pub(super) fn parse_plan(sql: &str, analyzed: bool, output: &JsonValue) -> DbResult<QueryPlan> {
+    let explained = output
+        .get(0)
+        .ok_or_else(|| DbError::Parsing("EXPLAIN returned an empty plan".to_string()))?;
+    let plan = explained
+        .get("Plan")
+        .ok_or_else(|| DbError::Parsing("EXPLAIN output has no plan".to_string()))?;
+
+    Ok(QueryPlan {
+        query: sql.to_string(),
+        analyzed,
+        planning_time_ms: float(explained, "Planning Time"),
+        execution_time_ms: float(explained, "Execution Time"),
+        root: parse_node(plan),
+    })
+}
+
+fn parse_node(node: &JsonValue) -> PlanNode {
+    let children = node
+        .get("Plans")
+        .and_then(JsonValue::as_array)
+        .map(|plans| plans.iter().map(parse_node).collect())
+        .unwrap_or_default();
+
+    // Only one condition is set depending on the node type
+    let condition = [
+        "Filter",
+        "Index Cond",
+        "Hash Cond",
+        "Merge Cond",
+        "Join Filter",
+        "Recheck Cond",
+    ]
+    .iter()
+    .find_map(|key| text(node, key));
+
+    let buffers = node
+        .get("Shared Hit Blocks")
+        .is_some()
+        .then(|| PlanBuffers {
+            shared_hit: blocks(node, "Shared Hit Blocks"),
+            shared_read: blocks(node, "Shared Read Blocks"),
+            shared_dirtied: blocks(node, "Shared Dirtied Blocks"),
+            shared_written: blocks(node, "Shared Written Blocks"),
+            temp_read: blocks(node, "Temp Read Blocks"),
+            temp_written: blocks(node, "Temp Written Blocks"),
+        });
+
+    let mut plan_node = PlanNode {
+        node_type: text(node, "Node Type").unwrap_or_default(),
+        relation_name: text(node, "Relation Name"),
+        schema: text(node, "Schema"),
+        alias: text(node, "Alias"),
+        index_name: text(node, "Index Name"),
+        join_type: text(node, "Join Type"),
+        condition,
+        startup_cost: float(node, "Startup Cost").unwrap_or_default(),
+        total_cost: float(node, "Total Cost").unwrap_or_default(),
+        plan_rows: float(node, "Plan Rows").unwrap_or_default(),
+        actual_rows: float(node, "Actual Rows"),
+        actual_loops: float(node, "Actual Loops"),
+        actual_startup_time_ms: float(node, "Actual Startup Time"),
+        actual_total_time_ms: float(node, "Actual Total Time"),
+        rows_removed_by_filter: float(node, "Rows Removed by Filter"),
+        buffers,
+        warnings: Vec::new(),
+        children,
+    };
+    plan_node.warnings = detect_warnings(&plan_node);
+    plan_node
+}
+
+fn detect_warnings(node: &PlanNode) -> Vec<PlanWarning> {
+    let mut warnings = Vec::new();
+
+    if node.node_type == "Seq Scan" {
+        // Rows actually read, or the estimate of rows returned when not analyzed
+        let scanned = match node.actual_rows {
+            Some(rows) => {
+                (rows + node.rows_removed_by_filter.unwrap_or(0.0))
+                    * node.actual_loops.unwrap_or(1.0)
+            }
+            None => node.plan_rows,
+        };
+
+        if scanned >= LARGE_SCAN_ROWS {
+            warnings.push(PlanWarning {
+                kind: PlanWarningKind::SeqScanOnLargeTable,
+                message: format!(
+                    "Sequential scan on {} reads about {} rows, consider adding an index",
+                    node.relation_name.as_deref().unwrap_or("a relation"),
+                    scanned.round()
+                ),
+            });
+        }
+    }
+
+    // Both are per loop, so they can be compared directly. Nodes that never ran,
+    // like the unused side of a join, report no rows whatever the estimate.
+    let ran = node.actual_loops != Some(0.0);
+    if let Some(actual) = node.actual_rows.filter(|_| ran) {
+        let estimated = node.plan_rows;
+        let (low, high) = if actual < estimated {
+            (actual, estimated)
+        } else {
+            (estimated, actual)
+        };
+
+        if high >= ESTIMATE_MISMATCH_MIN_ROWS && high >= low.max(1.0) * ESTIMATE_MISMATCH_FACTOR {
+            warnings.push(PlanWarning {
+                kind: PlanWarningKind::RowEstimateMismatch,
+                message: format!(
+                    "Estimated {} rows but got {}, statistics may be out of date (try ANALYZE)",
+                    estimated.round(),
+                    actual.round()
+                ),
+            });
+        }
+    }
+
+    warnings
+}
+
+fn text(node: &JsonValue, key: &str) -> Option<String> {
+    node.get(key)?.as_str().map(str::to_string)
+}
+
+fn float(node: &JsonValue, key: &str) -> Option<f64> {
+    node.get(key)?.as_f64()
+}
+
+fn blocks(node: &JsonValue, key: &str) -> u64 {
+    node.get(key).and_then(JsonValue::as_u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kinds(node: &PlanNode) -> Vec<PlanWarningKind> {
+        node.warnings.iter().map(|warning| warning.kind).collect()
+    }
+
+    #[test]
+    fn builds_explain_options() {
+        assert_eq!(
+            explain_sql("SELECT 1;", false, false),
+            "EXPLAIN (FORMAT JSON) SELECT 1"
+        );
+        assert_eq!(
+            explain_sql("SELECT 1", true, true),
+            "EXPLAIN (FORMAT JSON, ANALYZE, BUFFERS) SELECT 1"
+        );
+    }
+
+    #[test]
+    fn parses_nested_plans_with_timings() {
+        let output = json!([{
+            "Plan": {
+                "Node Type": "Hash Join",
+                "Join Type": "Inner",
+                "Hash Cond": "(a.id = b.a_id)",
+                "Startup Cost": 1.5,
+                "Total Cost": 20.25,
+                "Plan Rows": 10,
+                "Plans": [
+                    {"Node Type": "Seq Scan", "Relation Name": "a", "Schema": "public", "Alias": "a", "Plan Rows": 10},
+                    {"Node Type": "Index Scan", "Relation Name": "b", "Index Name": "b_pkey", "Plan Rows": 1}
+                ]
+            },
+            "Planning Time": 0.1,
+            "Execution Time": 2.5
+        }]);
+
+        let plan = parse_plan("SELECT", true, &output).unwrap();
+        assert_eq!(plan.planning_time_ms, Some(0.1));
+        assert_eq!(plan.execution_time_ms, Some(2.5));
+        assert_eq!(plan.root.node_type, "Hash Join");
+        assert_eq!(plan.root.condition.as_deref(), Some("(a.id = b.a_id)"));
+        assert_eq!(plan.root.total_cost, 20.25);
+        assert_eq!(plan.root.children.len(), 2);
+        assert_eq!(plan.root.children[0].schema.as_deref(), Some("public"));
+        assert_eq!(plan.root.children[1].index_name.as_deref(), Some("b_pkey"));
+        assert!(plan.root.buffers.is_none());
+    }
+
+    #[test]
+    fn rejects_output_without_a_plan() {
+        assert!(parse_plan("SELECT", false, &json!([])).is_err());
+        assert!(parse_plan("SELECT", false, &json!([{}])).is_err());
+    }
+
+    #[test]
+    fn reads_buffer_counts() {
+        let output = json!([{"Plan": {
+            "Node Type": "Seq Scan",
+            "Shared Hit Blocks": 4,
+            "Shared Read Blocks": 2
+        }}]);
+
+        let buffers = parse_plan("SELECT", true, &output)
+            .unwrap()
+            .root
+            .buffers
+            .unwrap();
+        assert_eq!(buffers.shared_hit, 4);
+        assert_eq!(buffers.shared_read, 2);
+        assert_eq!(buffers.temp_written, 0);
+    }
+
+    #[test]
+    fn flags_large_sequential_scans() {
+        let estimated = json!([{"Plan": {"Node Type": "Seq Scan", "Plan Rows": 50000}}]);
+        let plan = parse_plan("SELECT", false, &estimated).unwrap();
+        assert_eq!(
+            kinds(&plan.root),
+            vec![PlanWarningKind::SeqScanOnLargeTable]
+        );
+
+        // Filtered rows were read too, in every loop
+        let analyzed = json!([{"Plan": {
+            "Node Type": "Seq Scan",
+            "Plan Rows": 10,
+            "Actual Rows": 10,
+            "Actual Loops": 100,
+            "Rows Removed by Filter": 90
+        }}]);
+        let plan = parse_plan("SELECT", true, &analyzed).unwrap();
+        assert_eq!(
+            kinds(&plan.root),
+            vec![PlanWarningKind::SeqScanOnLargeTable]
+        );
+
+        let small = json!([{"Plan": {"Node Type": "Seq Scan", "Plan Rows": 500}}]);
+        let plan = parse_plan("SELECT", false, &small).unwrap();
+        assert!(plan.root.warnings.is_empty());
+    }
+
+    #[test]
+    fn flags_row_estimate_mismatches() {
+        let output = json!([{"Plan": {
+            "Node Type": "Index Scan",
+            "Plan Rows": 5,
+            "Actual Rows": 5000,
+            "Actual Loops": 1
+        }}]);
+        let plan = parse_plan("SELECT", true, &output).unwrap();
+        assert_eq!(
+            kinds(&plan.root),
+            vec![PlanWarningKind::RowEstimateMismatch]
+        );
+
+        // Small row counts don't matter much to the plan
+        let small = json!([{"Plan": {
+            "Node Type": "Index Scan",
+            "Plan Rows": 1,
+            "Actual Rows": 50,
+            "Actual Loops": 1
+        }}]);
+        let plan = parse_plan("SELECT", true, &small).unwrap();
+        assert!(plan.root.warnings.is_empty());
+    }
+
+    #[test]
+    fn skips_mismatches_for_nodes_that_never_ran() {
+        let output = json!([{"Plan": {
+            "Node Type": "Index Scan",
+            "Plan Rows": 5000,
+            "Actual Rows": 0,
+            "Actual Loops": 0
+        }}]);
+        let plan = parse_plan("SELECT", true, &output).unwrap();
+        assert!(plan.root.warnings.is_empty());
+    }
+}
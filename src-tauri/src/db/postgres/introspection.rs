@@ -0,0 +1,327 @@
+use sqlx::{postgres::types::Oid, PgConnection, Pool, Postgres, Row};
+
+use super::run_query_with;
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::{quote_ident, FOLLOW_REFERENCE_LIMIT},
+    types::{
+        ConstraintKind, ForeignKey, QueryResult, RelationColumn, RelationConstraint,
+        RelationDetails,
+    },
+};
+
+/// Parses an entity ID, which is the object's OID as text
+pub(super) fn parse_oid(id: &str) -> DbResult<Oid> {
+    id.parse::<u32>()
+        .map(Oid)
+        .map_err(|_| DbError::NotFound(format!("Invalid entity ID: {}", id)))
+}
+
+/// Describes a relation's columns, constraints and foreign keys from the catalog
+pub(super) async fn describe_relation(
+    pool: &Pool<Postgres>,
+    id: &str,
+) -> DbResult<RelationDetails> {
+    let oid = parse_oid(id)?;
+
+    let relation_query = r#"
+        SELECT
+            c.relname AS name,
+            n.nspname AS schema,
+            c.relkind::TEXT AS kind,
+            obj_description(c.oid, 'pg_class') AS comment
+        FROM pg_class c
+        JOIN pg_namespace n ON c.relnamespace = n.oid
+        WHERE c.oid = $1
+    "#;
+
+    let relation = sqlx::query(relation_query)
+        .bind(oid)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("Relation {} not found", id)))?;
+
+    let kind: String = relation.get("kind");
+
+    Ok(RelationDetails {
+        id: id.to_string(),
+        name: relation.get("name"),
+        schema: relation.get("schema"),
+        kind: relation_kind(&kind).to_string(),
+        comment: relation.get("comment"),
+        columns: fetch_columns(pool, oid).await?,
+        constraints: fetch_constraints(pool, oid).await?,
+        foreign_keys: fetch_foreign_keys(pool, Some(oid)).await?,
+    })
+}
+
+async fn fetch_columns(pool: &Pool<Postgres>, oid: Oid) -> DbResult<Vec<RelationColumn>> {
+    let column_query = r#"
+        SELECT
+            a.attnum::INT4 AS position,
+            a.attname AS name,
+            format_type(a.atttypid, a.atttypmod) AS data_type,
+            CASE WHEN t.typcategory = 'A' THEN format_type(t.typelem, NULL) END AS element_type,
+            CASE WHEN t.typtype = 'd' THEN format_type(t.typbasetype, t.typtypmod) END
+                AS domain_base_type,
+            CASE WHEN t.typtype = 'e' THEN ARRAY(
+                SELECT e.enumlabel::TEXT
+                FROM pg_enum e
+                WHERE e.enumtypid = t.oid
+                ORDER BY e.enumsortorder
+            ) END AS enum_values,
+            NOT a.attnotnull AS nullable,
+            EXISTS (
+                SELECT 1
+                FROM pg_constraint pk
+                WHERE pk.conrelid = a.attrelid
+                  AND pk.contype = 'p'
+                  AND a.attnum = ANY(pk.conkey)
+            ) AS primary_key,
+            pg_get_expr(ad.adbin, ad.adrelid) AS default_expr,
+            a.attgenerated = 's' AS is_generated,
+            CASE a.attidentity
+                WHEN 'a' THEN 'ALWAYS'
+                WHEN 'd' THEN 'BY DEFAULT'
+            END AS identity,
+            col_description(a.attrelid, a.attnum) AS comment
+        FROM pg_attribute a
+        JOIN pg_type t ON t.oid = a.atttypid
+        LEFT JOIN pg_attrdef ad ON ad.adrelid = a.attrelid AND ad.adnum = a.attnum
+        WHERE a.attrelid = $1
+          AND a.attnum > 0
+          AND NOT a.attisdropped
+        ORDER BY a.attnum
+    "#;
+
+    let rows = sqlx::query(column_query).bind(oid).fetch_all(pool).await?;
+    let columns = rows
+        .into_iter()
+        .map(|row| {
+            // Generated columns keep their expression in pg_attrdef too
+            let default_expr: Option<String> = row.get("default_expr");
+            let (default_value, generated) = if row.get::<bool, _>("is_generated") {
+                (None, default_expr)
+            } else {
+                (default_expr, None)
+            };
+
+            RelationColumn {
+                position: row.get("position"),
+                name: row.get("name"),
+                data_type: row.get("data_type"),
+                element_type: row.get("element_type"),
+                domain_base_type: row.get("domain_base_type"),
+                enum_values: row.get("enum_values"),
+                nullable: row.get("nullable"),
+                primary_key: row.get("primary_key"),
+                default_value,
+                generated,
+                identity: row.get("identity"),
+                comment: row.get("comment"),
+            }
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+async fn fetch_constraints(pool: &Pool<Postgres>, oid: Oid) -> DbResult<Vec<RelationConstraint>> {
+    let constraint_query = r#"
+        SELECT
+            con.conname AS name,
+            con.contype::TEXT AS kind,
+            ARRAY(
+                SELECT a.attname::TEXT
+                FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord)
+                JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                ORDER BY k.ord
+            ) AS columns,
+            pg_get_constraintdef(con.oid) AS definition
+        FROM pg_constraint con
+        WHERE con.conrelid = $1
+          AND con.contype IN ('p', 'u', 'c', 'x')
+        ORDER BY con.contype = 'p' DESC, con.conname
+    "#;
+
+    let rows = sqlx::query(constraint_query)
+        .bind(oid)
+        .fetch_all(pool)
+        .await?;
+
+    let mut constraints = Vec::with_capacity(rows.len());
+    for row in rows {
+        let kind: String = row.get("kind");
+        let kind = match kind.as_str() {
+            "p" => ConstraintKind::PrimaryKey,
+            "u" => ConstraintKind::Unique,
+            "c" => ConstraintKind::Check,
+            "x" => ConstraintKind::Exclusion,
+            _ => continue,
+        };
+
+        constraints.push(RelationConstraint {
+            name: row.get("name"),
+            kind,
+            columns: row.get("columns"),
+            definition: row.get("definition"),
+        });
+    }
+
+    Ok(constraints)
+}
+
+/// Returns every foreign key in the database, which together form the graph of
+/// references between tables. Table IDs match the IDs of `DbEntity::Table`.
+pub(super) async fn foreign_key_graph(pool: &Pool<Postgres>) -> DbResult<Vec<ForeignKey>> {
+    fetch_foreign_keys(pool, None).await
+}
+
+/// Fetches the foreign keys of one relation, or of all relations if `oid` is `None`
+async fn fetch_foreign_keys(pool: &Pool<Postgres>, oid: Option<Oid>) -> DbResult<Vec<ForeignKey>> {
+    let foreign_key_query = r#"
+        SELECT
+            con.oid::TEXT AS id,
+            con.conname AS name,
+            con.conrelid::TEXT AS table_id,
+            ARRAY(
+                SELECT a.attname::TEXT
+                FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord)
+                JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                ORDER BY k.ord
+            ) AS columns,
+            con.confrelid::TEXT AS referenced_table_id,
+            rn.nspname AS referenced_schema,
+            rc.relname AS referenced_table,
+            ARRAY(
+                SELECT a.attname::TEXT
+                FROM unnest(con.confkey) WITH ORDINALITY AS k(attnum, ord)
+                JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum
+                ORDER BY k.ord
+            ) AS referenced_columns,
+            con.confupdtype::TEXT AS on_update,
+            con.confdeltype::TEXT AS on_delete,
+            con.condeferrable AS deferrable,
+            con.condeferred AS initially_deferred,
+            pg_get_constraintdef(con.oid) AS definition
+        FROM pg_constraint con
+        JOIN pg_class rc ON rc.oid = con.confrelid
+        JOIN pg_namespace rn ON rc.relnamespace = rn.oid
+        WHERE con.contype = 'f'
+          AND CASE
+              -- Partitions inherit their parent's foreign keys, the graph only needs the parent's
+              WHEN $1::OID IS NULL THEN con.conparentid = 0
+              ELSE con.conrelid = $1
+          END
+        ORDER BY con.conname
+    "#;
+
+    let rows = sqlx::query(foreign_key_query)
+        .bind(oid)
+        .fetch_all(pool)
+        .await?;
+
+    let foreign_keys = rows
+        .into_iter()
+        .map(|row| {
+            let on_update: String = row.get("on_update");
+            let on_delete: String = row.get("on_delete");
+
+            ForeignKey {
+                id: row.get("id"),
+                name: row.get("name"),
+                table_id: row.get("table_id"),
+                columns: row.get("columns"),
+                referenced_table_id: row.get("referenced_table_id"),
+                referenced_schema: row.get("referenced_schema"),
+                referenced_table: row.get("referenced_table"),
+                referenced_columns: row.get("referenced_columns"),
+                on_update: foreign_key_action(&on_update).to_string(),
+                on_delete: foreign_key_action(&on_delete).to_string(),
+                deferrable: row.get("deferrable"),
+                initially_deferred: row.get("initially_deferred"),
+                definition: row.get("definition"),
+            }
+        })
+        .collect();
+
+    Ok(foreign_keys)
+}
+
+/// Fetches the rows referenced by `value` in a foreign key `column` of a table.
+///
+/// If the column is part of a composite foreign key only that column is matched,
+/// so this can return more than one row.
+pub(super) async fn follow_reference(
+    conn: &mut PgConnection,
+    table_id: &str,
+    column: &str,
+    value: &str,
+) -> DbResult<QueryResult> {
+    let oid = parse_oid(table_id)?;
+
+    // Single column keys are preferred when a column is part of several
+    let reference_query = r#"
+        SELECT
+            rn.nspname AS schema,
+            rc.relname AS table,
+            ra.attname AS column,
+            format_type(ra.atttypid, ra.atttypmod) AS data_type
+        FROM pg_constraint con
+        JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attname = $2
+        CROSS JOIN LATERAL unnest(con.conkey, con.confkey) AS k(attnum, ref_attnum)
+        JOIN pg_attribute ra ON ra.attrelid = con.confrelid AND ra.attnum = k.ref_attnum
+        JOIN pg_class rc ON rc.oid = con.confrelid
+        JOIN pg_namespace rn ON rc.relnamespace = rn.oid
+        WHERE con.conrelid = $1
+          AND con.contype = 'f'
+          AND k.attnum = a.attnum
+        ORDER BY array_length(con.conkey, 1), con.conname
+        LIMIT 1
+    "#;
+
+    let reference = sqlx::query(reference_query)
+        .bind(oid)
+        .bind(column)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| {
+            DbError::NotFound(format!("Column {} doesn't reference another table", column))
+        })?;
+
+    let data_type: String = reference.get("data_type");
+    let sql = format!(
+        "SELECT * FROM {}.{} WHERE {} = CAST($1::TEXT AS {}) LIMIT {}",
+        quote_ident(reference.get("schema")),
+        quote_ident(reference.get("table")),
+        quote_ident(reference.get("column")),
+        data_type,
+        FOLLOW_REFERENCE_LIMIT
+    );
+
+    run_query_with(conn, sqlx::query(&sql).bind(value), 0).await
+}
+
+fn relation_kind(relkind: &str) -> &'static str {
+    match relkind {
+        "r" => "table",
+        "p" => "partitioned table",
+        "v" => "view",
+        "m" => "materialized view",
+        "f" => "foreign table",
+        "S" => "sequence",
+        "c" => "composite type",
+        "i" | "I" => "index",
+        _ => "relation",
+    }
+}
+
+fn foreign_key_action(action: &str) -> &'static str {
+    match action {
+        "r" => "RESTRICT",
+        "c" => "CASCADE",
+        "n" => "SET NULL",
+        "d" => "SET DEFAULT",
+        _ => "NO ACTION",
+    }
+}
@@ -1,36 +1,242 @@
+mod cursor;
+mod ddl;
+pub(crate) mod decode;
+mod encode;
+mod explain;
+mod introspection;
+#[cfg(test)]
+pub(crate) mod testing;
+
 use async_trait::async_trait;
-use serde_json::Value;
+use futures_util::TryStreamExt;
 use sqlx::{
-    postgres::{PgPoolOptions, PgRow},
-    Column, Pool, Postgres, Row as SqlxRow,
+    postgres::{PgArguments, PgConnectOptions, PgConnection, PgSslMode},
+    query::Query,
+    Connection, Either, Execute, Executor, Pool, Postgres, Row as SqlxRow, Statement,
 };
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
 
+use self::cursor::Cursor;
 use crate::db::{
-    client::DatabaseClient,
-    errors::{DbError, DbResult},
-    types::{ColumnDefinition, DbEntity, QueryResult, Row, SchemaEntity, SchemaLevelEntity},
+    client::{BatchSink, DatabaseClient},
+    errors::{connection_error, DbError, DbResult},
+    helpers::{column_definitions, quote_ident},
+    pool::{PoolSettings, DEFAULT_APPLICATION_NAME},
+    schema::SchemaTracker,
+    script::{leading_keyword, split_statements},
+    session::{transaction_change, QueryConnection, Session, TransactionChange},
+    tls::{SslMode, TlsSettings},
+    tunnel::{SshSettings, SshTunnel},
+    types::{
+        BindValue, ColumnDefinition, DbCapabilities, DbEntity, DbExtension, Dialect, EntityChanges,
+        ExplainFormat, ForeignKey, GlobalTrigger, ParameterDefinition, QueryPlan, QueryResult,
+        RelationDetails, SchemaEntity, SchemaLevelEntity, ScriptErrorMode, ServerInfo,
+        StatementDescription, TableLevelEntity, TransactionState,
+    },
 };
 
 pub struct PostgresClient {
     connection_string: String,
     pool: Option<Pool<Postgres>>,
+    pool_settings: PoolSettings,
+    ssh: Option<SshSettings>,
+    tls: Option<TlsSettings>,
+    /// Port forward to the server while connected through an SSH bastion
+    tunnel: Option<SshTunnel>,
+    /// Queries currently running, keyed by their query ID
+    running_queries: Mutex<HashMap<String, RunningQuery>>,
+    /// Open cursors, keyed by the query ID they were opened with
+    cursors: Mutex<HashMap<String, Arc<AsyncMutex<Cursor>>>>,
+    /// Connection pinned while an explicit transaction is open
+    session: Session<Postgres>,
+    schema_tracker: SchemaTracker,
+}
+
+/// Hashes the defining columns of every catalog row that makes up an entity,
+/// so renames and redefinitions change it but vacuums and stats updates don't.
+/// Temporary and TOAST objects come and go with sessions and are left out.
+const FINGERPRINT_QUERY: &str = r#"
+    WITH ns AS (
+        SELECT oid, nspname FROM pg_namespace
+        WHERE nspname NOT LIKE 'pg_toast%' AND nspname NOT LIKE 'pg_temp%'
+    ),
+    rel AS (
+        SELECT c.oid, c.relname, c.relnamespace, c.relkind, c.relnatts, c.relowner
+        FROM pg_class c
+        JOIN ns ON ns.oid = c.relnamespace
+        WHERE c.relpersistence <> 't'
+    )
+    SELECT md5(concat_ws('|',
+        (SELECT string_agg(ROW(oid, nspname)::TEXT, ',' ORDER BY oid) FROM ns),
+        (SELECT string_agg(ROW(rel.*)::TEXT, ',' ORDER BY oid) FROM rel),
+        (SELECT string_agg(ROW(a.attrelid, a.attnum, a.attname, a.atttypid, a.atttypmod,
+                               a.attnotnull, a.attisdropped)::TEXT, ','
+                           ORDER BY a.attrelid, a.attnum)
+         FROM pg_attribute a JOIN rel ON rel.oid = a.attrelid
+         WHERE a.attnum > 0),
+        (SELECT string_agg(ROW(p.oid, p.proname, p.pronamespace, p.prokind, p.proargtypes,
+                               p.prorettype, md5(p.prosrc))::TEXT, ',' ORDER BY p.oid)
+         FROM pg_proc p JOIN ns ON ns.oid = p.pronamespace),
+        (SELECT string_agg(ROW(t.oid, t.typname, t.typnamespace, t.typtype)::TEXT, ','
+                           ORDER BY t.oid)
+         FROM pg_type t JOIN ns ON ns.oid = t.typnamespace
+         WHERE t.typrelid = 0 OR t.typrelid IN (SELECT oid FROM rel)),
+        (SELECT string_agg(ROW(t.oid, t.tgname, t.tgrelid, t.tgenabled, t.tgfoid)::TEXT, ','
+                           ORDER BY t.oid)
+         FROM pg_trigger t JOIN rel ON rel.oid = t.tgrelid),
+        (SELECT string_agg(ROW(c.oid, c.conname, c.connamespace, c.contype, c.conrelid,
+                               c.conkey, c.confrelid, c.confkey)::TEXT, ',' ORDER BY c.oid)
+         FROM pg_constraint c JOIN ns ON ns.oid = c.connamespace
+         WHERE c.conrelid = 0 OR c.conrelid IN (SELECT oid FROM rel)),
+        (SELECT string_agg(ROW(oid, extname, extversion)::TEXT, ',' ORDER BY oid)
+         FROM pg_extension),
+        (SELECT string_agg(ROW(oid, evtname, evtevent, evtenabled)::TEXT, ',' ORDER BY oid)
+         FROM pg_event_trigger)
+    ))
+"#;
+
+struct RunningQuery {
+    /// PID of the backend the query is running on
+    backend_pid: i32,
+    /// Whether the user asked to cancel this query
+    cancelled: bool,
 }
 
 impl PostgresClient {
-    pub fn new(connection_string: &str) -> DbResult<Self> {
+    pub fn new(
+        connection_string: &str,
+        pool_settings: PoolSettings,
+        ssh: Option<SshSettings>,
+        tls: Option<TlsSettings>,
+    ) -> DbResult<Self> {
         Ok(Self {
             connection_string: connection_string.to_string(),
             pool: None,
+            pool_settings,
+            ssh,
+            tls,
+            tunnel: None,
+            running_queries: Mutex::new(HashMap::new()),
+            cursors: Mutex::new(HashMap::new()),
+            session: Session::new(),
+            schema_tracker: SchemaTracker::default(),
         })
     }
 
+    /// Options for connecting directly to the server, with the project's TLS
+    /// settings and application name applied over the connection string's
+    fn connect_options(&self) -> DbResult<PgConnectOptions> {
+        let mut options = PgConnectOptions::from_str(&self.connection_string)?;
+
+        // Sessions are tagged in pg_stat_activity, unless the connection string already names them
+        let application_name = match &self.pool_settings.application_name {
+            Some(name) => Some(name.as_str()),
+            None if options.get_application_name().is_none() => Some(DEFAULT_APPLICATION_NAME),
+            None => None,
+        };
+        if let Some(name) = application_name {
+            options = options.application_name(name);
+        }
+
+        let Some(tls) = &self.tls else {
+            return Ok(options);
+        };
+        if let Some(ssl_mode) = tls.ssl_mode {
+            options = options.ssl_mode(match ssl_mode {
+                SslMode::Disable => PgSslMode::Disable,
+                SslMode::Allow => PgSslMode::Allow,
+                SslMode::Prefer => PgSslMode::Prefer,
+                SslMode::Require => PgSslMode::Require,
+                SslMode::VerifyCa => PgSslMode::VerifyCa,
+                SslMode::VerifyFull => PgSslMode::VerifyFull,
+            });
+        }
+        if let Some(path) = &tls.root_cert_path {
+            options = options.ssl_root_cert(path);
+        }
+        if let Some(path) = &tls.client_cert_path {
+            options = options.ssl_client_cert(path);
+        }
+        if let Some(path) = &tls.client_key_path {
+            options = options.ssl_client_key(path);
+        }
+        Ok(options)
+    }
+
+    /// Opens a connection outside the pool, through the SSH tunnel if there is
+    /// one. Cancels go through it since every pooled connection may be busy.
+    async fn connect_once(&self) -> DbResult<PgConnection> {
+        self.get_pool()?;
+        let mut options = self.connect_options()?;
+        if let Some(tunnel) = &self.tunnel {
+            options = options.host("127.0.0.1").port(tunnel.local_port());
+        }
+        Ok(PgConnection::connect_with(&options).await?)
+    }
+
     // This function gets the pool or returns an error if not connected
     fn get_pool(&self) -> DbResult<&Pool<Postgres>> {
         self.pool
             .as_ref()
             .ok_or_else(|| DbError::Connection("Database client is not connected".to_string()))
     }
+
+    /// Registers the backend a query is about to run on, so it can be cancelled
+    async fn start_query(&self, query_id: &str, conn: &mut PgConnection) -> DbResult<()> {
+        let backend_pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+            .fetch_one(&mut *conn)
+            .await?;
+
+        self.running_queries.lock().unwrap().insert(
+            query_id.to_string(),
+            RunningQuery {
+                backend_pid,
+                cancelled: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn is_cancelled(&self, query_id: &str) -> bool {
+        self.running_queries
+            .lock()
+            .unwrap()
+            .get(query_id)
+            .is_some_and(|query| query.cancelled)
+    }
+
+    fn finish_query(&self, query_id: &str) {
+        self.running_queries.lock().unwrap().remove(query_id);
+    }
+
+    /// Closes every open cursor so their connections go back to the pool
+    async fn close_cursors(&self) {
+        let cursors: Vec<_> = self.cursors.lock().unwrap().drain().collect();
+        for (query_id, cursor) in cursors {
+            self.finish_query(&query_id);
+            if let Err(e) = cursor.lock().await.close(&self.session).await {
+                log::warn!("Failed to close cursor for query {}: {}", query_id, e);
+            }
+        }
+    }
+
+    /// Gets the session connection if a transaction is open, otherwise one from the pool
+    async fn connection(&self) -> DbResult<QueryConnection<'_, Postgres>> {
+        self.session.connection(self.get_pool()?).await
+    }
+
+    /// Reports errors caused by the user cancelling a query as `DbError::Cancelled`
+    fn cancelled_or(&self, query_id: &str, error: DbError) -> DbError {
+        if self.is_cancelled(query_id) {
+            DbError::Cancelled
+        } else {
+            error
+        }
+    }
 }
 
 #[async_trait]
@@ -39,6 +245,22 @@ impl DatabaseClient for PostgresClient {
         self.connection_string.clone()
     }
 
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn capabilities(&self) -> DbCapabilities {
+        DbCapabilities {
+            transactions: true,
+            savepoints: true,
+            cancellation: true,
+            query_plans: true,
+            analyzed_plans: true,
+            explain_formats: vec![ExplainFormat::Json],
+            ..self.dialect().capabilities()
+        }
+    }
+
     async fn is_connected(&self) -> DbResult<bool> {
         match self.get_pool() {
             Ok(pool) => Ok(!pool.is_closed()),
@@ -52,28 +274,97 @@ impl DatabaseClient for PostgresClient {
         Ok(())
     }
 
+    async fn probe_server(&self) -> DbResult<ServerInfo> {
+        let mut conn = PgConnection::connect_with(&self.connect_options()?)
+            .await
+            .map_err(connection_error)?;
+        let info = fetch_server_info(&mut conn).await;
+        let _ = conn.close().await;
+        info
+    }
+
+    async fn server_info(&self) -> DbResult<ServerInfo> {
+        let mut conn = self.get_pool()?.acquire().await?;
+        fetch_server_info(&mut conn).await
+    }
+
     async fn connect(&mut self) -> DbResult<()> {
         // Check if already connected
         if let Ok(true) = self.is_connected().await {
             return Ok(());
         }
 
-        // Create a new pool
-        let pool = PgPoolOptions::new()
-            .max_connections(10)
-            .connect(&self.connection_string)
-            .await?;
+        let settings = &self.pool_settings;
+        let mut options = self.connect_options()?;
 
-        self.pool = Some(pool);
-        Ok(())
+        // Connect to a local port the bastion forwards to the server. With
+        // `sslmode=verify-full` the certificate is checked against 127.0.0.1, so
+        // `verify-ca` is needed instead.
+        if let Some(ssh) = &self.ssh {
+            if options.get_socket().is_some() {
+                return Err(DbError::Config(
+                    "A Unix socket can't be reached through an SSH tunnel".to_string(),
+                ));
+            }
+            if let Some(tunnel) = self.tunnel.take() {
+                tunnel.close().await;
+            }
+
+            let tunnel = SshTunnel::open(ssh, options.get_host(), options.get_port()).await?;
+            options = options.host("127.0.0.1").port(tunnel.local_port());
+            self.tunnel = Some(tunnel);
+        }
+
+        // Session settings are applied to every connection the pool opens
+        let session_settings = Arc::new(session_settings(settings));
+        let pool = settings
+            .pool_options::<Postgres>()
+            .after_connect(move |conn, _meta| {
+                let session_settings = session_settings.clone();
+                Box::pin(async move {
+                    for (name, value) in session_settings.iter() {
+                        sqlx::query("SELECT set_config($1, $2, false)")
+                            .bind(*name)
+                            .bind(value.as_str())
+                            .execute(&mut *conn)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with(options)
+            .await;
+
+        match pool {
+            Ok(pool) => {
+                self.pool = Some(pool);
+                Ok(())
+            }
+            Err(e) => {
+                if let Some(tunnel) = self.tunnel.take() {
+                    tunnel.close().await;
+                }
+                Err(connection_error(e))
+            }
+        }
     }
 
     async fn disconnect(&mut self) -> DbResult<()> {
+        // The pool waits for every connection to be returned before closing,
+        // including the ones held by open cursors
+        self.close_cursors().await;
+        self.session.release();
+
         if let Ok(true) = self.is_connected().await {
             if let Some(pool) = self.pool.take() {
                 pool.close().await;
             }
         }
+
+        // Only torn down once the pool's connections through it are closed
+        if let Some(tunnel) = self.tunnel.take() {
+            tunnel.close().await;
+        }
         Ok(())
     }
 
@@ -88,65 +379,325 @@ impl DatabaseClient for PostgresClient {
         self.connect().await
     }
 
-    async fn execute_query(&self, sql: &str) -> DbResult<QueryResult> {
-        let pool = self.get_pool()?;
-        let rows = sqlx::query(sql).fetch_all(pool).await?;
-
-        if rows.is_empty() {
-            return Ok(QueryResult {
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs(),
-                query: sql.to_string(),
-                rows_affected: None,
-                execution_time_ms: 0,
-                columns: Vec::new(),
-                rows: Vec::new(),
-                warnings: Vec::new(),
-                result_index: 0,
-            });
+    async fn execute_query(&self, query_id: &str, sql: &str) -> DbResult<QueryResult> {
+        let mut conn = self.connection().await?;
+
+        self.start_query(query_id, &mut conn).await?;
+        let result = run_query(&mut conn, sql, 0)
+            .await
+            .map_err(|e| self.cancelled_or(query_id, e));
+        self.finish_query(query_id);
+
+        let open = match (&result, transaction_change(sql)) {
+            (Ok(_), Some(change)) => change == TransactionChange::Begin,
+            _ => conn.is_session(),
+        };
+        self.session.finish(conn, open).await;
+        result
+    }
+
+    async fn execute_query_with_params(
+        &self,
+        query_id: &str,
+        sql: &str,
+        params: &[BindValue],
+    ) -> DbResult<QueryResult> {
+        let mut conn = self.connection().await?;
+
+        self.start_query(query_id, &mut conn).await?;
+        let result = run_query_with_params(&mut conn, sql, params)
+            .await
+            .map_err(|e| self.cancelled_or(query_id, e));
+        self.finish_query(query_id);
+
+        let open = match (&result, transaction_change(sql)) {
+            (Ok(_), Some(change)) => change == TransactionChange::Begin,
+            _ => conn.is_session(),
+        };
+        self.session.finish(conn, open).await;
+        result
+    }
+
+    async fn describe_statement(&self, sql: &str) -> DbResult<StatementDescription> {
+        let mut conn = self.connection().await?;
+        let statement = (&mut *conn).prepare(sql).await?;
+
+        let parameters = match statement.parameters() {
+            Some(Either::Left(types)) => types
+                .iter()
+                .enumerate()
+                .map(|(i, param_type)| ParameterDefinition {
+                    index: i + 1,
+                    data_type: param_type.to_string(),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(StatementDescription {
+            parameters,
+            columns: column_definitions(statement.columns()),
+        })
+    }
+
+    async fn explain_query(&self, sql: &str, analyze: bool, buffers: bool) -> DbResult<QueryPlan> {
+        let mut conn = self.connection().await?;
+
+        // EXPLAIN ANALYZE runs the statement, so anything it changes is rolled back
+        let (start, undo) = if conn.is_session() {
+            (
+                "SAVEPOINT sqratch_explain",
+                "ROLLBACK TO SAVEPOINT sqratch_explain",
+            )
+        } else {
+            ("BEGIN", "ROLLBACK")
+        };
+
+        if analyze {
+            (&mut *conn).execute(start).await?;
+        }
+        let output = sqlx::query_scalar::<_, serde_json::Value>(&explain::explain_sql(
+            sql, analyze, buffers,
+        ))
+        .fetch_one(&mut *conn)
+        .await;
+        if analyze {
+            (&mut *conn).execute(undo).await?;
         }
 
-        let pg_row: &PgRow = rows.first().unwrap();
-        let columns = pg_row
-            .columns()
-            .iter()
-            .map(|col| ColumnDefinition {
-                name: col.name().to_string(),
-                data_type: col.type_info().to_string(),
-                nullable: true,      // Default to true since we can't easily determine
-                primary_key: false,  // Cannot determine from result alone
-                default_value: None, // Cannot determine from result alone
-            })
-            .collect();
-
-        let mut result_rows = Vec::new();
-        for row in rows {
-            let mut values = HashMap::new();
-            for (i, col) in row.columns().iter().enumerate() {
-                let value: Option<Value> = row.try_get(i)?;
-                values.insert(
-                    col.name().to_string(),
-                    value.map_or_else(|| "NULL".to_string(), |v| v.to_string()),
-                );
+        explain::parse_plan(sql, analyze, &output?)
+    }
+
+    async fn execute_script(
+        &self,
+        query_id: &str,
+        sql: &str,
+        on_error: ScriptErrorMode,
+    ) -> DbResult<Vec<QueryResult>> {
+        // Run every statement on the same connection so session state carries over
+        let mut conn = self.connection().await?;
+        self.start_query(query_id, &mut conn).await?;
+        let mut open = conn.is_session();
+        let mut results = Vec::new();
+
+        for (i, statement) in split_statements(sql).into_iter().enumerate() {
+            // A cancel between statements finds the connection idle, so nothing
+            // interrupts the next statement unless it's checked for here
+            if self.is_cancelled(query_id) {
+                let mut result = QueryResult::new(statement, i);
+                result.error = Some(DbError::Cancelled.to_string());
+                results.push(result);
+                break;
             }
-            result_rows.push(Row { values });
-        }
-
-        Ok(QueryResult {
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs(),
-            query: sql.to_string(),
-            rows_affected: None,
-            execution_time_ms: 0,
-            columns,
-            rows: result_rows,
-            warnings: Vec::new(),
-            result_index: 0,
-        })
+
+            let started = Instant::now();
+            match run_query(&mut conn, statement, i).await {
+                Ok(result) => {
+                    if let Some(change) = transaction_change(statement) {
+                        open = change == TransactionChange::Begin;
+                    }
+                    results.push(result);
+                }
+                Err(e) => {
+                    // A cancelled script never runs its remaining statements
+                    let cancelled = self.is_cancelled(query_id);
+                    let mut result = QueryResult::new(statement, i);
+                    result.execution_time_ms = started.elapsed().as_millis() as u64;
+                    result.error = Some(if cancelled {
+                        DbError::Cancelled.to_string()
+                    } else {
+                        e.to_string()
+                    });
+                    results.push(result);
+
+                    if cancelled || on_error == ScriptErrorMode::Stop {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.finish_query(query_id);
+        self.session.finish(conn, open).await;
+        Ok(results)
+    }
+
+    async fn cancel_query(&self, query_id: &str) -> DbResult<bool> {
+        let backend_pid = match self.running_queries.lock().unwrap().get_mut(query_id) {
+            Some(query) => {
+                query.cancelled = true;
+                query.backend_pid
+            }
+            None => return Ok(false),
+        };
+
+        // The query's own connection is busy and the pool may have no other one
+        // free, so the cancel goes through a connection of its own
+        let mut conn = self.connect_once().await?;
+        let cancelled = sqlx::query_scalar("SELECT pg_cancel_backend($1)")
+            .bind(backend_pid)
+            .fetch_one(&mut conn)
+            .await;
+        let _ = conn.close().await;
+        let cancelled: bool = cancelled?;
+
+        // A streaming query is idle between pages, so nothing was interrupted.
+        // Closing its cursor stops it and releases the connection.
+        let streaming = self.cursors.lock().unwrap().contains_key(query_id);
+        if streaming {
+            self.close_cursor(query_id).await?;
+            return Ok(true);
+        }
+        Ok(cancelled)
+    }
+
+    async fn open_cursor(
+        &self,
+        query_id: &str,
+        sql: &str,
+        page_size: u64,
+        max_rows: u64,
+        sink: BatchSink,
+    ) -> DbResult<Vec<ColumnDefinition>> {
+        let mut conn = self.connection().await?;
+        self.start_query(query_id, &mut conn).await?;
+
+        let mut cursor = match Cursor::declare(conn, query_id, sql, page_size, max_rows, sink).await
+        {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                self.finish_query(query_id);
+                return Err(e);
+            }
+        };
+
+        match cursor.fetch_page(&self.session).await {
+            Ok((columns, done)) => {
+                if done {
+                    self.finish_query(query_id);
+                    cursor.close(&self.session).await?;
+                } else {
+                    self.cursors
+                        .lock()
+                        .unwrap()
+                        .insert(query_id.to_string(), Arc::new(AsyncMutex::new(cursor)));
+                }
+                Ok(columns)
+            }
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                self.finish_query(query_id);
+                let _ = cursor.close(&self.session).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn fetch_cursor(&self, query_id: &str) -> DbResult<bool> {
+        let cursor = self
+            .cursors
+            .lock()
+            .unwrap()
+            .get(query_id)
+            .cloned()
+            .ok_or_else(|| DbError::NotFound(format!("No open cursor for query {}", query_id)))?;
+
+        let result = cursor.lock().await.fetch_page(&self.session).await;
+        match result {
+            Ok((_, false)) => Ok(true),
+            Ok((_, true)) => {
+                self.close_cursor(query_id).await?;
+                Ok(false)
+            }
+            Err(e) => {
+                let e = self.cancelled_or(query_id, e);
+                let _ = self.close_cursor(query_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn close_cursor(&self, query_id: &str) -> DbResult<()> {
+        let cursor = self.cursors.lock().unwrap().remove(query_id);
+        self.finish_query(query_id);
+
+        if let Some(cursor) = cursor {
+            cursor.lock().await.close(&self.session).await?;
+        }
+        Ok(())
+    }
+
+    async fn begin_transaction(&self) -> DbResult<()> {
+        self.session.begin(self.get_pool()?, "BEGIN").await
+    }
+
+    async fn commit(&self) -> DbResult<()> {
+        self.session.end("COMMIT").await
+    }
+
+    async fn rollback(&self, savepoint: Option<&str>) -> DbResult<()> {
+        match savepoint {
+            Some(name) => {
+                let sql = format!("ROLLBACK TO SAVEPOINT {}", quote_ident(name));
+                self.session.execute(&sql).await
+            }
+            None => self.session.end("ROLLBACK").await,
+        }
+    }
+
+    async fn savepoint(&self, name: &str) -> DbResult<()> {
+        self.session
+            .execute(&format!("SAVEPOINT {}", quote_ident(name)))
+            .await
+    }
+
+    fn transaction_state(&self) -> TransactionState {
+        self.session.state()
+    }
+
+    async fn schema_fingerprint(&self) -> DbResult<String> {
+        let pool = self.get_pool()?;
+        Ok(sqlx::query_scalar(FINGERPRINT_QUERY)
+            .fetch_one(pool)
+            .await?)
+    }
+
+    async fn get_entity_changes(&self, since: Option<&str>) -> DbResult<EntityChanges> {
+        let fingerprint = self.schema_fingerprint().await?;
+        if let Some(changes) = SchemaTracker::unchanged(&fingerprint, since) {
+            return Ok(changes);
+        }
+
+        let entities = self.get_all_entities().await?;
+        Ok(self.schema_tracker.changes(fingerprint, since, entities))
+    }
+
+    async fn check_schema_changed(&self) -> DbResult<Option<String>> {
+        let fingerprint = self.schema_fingerprint().await?;
+        Ok(self.schema_tracker.check(fingerprint))
+    }
+
+    async fn get_entity_ddl(&self, id: &str) -> DbResult<String> {
+        ddl::entity_ddl(self.get_pool()?, id).await
+    }
+
+    async fn describe_relation(&self, id: &str) -> DbResult<RelationDetails> {
+        introspection::describe_relation(self.get_pool()?, id).await
+    }
+
+    async fn get_foreign_keys(&self) -> DbResult<Vec<ForeignKey>> {
+        introspection::foreign_key_graph(self.get_pool()?).await
+    }
+
+    async fn follow_reference(
+        &self,
+        table_id: &str,
+        column: &str,
+        value: &str,
+    ) -> DbResult<QueryResult> {
+        let mut conn = self.connection().await?;
+        introspection::follow_reference(&mut conn, table_id, column, value).await
     }
 
     async fn get_all_entities(&self) -> DbResult<HashMap<String, DbEntity>> {
@@ -210,7 +761,7 @@ impl DatabaseClient for PostgresClient {
             JOIN pg_namespace n ON c.relnamespace = n.oid
             LEFT JOIN pg_depend d ON d.objid = n.oid AND d.deptype = 'e'
             LEFT JOIN pg_extension e ON e.oid = d.refobjid
-            WHERE c.relkind IN ('r', 'v', 'm', 'f', 'S')
+            WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f', 'S')
             ORDER BY n.nspname, c.relname
         "#;
 
@@ -236,11 +787,11 @@ impl DatabaseClient for PostgresClient {
             };
 
             let entity = match kind.as_str() {
-                "r" => DbEntity::Table(schema_level),
+                "r" | "p" => DbEntity::Table(schema_level),
                 "v" => DbEntity::View(schema_level),
                 "m" => DbEntity::MaterializedView(schema_level),
                 "f" => DbEntity::ForeignTable(schema_level),
-                // "S" => DbEntity::Sequence(schema_level),
+                "S" => DbEntity::Sequence(schema_level),
                 _ => continue,
             };
 
@@ -248,169 +799,237 @@ impl DatabaseClient for PostgresClient {
         }
 
         // Query 3: Get functions and procedures
-        // let proc_query = r#"
-        //     SELECT
-        //         p.oid::TEXT AS id,
-        //         p.proname AS name,
-        //         n.oid::TEXT AS schema_id,
-        //         CASE
-        //             WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
-        //                  OR n.nspname LIKE 'pg_%'
-        //             THEN true
-        //             ELSE false
-        //         END AS is_system,
-        //         e.extname AS extension_name
-        //     FROM pg_proc p
-        //     JOIN pg_namespace n ON p.pronamespace = n.oid
-        //     LEFT JOIN pg_depend d ON d.objid = n.oid AND d.deptype = 'e'
-        //     LEFT JOIN pg_extension e ON e.oid = d.refobjid
-        //     ORDER BY n.nspname, p.proname
-        // "#;
-
-        // let proc_rows = sqlx::query(proc_query).fetch_all(pool).await?;
-        // for row in proc_rows {
-        //     let id: String = row.get("id");
-        //     let name: String = row.get("name");
-        //     let schema_id: String = row.get("schema_id");
-        //     let is_system: bool = row.get("is_system");
-        //     let extension_name: Option<String> = row.get("extension_name");
-
-        //     if let Some(children) = schema_children_map.get_mut(&schema_id) {
-        //         children.push(id.clone());
-        //     }
-
-        //     entities.insert(
-        //         id.clone(),
-        //         DbEntity::Function(SchemaLevelEntity {
-        //             id,
-        //             name,
-        //             is_system,
-        //             schema_id,
-        //             extension_name,
-        //             children: Vec::new(),
-        //         }),
-        //     );
-        // }
-
-        // // Query 4: Get custom types
-        // let type_query = r#"
-        //     SELECT
-        //         t.oid::TEXT AS id,
-        //         t.typname AS name,
-        //         n.oid::TEXT AS schema_id,
-        //         CASE
-        //             WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
-        //                  OR n.nspname LIKE 'pg_%'
-        //             THEN true
-        //             ELSE false
-        //         END AS is_system,
-        //         e.extname AS extension_name
-        //     FROM pg_type t
-        //     JOIN pg_namespace n ON t.typnamespace = n.oid
-        //     LEFT JOIN pg_depend d ON d.objid = n.oid AND d.deptype = 'e'
-        //     LEFT JOIN pg_extension e ON e.oid = d.refobjid
-        //     WHERE t.typtype NOT IN ('b', 'p')  -- Exclude built-in and pseudo types
-        //     ORDER BY n.nspname, t.typname
-        // "#;
-
-        // let type_rows = sqlx::query(type_query).fetch_all(pool).await?;
-        // for row in type_rows {
-        //     let id: String = row.get("id");
-        //     let name: String = row.get("name");
-        //     let schema_id: String = row.get("schema_id");
-        //     let is_system: bool = row.get("is_system");
-        //     let extension_name: Option<String> = row.get("extension_name");
-
-        //     if let Some(children) = schema_children_map.get_mut(&schema_id) {
-        //         children.push(id.clone());
-        //     }
-
-        //     entities.insert(
-        //         id.clone(),
-        //         DbEntity::CustomType(SchemaLevelEntity {
-        //             id,
-        //             name,
-        //             is_system,
-        //             schema_id,
-        //             extension_name,
-        //             children: Vec::new(),
-        //         }),
-        //     );
-        // }
-
-        // // Query 5: Get indexes
-        // let index_query = r#"
-        //     SELECT
-        //         i.indexrelid::TEXT AS id,
-        //         ic.relname AS name,
-        //         i.indrelid::TEXT AS table_id,
-        //         CASE
-        //             WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
-        //                  OR n.nspname LIKE 'pg_%'
-        //             THEN true
-        //             ELSE false
-        //         END AS is_system
-        //     FROM pg_index i
-        //     JOIN pg_class ic ON ic.oid = i.indexrelid
-        //     JOIN pg_class tc ON tc.oid = i.indrelid
-        //     JOIN pg_namespace n ON tc.relnamespace = n.oid
-        //     ORDER BY ic.relname
-        // "#;
-
-        // let index_rows = sqlx::query(index_query).fetch_all(pool).await?;
-        // for row in index_rows {
-        //     let id: String = row.get("id");
-        //     let name: String = row.get("name");
-        //     let table_id: String = row.get("table_id");
-        //     let is_system: bool = row.get("is_system");
-
-        //     entities.insert(
-        //         id.clone(),
-        //         DbEntity::Index(TableLevelEntity {
-        //             id,
-        //             name,
-        //             is_system,
-        //             table_id,
-        //         }),
-        //     );
-        // }
-
-        // // Query 6: Get triggers
-        // let trigger_query = r#"
-        //     SELECT
-        //         t.oid::TEXT AS id,
-        //         t.tgname AS name,
-        //         t.tgrelid::TEXT AS table_id,
-        //         CASE
-        //             WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
-        //                  OR n.nspname LIKE 'pg_%'
-        //             THEN true
-        //             ELSE false
-        //         END AS is_system
-        //     FROM pg_trigger t
-        //     JOIN pg_class c ON c.oid = t.tgrelid
-        //     JOIN pg_namespace n ON c.relnamespace = n.oid
-        //     WHERE NOT t.tgisinternal  -- Exclude internal triggers
-        //     ORDER BY t.tgname
-        // "#;
-
-        // let trigger_rows = sqlx::query(trigger_query).fetch_all(pool).await?;
-        // for row in trigger_rows {
-        //     let id: String = row.get("id");
-        //     let name: String = row.get("name");
-        //     let table_id: String = row.get("table_id");
-        //     let is_system: bool = row.get("is_system");
-
-        //     entities.insert(
-        //         id.clone(),
-        //         DbEntity::Trigger(TableLevelEntity {
-        //             id,
-        //             name,
-        //             is_system,
-        //             table_id,
-        //         }),
-        //     );
-        // }
+        let proc_query = r#"
+            SELECT
+                p.oid::TEXT AS id,
+                p.proname AS name,
+                p.prokind::TEXT AS kind,
+                n.oid::TEXT AS schema_id,
+                CASE
+                    WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
+                         OR n.nspname LIKE 'pg_%'
+                    THEN true
+                    ELSE false
+                END AS is_system,
+                e.extname AS extension_name
+            FROM pg_proc p
+            JOIN pg_namespace n ON p.pronamespace = n.oid
+            LEFT JOIN pg_depend d ON d.classid = 'pg_proc'::regclass
+                AND d.objid = p.oid AND d.deptype = 'e'
+            LEFT JOIN pg_extension e ON e.oid = d.refobjid
+            ORDER BY n.nspname, p.proname
+        "#;
+
+        let proc_rows = sqlx::query(proc_query).fetch_all(pool).await?;
+        for row in proc_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+            let kind: String = row.get("kind");
+            let schema_id: String = row.get("schema_id");
+            let is_system: bool = row.get("is_system");
+            let extension_name: Option<String> = row.get("extension_name");
+
+            if let Some(children) = schema_children_map.get_mut(&schema_id) {
+                children.push(id.clone());
+            }
+
+            let schema_level = SchemaLevelEntity {
+                id: id.clone(),
+                name,
+                is_system,
+                schema_id,
+                extension_name,
+            };
+
+            // Aggregates and window functions are listed as functions
+            let entity = match kind.as_str() {
+                "p" => DbEntity::Procedure(schema_level),
+                _ => DbEntity::Function(schema_level),
+            };
+
+            entities.insert(id, entity);
+        }
+
+        // Query 4: Get custom types
+        let type_query = r#"
+            SELECT
+                t.oid::TEXT AS id,
+                t.typname AS name,
+                n.oid::TEXT AS schema_id,
+                CASE
+                    WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
+                         OR n.nspname LIKE 'pg_%'
+                    THEN true
+                    ELSE false
+                END AS is_system,
+                e.extname AS extension_name
+            FROM pg_type t
+            JOIN pg_namespace n ON t.typnamespace = n.oid
+            LEFT JOIN pg_class c ON c.oid = t.typrelid
+            LEFT JOIN pg_depend d ON d.classid = 'pg_type'::regclass
+                AND d.objid = t.oid AND d.deptype = 'e'
+            LEFT JOIN pg_extension e ON e.oid = d.refobjid
+            WHERE t.typtype NOT IN ('b', 'p')  -- Exclude built-in and pseudo types
+              AND (c.oid IS NULL OR c.relkind = 'c')  -- Exclude row types of tables
+            ORDER BY n.nspname, t.typname
+        "#;
+
+        let type_rows = sqlx::query(type_query).fetch_all(pool).await?;
+        for row in type_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+            let schema_id: String = row.get("schema_id");
+            let is_system: bool = row.get("is_system");
+            let extension_name: Option<String> = row.get("extension_name");
+
+            if let Some(children) = schema_children_map.get_mut(&schema_id) {
+                children.push(id.clone());
+            }
+
+            entities.insert(
+                id.clone(),
+                DbEntity::CustomType(SchemaLevelEntity {
+                    id,
+                    name,
+                    is_system,
+                    schema_id,
+                    extension_name,
+                }),
+            );
+        }
+
+        // Query 5: Get indexes
+        let index_query = r#"
+            SELECT
+                i.indexrelid::TEXT AS id,
+                ic.relname AS name,
+                i.indrelid::TEXT AS table_id,
+                CASE
+                    WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
+                         OR n.nspname LIKE 'pg_%'
+                    THEN true
+                    ELSE false
+                END AS is_system
+            FROM pg_index i
+            JOIN pg_class ic ON ic.oid = i.indexrelid
+            JOIN pg_class tc ON tc.oid = i.indrelid
+            JOIN pg_namespace n ON tc.relnamespace = n.oid
+            ORDER BY ic.relname
+        "#;
+
+        let index_rows = sqlx::query(index_query).fetch_all(pool).await?;
+        for row in index_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+            let table_id: String = row.get("table_id");
+            let is_system: bool = row.get("is_system");
+
+            // Skip indexes on relations that aren't listed (e.g. toast tables)
+            if !entities.contains_key(&table_id) {
+                continue;
+            }
+
+            entities.insert(
+                id.clone(),
+                DbEntity::Index(TableLevelEntity {
+                    id,
+                    name,
+                    is_system,
+                    table_id,
+                }),
+            );
+        }
+
+        // Query 6: Get triggers
+        let trigger_query = r#"
+            SELECT
+                t.oid::TEXT AS id,
+                t.tgname AS name,
+                t.tgrelid::TEXT AS table_id,
+                CASE
+                    WHEN n.nspname IN ('pg_catalog', 'information_schema', 'pg_toast')
+                         OR n.nspname LIKE 'pg_%'
+                    THEN true
+                    ELSE false
+                END AS is_system
+            FROM pg_trigger t
+            JOIN pg_class c ON c.oid = t.tgrelid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE NOT t.tgisinternal  -- Exclude internal triggers
+              AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+            ORDER BY t.tgname
+        "#;
+
+        let trigger_rows = sqlx::query(trigger_query).fetch_all(pool).await?;
+        for row in trigger_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+            let table_id: String = row.get("table_id");
+            let is_system: bool = row.get("is_system");
+
+            // Skip triggers on relations that aren't listed, like the indexes
+            if !entities.contains_key(&table_id) {
+                continue;
+            }
+
+            entities.insert(
+                id.clone(),
+                DbEntity::Trigger(TableLevelEntity {
+                    id,
+                    name,
+                    is_system,
+                    table_id,
+                }),
+            );
+        }
+
+        // Query 7: Get extensions
+        let extension_query = r#"
+            SELECT
+                e.oid::TEXT AS id,
+                e.extname AS name
+            FROM pg_extension e
+            ORDER BY e.extname
+        "#;
+
+        let extension_rows = sqlx::query(extension_query).fetch_all(pool).await?;
+        for row in extension_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+
+            entities.insert(id.clone(), DbEntity::Extension(DbExtension { id, name }));
+        }
+
+        // Query 8: Get event triggers, which don't belong to a schema or table
+        let event_trigger_query = r#"
+            SELECT
+                t.oid::TEXT AS id,
+                t.evtname AS name,
+                e.extname AS extension_name
+            FROM pg_event_trigger t
+            LEFT JOIN pg_depend d ON d.classid = 'pg_event_trigger'::regclass
+                AND d.objid = t.oid AND d.deptype = 'e'
+            LEFT JOIN pg_extension e ON e.oid = d.refobjid
+            ORDER BY t.evtname
+        "#;
+
+        let event_trigger_rows = sqlx::query(event_trigger_query).fetch_all(pool).await?;
+        for row in event_trigger_rows {
+            let id: String = row.get("id");
+            let name: String = row.get("name");
+            let extension_name: Option<String> = row.get("extension_name");
+
+            entities.insert(
+                id.clone(),
+                DbEntity::GlobalTrigger(GlobalTrigger {
+                    id,
+                    name,
+                    // Postgres doesn't ship any event triggers of its own
+                    is_system: false,
+                    extension_name,
+                }),
+            );
+        }
 
         // Update schema entities with their children
         for (schema_id, children) in schema_children_map {
@@ -422,3 +1041,220 @@ impl DatabaseClient for PostgresClient {
         Ok(entities)
     }
 }
+
+/// Reports the server, login and TLS state of a connection
+async fn fetch_server_info(conn: &mut PgConnection) -> DbResult<ServerInfo> {
+    let info_query = r#"
+        SELECT
+            current_setting('server_version') AS version,
+            current_user::text AS "user",
+            current_database()::text AS database,
+            coalesce(ssl.ssl, false) AS tls,
+            ssl.version AS tls_version,
+            ssl.cipher AS tls_cipher
+        FROM (SELECT pg_backend_pid() AS pid) AS backend
+        LEFT JOIN pg_stat_ssl ssl ON ssl.pid = backend.pid
+    "#;
+    let row = sqlx::query(info_query).fetch_one(&mut *conn).await?;
+
+    Ok(ServerInfo {
+        version: row.get("version"),
+        user: row.get("user"),
+        database: row.get("database"),
+        tls: row.get("tls"),
+        tls_version: row.get("tls_version"),
+        tls_cipher: row.get("tls_cipher"),
+    })
+}
+
+/// Settings from a project's pool config that are set on each session with `set_config`
+fn session_settings(settings: &PoolSettings) -> Vec<(&'static str, String)> {
+    let mut session_settings = Vec::new();
+    if let Some(ms) = settings.statement_timeout_ms {
+        session_settings.push(("statement_timeout", ms.to_string()));
+    }
+    if let Some(ms) = settings.lock_timeout_ms {
+        session_settings.push(("lock_timeout", ms.to_string()));
+    }
+    if let Some(search_path) = &settings.search_path {
+        session_settings.push(("search_path", search_path.clone()));
+    }
+    if let Some(role) = &settings.role {
+        session_settings.push(("role", role.clone()));
+    }
+    session_settings
+}
+
+/// Runs a single statement, collecting its rows and the number of rows affected
+async fn run_query(
+    conn: &mut PgConnection,
+    sql: &str,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    run_query_with(conn, sqlx::query(sql), result_index).await
+}
+
+/// Prepares `sql` to find out its parameter types, then binds `params` and runs it
+async fn run_query_with_params(
+    conn: &mut PgConnection,
+    sql: &str,
+    params: &[BindValue],
+) -> DbResult<QueryResult> {
+    let param_types = match (&mut *conn).prepare(sql).await?.parameters() {
+        Some(Either::Left(types)) => types.to_vec(),
+        _ => Vec::new(),
+    };
+
+    if param_types.len() != params.len() {
+        return Err(DbError::Query(format!(
+            "Query expects {} parameter(s) but {} were given",
+            param_types.len(),
+            params.len()
+        )));
+    }
+
+    let mut query = sqlx::query(sql);
+    for (i, (value, param_type)) in params.iter().zip(&param_types).enumerate() {
+        query = encode::bind_param(query, i + 1, value, param_type)?;
+    }
+
+    run_query_with(conn, query, 0).await
+}
+
+async fn run_query_with(
+    conn: &mut PgConnection,
+    query: Query<'_, Postgres, PgArguments>,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    let sql = query.sql().to_string();
+    let mut result = QueryResult::new(&sql, result_index);
+    result.command = leading_keyword(&sql);
+
+    // Only time spent waiting on the server counts, not decoding
+    let mut elapsed = Duration::ZERO;
+    {
+        let mut stream = (&mut *conn).fetch_many(query);
+        loop {
+            let polled = Instant::now();
+            let item = stream.try_next().await?;
+            elapsed += polled.elapsed();
+
+            match item {
+                Some(Either::Left(done)) => {
+                    // Taken from the command tag, e.g. `UPDATE 42`
+                    result.rows_affected =
+                        Some(result.rows_affected.unwrap_or(0) + done.rows_affected());
+                }
+                Some(Either::Right(row)) => {
+                    if result.columns.is_empty() {
+                        result.columns = column_definitions(row.columns());
+                    }
+
+                    let mut values = Vec::with_capacity(row.len());
+                    for i in 0..row.len() {
+                        values.push(decode::to_cell(row.try_get_raw(i)?));
+                    }
+                    result.rows.push(values);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Queries that return no rows should still report their columns. The statement
+    // was just executed, so this comes from the connection's statement cache.
+    if result.columns.is_empty() {
+        let statement = (&mut *conn).prepare(&sql).await?;
+        result.columns = column_definitions(statement.columns());
+    }
+
+    result.execution_time_ms = elapsed.as_millis() as u64;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::types::CellValue;
+
+    #[tokio::test]
+    async fn cancels_queries_when_the_pool_is_exhausted() {
+        let settings = PoolSettings {
+            max_connections: Some(1),
+            acquire_timeout_secs: Some(5),
+            ..Default::default()
+        };
+        let Some(client) = testing::client(settings).await else {
+            return;
+        };
+        let client = Arc::new(client);
+
+        let running = {
+            let client = client.clone();
+            tokio::spawn(async move { client.execute_query("sleep", "SELECT pg_sleep(30)").await })
+        };
+        while !client.running_queries.lock().unwrap().contains_key("sleep") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let started = Instant::now();
+        assert!(client.cancel_query("sleep").await.unwrap());
+        assert!(matches!(running.await.unwrap(), Err(DbError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn streams_results_of_different_shapes_on_one_connection() {
+        let settings = PoolSettings {
+            max_connections: Some(1),
+            ..Default::default()
+        };
+        let Some(client) = testing::client(settings).await else {
+            return;
+        };
+
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let sink = || -> BatchSink {
+            let rows = rows.clone();
+            Box::new(move |batch| {
+                rows.lock().unwrap().extend(batch.rows);
+                Ok(())
+            })
+        };
+
+        // The same query ID streams twice, so both FETCHes have the same text
+        let columns = client
+            .open_cursor(
+                "stream",
+                "SELECT n FROM generate_series(1, 3) n",
+                2,
+                100,
+                sink(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(columns[0].name, "n");
+        assert!(client.fetch_cursor("stream").await.is_ok());
+        assert_eq!(
+            std::mem::take(&mut *rows.lock().unwrap()),
+            vec![
+                vec![CellValue::Int(1)],
+                vec![CellValue::Int(2)],
+                vec![CellValue::Int(3)],
+            ]
+        );
+
+        let columns = client
+            .open_cursor("stream", "SELECT 'x'::text AS s, true AS b", 2, 100, sink())
+            .await
+            .unwrap();
+        assert_eq!(columns.len(), 2);
+        assert_eq!(
+            *rows.lock().unwrap(),
+            vec![vec![
+                CellValue::Text("x".to_string()),
+                CellValue::Bool(true)
+            ]]
+        );
+    }
+}
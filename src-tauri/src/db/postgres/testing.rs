@@ -0,0 +1,25 @@
+use sqlx::{Connection, PgConnection};
+
+use super::PostgresClient;
+use crate::db::{client::DatabaseClient, pool::PoolSettings};
+
+/// Variable holding the connection string of the server tests run against
+pub(crate) const TEST_URL_VAR: &str = "SQRATCH_TEST_POSTGRES_URL";
+
+/// Connects to the test server. Tests that need one pass without running
+/// when `SQRATCH_TEST_POSTGRES_URL` isn't set.
+pub(crate) async fn connect() -> Option<PgConnection> {
+    let url = std::env::var(TEST_URL_VAR).ok()?;
+    let conn = PgConnection::connect(&url)
+        .await
+        .unwrap_or_else(|e| panic!("Couldn't connect to {}: {}", TEST_URL_VAR, e));
+    Some(conn)
+}
+
+/// A client connected to the test server with `settings`
+pub(crate) async fn client(settings: PoolSettings) -> Option<PostgresClient> {
+    let url = std::env::var(TEST_URL_VAR).ok()?;
+    let mut client = PostgresClient::new(&url, settings, None, None).unwrap();
+    client.connect().await.unwrap();
+    Some(client)
+}
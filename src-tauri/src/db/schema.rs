@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::db::types::{DbEntity, EntityChanges};
+
+/// The entities last sent to the frontend, and the schema fingerprint they were fetched at
+pub struct EntitySnapshot {
+    pub fingerprint: String,
+    pub entities: HashMap<String, DbEntity>,
+}
+
+impl EntitySnapshot {
+    /// Returns the changes needed to go from this snapshot to `entities`
+    pub fn diff(&self, fingerprint: &str, entities: &HashMap<String, DbEntity>) -> EntityChanges {
+        let changed = entities
+            .iter()
+            .filter(|(id, entity)| self.entities.get(*id) != Some(*entity))
+            .map(|(id, entity)| (id.clone(), entity.clone()))
+            .collect();
+
+        let removed = self
+            .entities
+            .keys()
+            .filter(|id| !entities.contains_key(*id))
+            .cloned()
+            .collect();
+
+        EntityChanges {
+            fingerprint: fingerprint.to_string(),
+            full: false,
+            changed,
+            removed,
+        }
+    }
+}
+
+/// Changes for a caller that has no usable snapshot, containing every entity
+pub fn full_changes(fingerprint: &str, entities: &HashMap<String, DbEntity>) -> EntityChanges {
+    EntityChanges {
+        fingerprint: fingerprint.to_string(),
+        full: true,
+        changed: entities.clone(),
+        removed: Vec::new(),
+    }
+}
+
+/// Schema change state a client keeps between calls to `get_entity_changes`
+/// and `check_schema_changed`
+#[derive(Default)]
+pub struct SchemaTracker {
+    /// Entities last returned by `get_entity_changes`, to diff later calls against
+    snapshot: Mutex<Option<EntitySnapshot>>,
+    /// Fingerprint seen by the last `check_schema_changed`
+    last_fingerprint: Mutex<Option<String>>,
+}
+
+impl SchemaTracker {
+    /// Empty changes if the caller is already up to date with `fingerprint`
+    pub fn unchanged(fingerprint: &str, since: Option<&str>) -> Option<EntityChanges> {
+        (since == Some(fingerprint)).then(|| EntityChanges {
+            fingerprint: fingerprint.to_string(),
+            full: false,
+            changed: HashMap::new(),
+            removed: Vec::new(),
+        })
+    }
+
+    /// Diffs `entities` against the snapshot if the caller has it, then replaces the snapshot
+    pub fn changes(
+        &self,
+        fingerprint: String,
+        since: Option<&str>,
+        entities: HashMap<String, DbEntity>,
+    ) -> EntityChanges {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let changes = match snapshot.as_ref() {
+            Some(previous) if since == Some(previous.fingerprint.as_str()) => {
+                previous.diff(&fingerprint, &entities)
+            }
+            _ => full_changes(&fingerprint, &entities),
+        };
+
+        *snapshot = Some(EntitySnapshot {
+            fingerprint,
+            entities,
+        });
+        changes
+    }
+
+    /// Records `fingerprint`, returning it if it differs from the last one recorded
+    pub fn check(&self, fingerprint: String) -> Option<String> {
+        let mut last = self.last_fingerprint.lock().unwrap();
+        let changed = last.as_ref().is_some_and(|last| *last != fingerprint);
+        *last = Some(fingerprint.clone());
+
+        changed.then_some(fingerprint)
+    }
+}
@@ -0,0 +1,280 @@
+/// Splits a SQL script into individual statements on top-level semicolons.
+///
+/// Semicolons inside string literals (including `E''` escape strings), quoted
+/// identifiers, dollar-quoted bodies and comments are ignored. Statements that
+/// only contain whitespace or comments are dropped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = skip_line_comment(bytes, i);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            }
+            b'\'' => {
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'e' | b'E')
+                    && (i < 2 || !is_ident_char(bytes[i - 2]));
+                i = skip_quoted(bytes, i, b'\'', escapes);
+                has_code = true;
+                continue;
+            }
+            b'"' => {
+                i = skip_quoted(bytes, i, b'"', false);
+                has_code = true;
+                continue;
+            }
+            b'$' if i == 0 || !is_ident_char(bytes[i - 1]) => {
+                if let Some(tag_len) = dollar_tag_len(bytes, i) {
+                    i = skip_dollar_quoted(bytes, i, tag_len);
+                    has_code = true;
+                    continue;
+                }
+                has_code = true;
+            }
+            b';' => {
+                if has_code {
+                    statements.push(sql[start..i].trim());
+                }
+                start = i + 1;
+                has_code = false;
+            }
+            c if !c.is_ascii_whitespace() => has_code = true,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if has_code {
+        statements.push(sql[start..].trim());
+    }
+
+    statements
+}
+
+/// Returns the first keyword of a statement in uppercase (e.g. `UPDATE`),
+/// skipping any leading comments and parentheses.
+pub fn leading_keyword(statement: &str) -> Option<String> {
+    let bytes = statement.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_line_comment(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_block_comment(bytes, i),
+            b'(' => i += 1,
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => break,
+        }
+    }
+
+    let keyword: String = statement[i..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    (!keyword.is_empty()).then(|| keyword.to_ascii_uppercase())
+}
+
+/// Whether a statement can change the schema, either directly (e.g. `CREATE`) or
+/// by committing a transaction that may have contained DDL
+pub fn may_change_schema(statement: &str) -> bool {
+    matches!(
+        leading_keyword(statement).as_deref(),
+        Some("CREATE" | "ALTER" | "DROP" | "COMMENT" | "IMPORT" | "REINDEX" | "COMMIT" | "END")
+    )
+}
+
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+/// Returns the index just past the end of the line
+fn skip_line_comment(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&c| c == b'\n')
+        .map_or(bytes.len(), |pos| start + pos + 1)
+}
+
+/// Block comments can be nested in Postgres
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Skips a quoted string or identifier, where the quote is escaped by doubling it
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if backslash_escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the length of a dollar quote tag (e.g. `$$` or `$body$`) starting at `start`
+fn dollar_tag_len(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'$' => return Some(i - start + 1),
+            // Tags can't start with a digit, otherwise this is a parameter like `$1`
+            c if c.is_ascii_digit() && i == start + 1 => return None,
+            c if is_ident_char(c) => i += 1,
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn skip_dollar_quoted(bytes: &[u8], start: usize, tag_len: usize) -> usize {
+    let tag = &bytes[start..start + tag_len];
+    let body_start = start + tag_len;
+    bytes[body_start..]
+        .windows(tag_len)
+        .position(|window| window == tag)
+        .map_or(bytes.len(), |pos| body_start + pos + tag_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_top_level_semicolons() {
+        assert_eq!(
+            split_statements("SELECT 1; SELECT 2;\n  SELECT 3"),
+            vec!["SELECT 1", "SELECT 2", "SELECT 3"]
+        );
+    }
+
+    #[test]
+    fn drops_empty_and_comment_only_statements() {
+        assert_eq!(
+            split_statements(";; -- nothing here;\n/* or; here */; SELECT 1;"),
+            vec!["SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn ignores_semicolons_in_strings_and_identifiers() {
+        assert_eq!(
+            split_statements(r#"SELECT 'a;b', 'it''s;' AS "x;y"; SELECT 2"#),
+            vec![r#"SELECT 'a;b', 'it''s;' AS "x;y""#, "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn handles_backslash_escapes_in_e_strings() {
+        assert_eq!(
+            split_statements(r"SELECT E'a\';b', e'\\'; SELECT 2"),
+            vec![r"SELECT E'a\';b', e'\\'", "SELECT 2"]
+        );
+        // Only `E''` strings use backslash escapes, in others it's a plain character
+        assert_eq!(
+            split_statements(r"SELECT 'a\'; SELECT 2"),
+            vec![r"SELECT 'a\'", "SELECT 2"]
+        );
+        // A name ending in `e` isn't an escape string prefix
+        assert_eq!(
+            split_statements(r"SELECT name'a\'; SELECT 2"),
+            vec![r"SELECT name'a\'", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn skips_dollar_quoted_bodies() {
+        let sql = "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql; SELECT 2";
+        assert_eq!(
+            split_statements(sql),
+            vec![
+                "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql",
+                "SELECT 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_dollar_tags_exactly() {
+        let sql = "DO $body$ BEGIN RAISE NOTICE '$$;'; END $body$; SELECT 2";
+        assert_eq!(
+            split_statements(sql),
+            vec!["DO $body$ BEGIN RAISE NOTICE '$$;'; END $body$", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn treats_numbered_parameters_as_code() {
+        assert_eq!(
+            split_statements("SELECT $1; SELECT $2"),
+            vec!["SELECT $1", "SELECT $2"]
+        );
+    }
+
+    #[test]
+    fn skips_nested_block_comments() {
+        assert_eq!(
+            split_statements("SELECT /* outer /* inner; */ still comment; */ 1; SELECT 2"),
+            vec![
+                "SELECT /* outer /* inner; */ still comment; */ 1",
+                "SELECT 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_line_comments() {
+        assert_eq!(
+            split_statements("SELECT 1 -- trailing; comment\n; SELECT 2"),
+            vec!["SELECT 1 -- trailing; comment", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn leading_keyword_skips_comments_and_parentheses() {
+        assert_eq!(
+            leading_keyword("-- note\n/* c */ ((select 1))").as_deref(),
+            Some("SELECT")
+        );
+        assert_eq!(leading_keyword("  ;"), None);
+    }
+
+    #[test]
+    fn may_change_schema_for_ddl_and_commits() {
+        assert!(may_change_schema("create table t (id int)"));
+        assert!(may_change_schema("COMMIT"));
+        assert!(!may_change_schema("SELECT 1"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use sqlx::{pool::PoolConnection, Database, Executor, Pool};
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+
+use crate::db::{
+    errors::{DbError, DbResult},
+    script::leading_keyword,
+    types::TransactionState,
+};
+
+/// The connection pinned for an explicit transaction, shared by the pooled backends
+pub struct Session<DB: Database> {
+    /// Connection pinned while an explicit transaction is open
+    connection: AsyncMutex<Option<PoolConnection<DB>>>,
+    /// Mirrors `connection` so the state can be read without awaiting the lock
+    state: Mutex<TransactionState>,
+}
+
+/// The connection a query runs on
+pub enum QueryConnection<'a, DB: Database> {
+    /// The connection pinned for an open transaction, locked for the duration of the query
+    Session(MutexGuard<'a, Option<PoolConnection<DB>>>),
+    /// Any connection from the pool
+    Pooled(PoolConnection<DB>),
+}
+
+impl<DB: Database> Deref for QueryConnection<'_, DB> {
+    type Target = DB::Connection;
+
+    fn deref(&self) -> &DB::Connection {
+        match self {
+            QueryConnection::Session(session) => session
+                .as_ref()
+                .expect("session connection is only locked while a transaction is open"),
+            QueryConnection::Pooled(conn) => conn,
+        }
+    }
+}
+
+impl<DB: Database> DerefMut for QueryConnection<'_, DB> {
+    fn deref_mut(&mut self) -> &mut DB::Connection {
+        match self {
+            QueryConnection::Session(session) => session
+                .as_mut()
+                .expect("session connection is only locked while a transaction is open"),
+            QueryConnection::Pooled(conn) => conn,
+        }
+    }
+}
+
+impl<DB: Database> QueryConnection<'_, DB> {
+    pub fn is_session(&self) -> bool {
+        matches!(self, QueryConnection::Session(_))
+    }
+}
+
+impl<DB: Database> Session<DB>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    pub fn new() -> Self {
+        Self {
+            connection: AsyncMutex::new(None),
+            state: Mutex::new(TransactionState::default()),
+        }
+    }
+
+    /// Current state of the session, readable without awaiting
+    pub fn state(&self) -> TransactionState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Gets the session connection if a transaction is open, otherwise one from the pool
+    pub async fn connection(&self, pool: &Pool<DB>) -> DbResult<QueryConnection<'_, DB>> {
+        let session = self.connection.lock().await;
+        if session.is_some() {
+            return Ok(QueryConnection::Session(session));
+        }
+        drop(session);
+
+        let conn = pool.acquire().await?;
+        Ok(QueryConnection::Pooled(conn))
+    }
+
+    /// Locks the session connection, failing if no transaction is open
+    pub async fn transaction(&self) -> DbResult<QueryConnection<'_, DB>> {
+        let session = self.connection.lock().await;
+        if session.is_none() {
+            return Err(DbError::Query("No transaction is open".to_string()));
+        }
+        Ok(QueryConnection::Session(session))
+    }
+
+    /// Pins a query's connection if it left a transaction open, or releases the
+    /// session connection once its transaction has ended
+    pub async fn finish(&self, conn: QueryConnection<'_, DB>, open: bool) {
+        match (conn, open) {
+            (QueryConnection::Pooled(conn), true) => self.pin(conn).await,
+            (QueryConnection::Session(mut session), false) => {
+                session.take();
+                self.set_active(false);
+            }
+            _ => {}
+        }
+    }
+
+    async fn pin(&self, mut conn: PoolConnection<DB>) {
+        let mut session = self.connection.lock().await;
+        if session.is_some() {
+            // Another query opened a session first, don't leave this transaction dangling
+            log::warn!("A transaction is already open, rolling back the new one");
+            let _ = (&mut *conn).execute("ROLLBACK").await;
+            return;
+        }
+
+        *session = Some(conn);
+        self.set_active(true);
+    }
+
+    fn set_active(&self, active: bool) {
+        let started_at = active.then(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+        *self.state.lock().unwrap() = TransactionState { active, started_at };
+    }
+
+    /// Opens a transaction with `begin` on a connection from the pool and pins it
+    pub async fn begin(&self, pool: &Pool<DB>, begin: &str) -> DbResult<()> {
+        let mut session = self.connection.lock().await;
+        if session.is_some() {
+            return Err(DbError::Query("A transaction is already open".to_string()));
+        }
+
+        let mut conn = pool.acquire().await?;
+        (&mut *conn).execute(begin).await?;
+
+        *session = Some(conn);
+        self.set_active(true);
+        Ok(())
+    }
+
+    /// Runs a statement on the session connection, failing if no transaction is open
+    pub async fn execute(&self, sql: &str) -> DbResult<()> {
+        let mut session = self.connection.lock().await;
+        let conn = session
+            .as_mut()
+            .ok_or_else(|| DbError::Query("No transaction is open".to_string()))?;
+        (&mut **conn).execute(sql).await?;
+        Ok(())
+    }
+
+    /// Ends the session's transaction with `COMMIT` or `ROLLBACK` and releases its connection
+    pub async fn end(&self, sql: &str) -> DbResult<()> {
+        let mut session = self.connection.lock().await;
+        let mut conn = session
+            .take()
+            .ok_or_else(|| DbError::Query("No transaction is open".to_string()))?;
+        self.set_active(false);
+
+        // Even if this fails the connection is released, the server rolls back
+        // whatever is left when it's closed
+        if let Err(e) = (&mut *conn).execute(sql).await {
+            conn.close_on_drop();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Closes the session connection without ending its transaction, so the pool can close
+    pub fn release(&mut self) {
+        if let Some(mut conn) = self.connection.get_mut().take() {
+            // Back in the pool it would still be inside the transaction
+            conn.close_on_drop();
+            self.set_active(false);
+        }
+    }
+}
+
+/// How a statement changes the transaction state of its connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionChange {
+    Begin,
+    End,
+}
+
+/// Detects statements that open or close a transaction, e.g. `BEGIN` or `COMMIT`.
+/// `ROLLBACK TO SAVEPOINT` keeps the transaction open so it isn't counted.
+pub fn transaction_change(sql: &str) -> Option<TransactionChange> {
+    let upper = sql.to_ascii_uppercase();
+    let has_word = |word: &str| upper.split_whitespace().any(|w| w == word);
+
+    match leading_keyword(sql)?.as_str() {
+        "BEGIN" => Some(TransactionChange::Begin),
+        // Not START REPLICA or START SLAVE
+        "START" if has_word("TRANSACTION") => Some(TransactionChange::Begin),
+        "COMMIT" | "END" | "ABORT" => Some(TransactionChange::End),
+        "ROLLBACK" if !has_word("TO") => Some(TransactionChange::End),
+        "PREPARE" if has_word("TRANSACTION") => Some(TransactionChange::End),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_statements_that_open_a_transaction() {
+        assert_eq!(transaction_change("BEGIN"), Some(TransactionChange::Begin));
+        assert_eq!(
+            transaction_change("begin isolation level serializable"),
+            Some(TransactionChange::Begin)
+        );
+        assert_eq!(
+            transaction_change("/* note */ START TRANSACTION READ ONLY"),
+            Some(TransactionChange::Begin)
+        );
+    }
+
+    #[test]
+    fn detects_statements_that_end_a_transaction() {
+        for sql in ["COMMIT", "end", "ABORT", "ROLLBACK", "rollback work"] {
+            assert_eq!(
+                transaction_change(sql),
+                Some(TransactionChange::End),
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            transaction_change("PREPARE TRANSACTION 'tx1'"),
+            Some(TransactionChange::End)
+        );
+    }
+
+    #[test]
+    fn keeps_the_transaction_open_for_savepoint_rollbacks() {
+        assert_eq!(transaction_change("ROLLBACK TO SAVEPOINT a"), None);
+        assert_eq!(transaction_change("rollback to a"), None);
+    }
+
+    #[test]
+    fn ignores_other_statements() {
+        assert_eq!(transaction_change("SELECT 1"), None);
+        assert_eq!(transaction_change("START REPLICA"), None);
+        assert_eq!(transaction_change("start slave io_thread"), None);
+        assert_eq!(transaction_change("PREPARE q AS SELECT 1"), None);
+        assert_eq!(transaction_change("SAVEPOINT a"), None);
+        assert_eq!(transaction_change(""), None);
+    }
+}
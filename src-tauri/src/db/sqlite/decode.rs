@@ -0,0 +1,93 @@
+use sqlx::{
+    sqlite::{Sqlite, SqliteTypeInfo, SqliteValue, SqliteValueRef},
+    Decode, TypeInfo, Value, ValueRef,
+};
+
+use crate::db::{helpers::to_hex, types::CellValue};
+
+/// Decodes a single SQLite value into a cell.
+///
+/// SQLite values are dynamically typed, so the decoder is picked from the value's
+/// storage class. The column's declared type only refines how that class is shown,
+/// e.g. an `INTEGER` in a `BOOLEAN` column is a bool and `TEXT` in a `DATE` column a date.
+pub(crate) fn to_cell(v: SqliteValueRef, declared: &SqliteTypeInfo) -> CellValue {
+    if v.is_null() {
+        return CellValue::Null;
+    }
+
+    let storage = v.type_info().name().to_string();
+    let value = ValueRef::to_owned(&v);
+    let res = match (storage.as_str(), declared.name()) {
+        ("INTEGER", "BOOLEAN") => decode::<bool>(&value).map(CellValue::Bool),
+        ("INTEGER", _) => decode::<i64>(&value).map(CellValue::Int),
+        ("REAL", _) => decode::<f64>(&value).map(CellValue::Float),
+        ("BLOB", _) => decode::<Vec<u8>>(&value).map(|v| CellValue::Bytes(to_hex(&v))),
+        (_, "DATE") => decode::<String>(&value).map(CellValue::Date),
+        (_, "TIME") => decode::<String>(&value).map(CellValue::Time),
+        (_, "DATETIME") => decode::<String>(&value).map(CellValue::Timestamp),
+        _ => decode::<String>(&value).map(CellValue::Text),
+    };
+
+    res.unwrap_or_else(|| to_raw(&value))
+}
+
+/// Fallback for values that can't be decoded as their storage class, like `TEXT`
+/// that isn't valid UTF-8, which is returned as its bytes
+fn to_raw(value: &SqliteValue) -> CellValue {
+    match decode::<String>(value) {
+        Some(text) => CellValue::Text(text),
+        None => decode::<Vec<u8>>(value)
+            .map(|bytes| CellValue::Bytes(to_hex(&bytes)))
+            .unwrap_or(CellValue::Null),
+    }
+}
+
+/// Decodes a value without checking type compatibility, SQLite converts between
+/// storage classes itself
+fn decode<T>(value: &SqliteValue) -> Option<T>
+where
+    T: for<'r> Decode<'r, Sqlite>,
+{
+    value.try_decode_unchecked().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Column, Connection, Row, SqliteConnection};
+
+    #[tokio::test]
+    async fn decodes_by_storage_class_and_declared_type() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE t (b BOOLEAN, d DATE, n INTEGER, x TEXT, blob BLOB);
+             INSERT INTO t VALUES (1, '2024-02-29', 'not a number', CAST(x'ff' AS TEXT), x'00ff');",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        let row = sqlx::query("SELECT * FROM t")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        let cells: Vec<CellValue> = row
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(i, column)| to_cell(row.try_get_raw(i).unwrap(), column.type_info()))
+            .collect();
+
+        assert_eq!(
+            cells,
+            vec![
+                CellValue::Bool(true),
+                CellValue::Date("2024-02-29".to_string()),
+                CellValue::Text("not a number".to_string()),
+                // Invalid UTF-8 is shown as bytes rather than NULL
+                CellValue::Bytes("\\xff".to_string()),
+                CellValue::Bytes("\\x00ff".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use sqlx::{Pool, Row, Sqlite};
+
+use super::{entity_id, run_query_with};
+use crate::db::{
+    errors::{DbError, DbResult},
+    helpers::{quote_ident, FOLLOW_REFERENCE_LIMIT},
+    types::{
+        ConstraintKind, ForeignKey, QueryResult, RelationColumn, RelationConstraint,
+        RelationDetails,
+    },
+};
+
+/// Splits an entity ID into its schema (database) and object name. Both can
+/// contain dots, so the ID is matched against the names of the attached
+/// databases, preferring the longest.
+async fn parse_id<'a>(pool: &Pool<Sqlite>, id: &'a str) -> DbResult<(&'a str, &'a str)> {
+    let schemas: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_database_list")
+        .fetch_all(pool)
+        .await?;
+
+    schemas
+        .iter()
+        .filter(|schema| {
+            id.strip_prefix(schema.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .map(String::len)
+        .max()
+        .map(|len| (&id[..len], &id[len + 1..]))
+        .ok_or_else(|| DbError::NotFound(format!("Invalid entity ID: {}", id)))
+}
+
+/// Returns the `CREATE` statement SQLite stored for an entity. Tables also get
+/// the statements of their indexes and triggers.
+pub(super) async fn entity_ddl(pool: &Pool<Sqlite>, id: &str) -> DbResult<String> {
+    let (schema, name) = parse_id(pool, id).await?;
+
+    // Indexes backing constraints have no SQL of their own
+    let ddl_query = format!(
+        r#"
+        SELECT sql
+        FROM {schema}.sqlite_master
+        WHERE sql IS NOT NULL
+          AND (name = ?1 OR (type IN ('index', 'trigger') AND tbl_name = ?1
+              AND (SELECT type FROM {schema}.sqlite_master WHERE name = ?1) = 'table'))
+        ORDER BY name <> ?1, type, name
+        "#,
+        schema = quote_ident(schema)
+    );
+
+    let statements: Vec<String> = sqlx::query_scalar(&ddl_query)
+        .bind(name)
+        .fetch_all(pool)
+        .await?;
+    if statements.is_empty() {
+        return Err(DbError::NotFound(format!("Entity {} not found", id)));
+    }
+
+    Ok(statements
+        .iter()
+        .map(|sql| format!("{};", sql))
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+/// Describes a table or view from the `table_xinfo`, `index_list` and
+/// `foreign_key_list` pragmas. `CHECK` constraints aren't exposed by any pragma
+/// so they aren't listed.
+pub(super) async fn describe_relation(pool: &Pool<Sqlite>, id: &str) -> DbResult<RelationDetails> {
+    let (schema, name) = parse_id(pool, id).await?;
+
+    let kind: String = sqlx::query_scalar(&format!(
+        "SELECT type FROM {}.sqlite_master WHERE name = ?1 AND type IN ('table', 'view')",
+        quote_ident(schema)
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| DbError::NotFound(format!("Relation {} not found", id)))?;
+
+    Ok(RelationDetails {
+        id: id.to_string(),
+        name: name.to_string(),
+        schema: schema.to_string(),
+        kind,
+        comment: None,
+        columns: fetch_columns(pool, schema, name).await?,
+        constraints: fetch_constraints(pool, schema, name).await?,
+        foreign_keys: fetch_foreign_keys(pool, schema, name).await?,
+    })
+}
+
+async fn fetch_columns(
+    pool: &Pool<Sqlite>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<RelationColumn>> {
+    // Hidden columns of virtual tables are left out, generated columns are kept
+    let column_query = r#"
+        SELECT cid, name, type, "notnull", dflt_value, pk
+        FROM pragma_table_xinfo(?1, ?2)
+        WHERE hidden <> 1
+        ORDER BY cid
+    "#;
+
+    let rows = sqlx::query(column_query)
+        .bind(table)
+        .bind(schema)
+        .fetch_all(pool)
+        .await?;
+
+    let columns = rows
+        .into_iter()
+        .map(|row| RelationColumn {
+            position: row.get::<i32, _>("cid") + 1,
+            name: row.get("name"),
+            data_type: row.get("type"),
+            element_type: None,
+            domain_base_type: None,
+            enum_values: None,
+            nullable: !row.get::<bool, _>("notnull"),
+            primary_key: row.get::<i32, _>("pk") > 0,
+            default_value: row.get("dflt_value"),
+            generated: None,
+            identity: None,
+            comment: None,
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+async fn fetch_constraints(
+    pool: &Pool<Sqlite>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<RelationConstraint>> {
+    let mut constraints = Vec::new();
+
+    // A rowid alias (`INTEGER PRIMARY KEY`) has no index, so the key comes from the columns
+    let primary_key = primary_key_columns(pool, schema, table).await?;
+    if !primary_key.is_empty() {
+        constraints.push(RelationConstraint {
+            name: format!("{}_pkey", table),
+            kind: ConstraintKind::PrimaryKey,
+            definition: format!("PRIMARY KEY ({})", quote_list(&primary_key)),
+            columns: primary_key,
+        });
+    }
+
+    let index_query = r#"
+        SELECT name
+        FROM pragma_index_list(?1, ?2)
+        WHERE origin = 'u'
+        ORDER BY name
+    "#;
+
+    let unique_indexes: Vec<String> = sqlx::query_scalar(index_query)
+        .bind(table)
+        .bind(schema)
+        .fetch_all(pool)
+        .await?;
+
+    for index in unique_indexes {
+        let columns: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_index_info(?1, ?2) ORDER BY seqno")
+                .bind(&index)
+                .bind(schema)
+                .fetch_all(pool)
+                .await?;
+
+        constraints.push(RelationConstraint {
+            name: index,
+            kind: ConstraintKind::Unique,
+            definition: format!("UNIQUE ({})", quote_list(&columns)),
+            columns,
+        });
+    }
+
+    Ok(constraints)
+}
+
+async fn primary_key_columns(
+    pool: &Pool<Sqlite>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<String>> {
+    Ok(
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?1, ?2) WHERE pk > 0 ORDER BY pk")
+            .bind(table)
+            .bind(schema)
+            .fetch_all(pool)
+            .await?,
+    )
+}
+
+/// Returns the foreign keys of every table in a schema
+pub(super) async fn schema_foreign_keys(
+    pool: &Pool<Sqlite>,
+    schema: &str,
+) -> DbResult<Vec<ForeignKey>> {
+    let tables: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT name FROM {}.sqlite_master WHERE type = 'table' ORDER BY name",
+        quote_ident(schema)
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut foreign_keys = Vec::new();
+    for table in tables {
+        foreign_keys.extend(fetch_foreign_keys(pool, schema, &table).await?);
+    }
+    Ok(foreign_keys)
+}
+
+/// Fetches a table's foreign keys. SQLite doesn't name them or expose whether
+/// they're deferrable, so they're named like Postgres would and reported as not deferrable.
+async fn fetch_foreign_keys(
+    pool: &Pool<Sqlite>,
+    schema: &str,
+    table: &str,
+) -> DbResult<Vec<ForeignKey>> {
+    let foreign_key_query = r#"
+        SELECT id, "table", "from", "to", on_update, on_delete
+        FROM pragma_foreign_key_list(?1, ?2)
+        ORDER BY id, seq
+    "#;
+
+    let rows = sqlx::query(foreign_key_query)
+        .bind(table)
+        .bind(schema)
+        .fetch_all(pool)
+        .await?;
+
+    // Each key has one row per column
+    let mut foreign_keys: Vec<(i64, ForeignKey)> = Vec::new();
+    for row in rows {
+        let key_id: i64 = row.get("id");
+        let referenced_table: String = row.get("table");
+        let column: String = row.get("from");
+        let referenced_column: Option<String> = row.get("to");
+
+        match foreign_keys.last_mut() {
+            Some((id, foreign_key)) if *id == key_id => {
+                foreign_key.columns.push(column);
+                foreign_key.referenced_columns.extend(referenced_column);
+            }
+            _ => foreign_keys.push((
+                key_id,
+                ForeignKey {
+                    id: format!("{}.{}", entity_id(schema, table), key_id),
+                    name: String::new(),
+                    table_id: entity_id(schema, table),
+                    columns: vec![column],
+                    referenced_table_id: entity_id(schema, &referenced_table),
+                    referenced_schema: schema.to_string(),
+                    referenced_table,
+                    referenced_columns: referenced_column.into_iter().collect(),
+                    on_update: row.get("on_update"),
+                    on_delete: row.get("on_delete"),
+                    deferrable: false,
+                    initially_deferred: false,
+                    definition: String::new(),
+                },
+            )),
+        }
+    }
+
+    let mut result = Vec::with_capacity(foreign_keys.len());
+    for (_, mut foreign_key) in foreign_keys {
+        // Keys without target columns reference the other table's primary key
+        if foreign_key.referenced_columns.is_empty() {
+            foreign_key.referenced_columns =
+                primary_key_columns(pool, schema, &foreign_key.referenced_table).await?;
+        }
+
+        foreign_key.name = format!("{}_{}_fkey", table, foreign_key.columns.join("_"));
+        foreign_key.definition = format!(
+            "FOREIGN KEY ({}) REFERENCES {} ({}) ON UPDATE {} ON DELETE {}",
+            quote_list(&foreign_key.columns),
+            quote_ident(&foreign_key.referenced_table),
+            quote_list(&foreign_key.referenced_columns),
+            foreign_key.on_update,
+            foreign_key.on_delete
+        );
+        result.push(foreign_key);
+    }
+
+    Ok(result)
+}
+
+/// Fetches the rows referenced by `value` in a foreign key `column` of a table.
+///
+/// If the column is part of a composite foreign key only that column is matched,
+/// so this can return more than one row.
+pub(super) async fn follow_reference(
+    pool: &Pool<Sqlite>,
+    table_id: &str,
+    column: &str,
+    value: &str,
+) -> DbResult<QueryResult> {
+    let (schema, table) = parse_id(pool, table_id).await?;
+
+    // Single column keys are preferred when a column is part of several
+    let foreign_key = fetch_foreign_keys(pool, schema, table)
+        .await?
+        .into_iter()
+        .filter(|foreign_key| foreign_key.columns.iter().any(|c| c == column))
+        .min_by_key(|foreign_key| foreign_key.columns.len())
+        .ok_or_else(|| {
+            DbError::NotFound(format!("Column {} doesn't reference another table", column))
+        })?;
+
+    let position = foreign_key
+        .columns
+        .iter()
+        .position(|c| c == column)
+        .unwrap_or_default();
+    let referenced_column = foreign_key
+        .referenced_columns
+        .get(position)
+        .ok_or_else(|| DbError::NotFound(format!("Referenced column of {} not found", column)))?;
+
+    // The column's affinity converts the text value when comparing
+    let sql = format!(
+        "SELECT * FROM {}.{} WHERE {} = ?1 LIMIT {}",
+        quote_ident(schema),
+        quote_ident(&foreign_key.referenced_table),
+        quote_ident(referenced_column),
+        FOLLOW_REFERENCE_LIMIT
+    );
+
+    let mut conn = pool.acquire().await?;
+    run_query_with(&mut conn, sqlx::query(&sql).bind(value), 0).await
+}
+
+fn quote_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote_ident(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
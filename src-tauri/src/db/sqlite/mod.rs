@@ -0,0 +1,371 @@
+mod decode;
+mod introspection;
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use sqlx::{
+    query::Query,
+    sqlite::{SqliteArguments, SqliteConnection, SqlitePoolOptions},
+    Column, Connection, Either, Execute, Executor, Pool, Row as SqlxRow, Sqlite, Statement,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::db::{
+    client::DatabaseClient,
+    errors::{connection_error, DbError, DbResult},
+    helpers::{column_definitions, quote_ident},
+    schema::SchemaTracker,
+    script::{leading_keyword, split_statements},
+    types::{
+        DbEntity, Dialect, EntityChanges, ForeignKey, QueryResult, RelationDetails, SchemaEntity,
+        SchemaLevelEntity, ScriptErrorMode, ServerInfo, TableLevelEntity,
+    },
+};
+
+pub struct SqliteClient {
+    connection_string: String,
+    pool: Option<Pool<Sqlite>>,
+    schema_tracker: SchemaTracker,
+}
+
+impl SqliteClient {
+    pub fn new(connection_string: &str) -> DbResult<Self> {
+        Ok(Self {
+            connection_string: connection_string.to_string(),
+            pool: None,
+            schema_tracker: SchemaTracker::default(),
+        })
+    }
+
+    // This function gets the pool or returns an error if not connected
+    fn get_pool(&self) -> DbResult<&Pool<Sqlite>> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| DbError::Connection("Database client is not connected".to_string()))
+    }
+
+    /// Names of the main database and any attached to it, which are listed as schemas
+    async fn database_names(&self) -> DbResult<Vec<String>> {
+        let pool = self.get_pool()?;
+        Ok(
+            sqlx::query_scalar("SELECT name FROM pragma_database_list ORDER BY seq")
+                .fetch_all(pool)
+                .await?,
+        )
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for SqliteClient {
+    fn get_connection_string(&self) -> String {
+        self.connection_string.clone()
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    async fn is_connected(&self) -> DbResult<bool> {
+        match self.get_pool() {
+            Ok(pool) => Ok(!pool.is_closed()),
+            Err(_) => Ok(false),
+        }
+    }
+
+    async fn test_connection(&self) -> DbResult<()> {
+        let pool = self.get_pool()?;
+        sqlx::query("SELECT 1").execute(pool).await?;
+        Ok(())
+    }
+
+    async fn probe_server(&self) -> DbResult<ServerInfo> {
+        let mut conn = SqliteConnection::connect(&self.connection_string)
+            .await
+            .map_err(connection_error)?;
+
+        // The file is empty for in-memory databases
+        let info_query = r#"
+            SELECT sqlite_version() AS version, nullif(file, '') AS database
+            FROM pragma_database_list
+            WHERE name = 'main'
+        "#;
+        let row = sqlx::query(info_query).fetch_one(&mut conn).await?;
+        let _ = conn.close().await;
+
+        Ok(ServerInfo {
+            version: row.get("version"),
+            user: None,
+            database: row.get("database"),
+            tls: false,
+            tls_version: None,
+            tls_cipher: None,
+        })
+    }
+
+    async fn connect(&mut self) -> DbResult<()> {
+        // Check if already connected
+        if let Ok(true) = self.is_connected().await {
+            return Ok(());
+        }
+
+        // SQLite only allows one writer at a time, and a single connection means
+        // state like an open `BEGIN` or `ATTACH` carries over between queries
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&self.connection_string)
+            .await
+            .map_err(connection_error)?;
+
+        self.pool = Some(pool);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> DbResult<()> {
+        if let Ok(true) = self.is_connected().await {
+            if let Some(pool) = self.pool.take() {
+                pool.close().await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connect().await
+    }
+
+    async fn reconnect_with_string(&mut self, connection_string: &str) -> DbResult<()> {
+        self.disconnect().await?;
+        self.connection_string = connection_string.to_string();
+        self.connect().await
+    }
+
+    async fn execute_query(&self, _query_id: &str, sql: &str) -> DbResult<QueryResult> {
+        let mut conn = self.get_pool()?.acquire().await?;
+        run_query(&mut conn, sql, 0).await
+    }
+
+    async fn execute_script(
+        &self,
+        _query_id: &str,
+        sql: &str,
+        on_error: ScriptErrorMode,
+    ) -> DbResult<Vec<QueryResult>> {
+        let mut conn = self.get_pool()?.acquire().await?;
+        let mut results = Vec::new();
+
+        for (i, statement) in split_statements(sql).into_iter().enumerate() {
+            let started = Instant::now();
+            match run_query(&mut conn, statement, i).await {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    let mut result = QueryResult::new(statement, i);
+                    result.execution_time_ms = started.elapsed().as_millis() as u64;
+                    result.error = Some(e.to_string());
+                    results.push(result);
+
+                    if on_error == ScriptErrorMode::Stop {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn get_all_entities(&self) -> DbResult<HashMap<String, DbEntity>> {
+        let pool = self.get_pool()?;
+        let mut entities = HashMap::new();
+
+        for schema in self.database_names().await? {
+            let object_query = format!(
+                r#"
+                SELECT type, name, tbl_name
+                FROM {}.sqlite_master
+                WHERE type IN ('table', 'view', 'index', 'trigger')
+                ORDER BY name
+                "#,
+                quote_ident(&schema)
+            );
+
+            let rows = sqlx::query(&object_query).fetch_all(pool).await?;
+            let mut children = Vec::new();
+            for row in rows {
+                let kind: String = row.get("type");
+                let name: String = row.get("name");
+                let table_name: String = row.get("tbl_name");
+                let id = entity_id(&schema, &name);
+                // Internal tables and the indexes backing UNIQUE and PRIMARY KEY constraints
+                let is_system = name.starts_with("sqlite_");
+
+                let entity = match kind.as_str() {
+                    "table" | "view" => {
+                        children.push(id.clone());
+                        let schema_level = SchemaLevelEntity {
+                            id: id.clone(),
+                            name,
+                            is_system,
+                            schema_id: schema.clone(),
+                            extension_name: None,
+                        };
+                        if kind == "table" {
+                            DbEntity::Table(schema_level)
+                        } else {
+                            DbEntity::View(schema_level)
+                        }
+                    }
+                    _ => {
+                        let table_level = TableLevelEntity {
+                            id: id.clone(),
+                            name,
+                            is_system,
+                            table_id: entity_id(&schema, &table_name),
+                        };
+                        if kind == "index" {
+                            DbEntity::Index(table_level)
+                        } else {
+                            DbEntity::Trigger(table_level)
+                        }
+                    }
+                };
+
+                entities.insert(id, entity);
+            }
+
+            entities.insert(
+                schema.clone(),
+                DbEntity::Schema(SchemaEntity {
+                    id: schema.clone(),
+                    name: schema,
+                    is_system: false,
+                    extension_name: None,
+                    children,
+                }),
+            );
+        }
+
+        Ok(entities)
+    }
+
+    async fn schema_fingerprint(&self) -> DbResult<String> {
+        let pool = self.get_pool()?;
+
+        // Every database keeps a counter that is bumped whenever its schema changes
+        let mut versions = Vec::new();
+        for schema in self.database_names().await? {
+            let version: i64 =
+                sqlx::query_scalar(&format!("PRAGMA {}.schema_version", quote_ident(&schema)))
+                    .fetch_one(pool)
+                    .await?;
+            versions.push(format!("{}:{}", schema, version));
+        }
+
+        Ok(versions.join(","))
+    }
+
+    async fn get_entity_changes(&self, since: Option<&str>) -> DbResult<EntityChanges> {
+        let fingerprint = self.schema_fingerprint().await?;
+        if let Some(changes) = SchemaTracker::unchanged(&fingerprint, since) {
+            return Ok(changes);
+        }
+
+        let entities = self.get_all_entities().await?;
+        Ok(self.schema_tracker.changes(fingerprint, since, entities))
+    }
+
+    async fn check_schema_changed(&self) -> DbResult<Option<String>> {
+        let fingerprint = self.schema_fingerprint().await?;
+        Ok(self.schema_tracker.check(fingerprint))
+    }
+
+    async fn get_entity_ddl(&self, id: &str) -> DbResult<String> {
+        introspection::entity_ddl(self.get_pool()?, id).await
+    }
+
+    async fn describe_relation(&self, id: &str) -> DbResult<RelationDetails> {
+        introspection::describe_relation(self.get_pool()?, id).await
+    }
+
+    async fn get_foreign_keys(&self) -> DbResult<Vec<ForeignKey>> {
+        let pool = self.get_pool()?;
+        let mut foreign_keys = Vec::new();
+        for schema in self.database_names().await? {
+            foreign_keys.extend(introspection::schema_foreign_keys(pool, &schema).await?);
+        }
+        Ok(foreign_keys)
+    }
+
+    async fn follow_reference(
+        &self,
+        table_id: &str,
+        column: &str,
+        value: &str,
+    ) -> DbResult<QueryResult> {
+        introspection::follow_reference(self.get_pool()?, table_id, column, value).await
+    }
+}
+
+async fn run_query(
+    conn: &mut SqliteConnection,
+    sql: &str,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    run_query_with(conn, sqlx::query(sql), result_index).await
+}
+
+async fn run_query_with<'q>(
+    conn: &mut SqliteConnection,
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    result_index: usize,
+) -> DbResult<QueryResult> {
+    let sql = query.sql().to_string();
+    let mut result = QueryResult::new(&sql, result_index);
+    result.command = leading_keyword(&sql);
+
+    // Only time spent stepping the statement counts, not decoding
+    let mut elapsed = Duration::ZERO;
+    {
+        let mut stream = (&mut *conn).fetch_many(query);
+        loop {
+            let polled = Instant::now();
+            let item = stream.try_next().await?;
+            elapsed += polled.elapsed();
+
+            match item {
+                Some(Either::Left(done)) => {
+                    result.rows_affected =
+                        Some(result.rows_affected.unwrap_or(0) + done.rows_affected());
+                }
+                Some(Either::Right(row)) => {
+                    if result.columns.is_empty() {
+                        result.columns = column_definitions(row.columns());
+                    }
+
+                    let mut values = Vec::with_capacity(row.len());
+                    for (i, column) in row.columns().iter().enumerate() {
+                        values.push(decode::to_cell(row.try_get_raw(i)?, column.type_info()));
+                    }
+                    result.rows.push(values);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Queries that return no rows should still report their columns
+    if result.columns.is_empty() {
+        let statement = (&mut *conn).prepare(&sql).await?;
+        result.columns = column_definitions(statement.columns());
+    }
+
+    result.execution_time_ms = elapsed.as_millis() as u64;
+    Ok(result)
+}
+
+/// Entity IDs are the schema (database) name and object name, which SQLite
+/// requires to be unique across tables, views, indexes and triggers
+fn entity_id(schema: &str, name: &str) -> String {
+    format!("{}.{}", schema, name)
+}
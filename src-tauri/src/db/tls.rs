@@ -0,0 +1,52 @@
+use std::path::Path;
+
+/// How strictly the server's certificate is checked, named like libpq's `sslmode`
+#[taurpc::ipc_type]
+#[serde(rename_all = "kebab-case")]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    /// Never use TLS
+    Disable,
+    /// Only use TLS if the server requires it
+    Allow,
+    /// Use TLS if the server supports it
+    Prefer,
+    /// Always use TLS without checking the certificate
+    Require,
+    /// Check the certificate was signed by a trusted CA
+    VerifyCa,
+    /// Check the certificate was signed by a trusted CA and matches the host name
+    VerifyFull,
+}
+
+/// TLS settings from a project's config, which override the connection string's
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Default)]
+pub struct TlsSettings {
+    pub ssl_mode: Option<SslMode>,
+    /// CA certificates (PEM) to check the server's certificate against, e.g. for a private CA
+    pub root_cert_path: Option<String>,
+    /// Client certificate (PEM) to log in with
+    pub client_cert_path: Option<String>,
+    /// Private key (PEM) of the client certificate
+    pub client_key_path: Option<String>,
+}
+
+impl TlsSettings {
+    /// Resolves relative certificate paths against the project directory
+    pub fn resolve_paths(&mut self, project_dir: &Path) {
+        for path in [
+            &mut self.root_cert_path,
+            &mut self.client_cert_path,
+            &mut self.client_key_path,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if Path::new(path.as_str()).is_relative() {
+                *path = project_dir.join(&*path).to_string_lossy().to_string();
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, Command};
+
+use crate::db::{
+    errors::{DbError, DbResult},
+    types::ConnectionErrorKind,
+};
+
+/// How long to wait for the tunnel to start forwarding
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often to check whether the tunnel is forwarding yet
+const TUNNEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// SSH bastion a project's database is reached through
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct SshSettings {
+    /// Host name of the bastion
+    pub host: String,
+    /// SSH port of the bastion (default 22)
+    pub port: Option<u16>,
+    /// User to log in as, defaults to the one in `~/.ssh/config` or the current user
+    pub user: Option<String>,
+    /// Private key to log in with. Without one the SSH agent and default keys are used.
+    pub key_path: Option<String>,
+    /// Whether keys from the SSH agent may be used (default true)
+    pub use_agent: Option<bool>,
+    /// Whether the bastion's host key must already be in `known_hosts` (default true)
+    pub check_host_key: Option<bool>,
+    /// `known_hosts` file to check the host key against, instead of `~/.ssh/known_hosts`
+    pub known_hosts_path: Option<String>,
+}
+
+impl SshSettings {
+    /// Checks settings ssh would read as options instead of a destination
+    pub fn validate(&self) -> Result<(), String> {
+        if self.host.is_empty() || self.host.starts_with('-') {
+            return Err(format!("ssh.host isn't a valid host name: {:?}", self.host));
+        }
+        if let Some(user) = &self.user {
+            if user.is_empty() || user.starts_with('-') {
+                return Err(format!("ssh.user isn't a valid user name: {:?}", user));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A local port forwarded to a database through an `ssh` process. The process
+/// is killed when the tunnel is closed or dropped.
+pub struct SshTunnel {
+    process: Child,
+    local_port: u16,
+}
+
+impl SshTunnel {
+    /// Starts forwarding a free local port to `target_host:target_port` (as seen
+    /// from the bastion) and waits until it accepts connections
+    pub async fn open(
+        settings: &SshSettings,
+        target_host: &str,
+        target_port: u16,
+    ) -> DbResult<Self> {
+        settings.validate().map_err(DbError::Config)?;
+        let local_port = free_local_port().await?;
+
+        let mut command = Command::new("ssh");
+        command
+            .args(ssh_args(settings, local_port, target_host, target_port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let process = command.spawn().map_err(|e| {
+            DbError::Connection(format!("Couldn't start ssh, is it installed? {}", e))
+        })?;
+        let mut tunnel = Self {
+            process,
+            local_port,
+        };
+        tunnel.wait_until_forwarding().await?;
+
+        log::info!(
+            "Forwarding 127.0.0.1:{} to {}:{} through {}",
+            local_port,
+            target_host,
+            target_port,
+            settings.host
+        );
+        Ok(tunnel)
+    }
+
+    /// Local port the database can be connected to
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// Stops forwarding and waits for the `ssh` process to exit
+    pub async fn close(mut self) {
+        if let Err(e) = self.process.kill().await {
+            log::warn!("Failed to stop SSH tunnel: {}", e);
+        }
+    }
+
+    async fn wait_until_forwarding(&mut self) -> DbResult<()> {
+        let started = Instant::now();
+        loop {
+            // ssh exits if it can't log in or set up the forward
+            if self.process.try_wait()?.is_some() {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = self.process.stderr.take() {
+                    let _ = pipe.read_to_string(&mut stderr).await;
+                }
+                return Err(ssh_error(stderr.trim()));
+            }
+
+            // The local port only accepts connections once ssh has logged in
+            if TcpStream::connect(("127.0.0.1", self.local_port))
+                .await
+                .is_ok()
+            {
+                self.log_errors();
+                return Ok(());
+            }
+
+            if started.elapsed() > TUNNEL_TIMEOUT {
+                let _ = self.process.kill().await;
+                return Err(DbError::ConnectionFailed(
+                    ConnectionErrorKind::Timeout,
+                    format!(
+                        "SSH tunnel didn't start within {} seconds",
+                        TUNNEL_TIMEOUT.as_secs()
+                    ),
+                ));
+            }
+            tokio::time::sleep(TUNNEL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Logs what ssh reports while forwarding, e.g. dropped channels. Reading it
+    /// also keeps the pipe from filling up and blocking ssh.
+    fn log_errors(&mut self) {
+        if let Some(pipe) = self.process.stderr.take() {
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(pipe).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    log::warn!("ssh: {}", line);
+                }
+            });
+        }
+    }
+}
+
+/// Arguments to run ssh with, forwarding `local_port` to the target
+fn ssh_args(
+    settings: &SshSettings,
+    local_port: u16,
+    target_host: &str,
+    target_port: u16,
+) -> Vec<String> {
+    // ssh reads the colons of an IPv6 address as separators unless it's bracketed
+    let target_host = if target_host.contains(':') && !target_host.starts_with('[') {
+        format!("[{}]", target_host)
+    } else {
+        target_host.to_string()
+    };
+
+    let mut args = vec![
+        "-N".to_string(),
+        "-L".to_string(),
+        format!("127.0.0.1:{}:{}:{}", local_port, target_host, target_port),
+        "-p".to_string(),
+        settings.port.unwrap_or(22).to_string(),
+    ];
+    // Fail instead of prompting for passwords or host keys, there's no terminal
+    for option in [
+        "BatchMode=yes",
+        "ExitOnForwardFailure=yes",
+        "ServerAliveInterval=15",
+    ] {
+        args.extend(["-o".to_string(), option.to_string()]);
+    }
+
+    if let Some(key_path) = &settings.key_path {
+        args.extend([
+            "-i".to_string(),
+            key_path.clone(),
+            "-o".to_string(),
+            "IdentitiesOnly=yes".to_string(),
+        ]);
+    }
+    if settings.use_agent == Some(false) {
+        args.extend(["-o".to_string(), "IdentityAgent=none".to_string()]);
+    }
+    if settings.check_host_key == Some(false) {
+        let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+        args.extend([
+            "-o".to_string(),
+            "StrictHostKeyChecking=no".to_string(),
+            "-o".to_string(),
+            format!("UserKnownHostsFile={}", null),
+        ]);
+    } else {
+        args.extend(["-o".to_string(), "StrictHostKeyChecking=yes".to_string()]);
+        if let Some(known_hosts_path) = &settings.known_hosts_path {
+            args.extend([
+                "-o".to_string(),
+                format!("UserKnownHostsFile={}", known_hosts_path),
+            ]);
+        }
+    }
+
+    let destination = match &settings.user {
+        Some(user) => format!("{}@{}", user, settings.host),
+        None => settings.host.clone(),
+    };
+    // Nothing after `--` is read as an option
+    args.extend(["--".to_string(), destination]);
+    args
+}
+
+/// Asks the OS for a free port. Another process could take it before ssh binds
+/// it, in which case ssh exits because of `ExitOnForwardFailure`.
+async fn free_local_port() -> DbResult<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Classifies why ssh exited from its error output
+fn ssh_error(stderr: &str) -> DbError {
+    let message = if stderr.is_empty() {
+        "SSH tunnel exited".to_string()
+    } else {
+        format!("SSH tunnel failed: {}", stderr)
+    };
+
+    let lower = stderr.to_lowercase();
+    let kind = if lower.contains("permission denied") {
+        ConnectionErrorKind::AuthFailed
+    } else if lower.contains("could not resolve hostname") {
+        ConnectionErrorKind::Dns
+    } else if lower.contains("connection refused") {
+        ConnectionErrorKind::Refused
+    } else if lower.contains("timed out") {
+        ConnectionErrorKind::Timeout
+    } else {
+        ConnectionErrorKind::Other
+    };
+
+    DbError::ConnectionFailed(kind, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(host: &str) -> SshSettings {
+        SshSettings {
+            host: host.to_string(),
+            port: None,
+            user: None,
+            key_path: None,
+            use_agent: None,
+            check_host_key: None,
+            known_hosts_path: None,
+        }
+    }
+
+    #[test]
+    fn rejects_hosts_and_users_read_as_options() {
+        assert!(settings("bastion.example.com").validate().is_ok());
+        for host in ["", "-oProxyCommand=touch /tmp/x"] {
+            assert!(settings(host).validate().is_err(), "{:?}", host);
+        }
+        for user in ["", "-oProxyCommand=touch /tmp/x"] {
+            let settings = SshSettings {
+                user: Some(user.to_string()),
+                ..settings("bastion.example.com")
+            };
+            assert!(settings.validate().is_err(), "{:?}", user);
+        }
+    }
+
+    #[test]
+    fn builds_ssh_arguments() {
+        assert_eq!(
+            ssh_args(&settings("bastion.example.com"), 40000, "db.internal", 5432),
+            [
+                "-N",
+                "-L",
+                "127.0.0.1:40000:db.internal:5432",
+                "-p",
+                "22",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ExitOnForwardFailure=yes",
+                "-o",
+                "ServerAliveInterval=15",
+                "-o",
+                "StrictHostKeyChecking=yes",
+                "--",
+                "bastion.example.com",
+            ]
+        );
+
+        let settings = SshSettings {
+            port: Some(2222),
+            user: Some("deploy".to_string()),
+            key_path: Some("/keys/id_ed25519".to_string()),
+            use_agent: Some(false),
+            check_host_key: Some(false),
+            ..settings("bastion.example.com")
+        };
+        let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+        assert_eq!(
+            ssh_args(&settings, 40000, "10.0.0.5", 3306),
+            [
+                "-N".to_string(),
+                "-L".to_string(),
+                "127.0.0.1:40000:10.0.0.5:3306".to_string(),
+                "-p".to_string(),
+                "2222".to_string(),
+                "-o".to_string(),
+                "BatchMode=yes".to_string(),
+                "-o".to_string(),
+                "ExitOnForwardFailure=yes".to_string(),
+                "-o".to_string(),
+                "ServerAliveInterval=15".to_string(),
+                "-i".to_string(),
+                "/keys/id_ed25519".to_string(),
+                "-o".to_string(),
+                "IdentitiesOnly=yes".to_string(),
+                "-o".to_string(),
+                "IdentityAgent=none".to_string(),
+                "-o".to_string(),
+                "StrictHostKeyChecking=no".to_string(),
+                "-o".to_string(),
+                format!("UserKnownHostsFile={}", null),
+                "--".to_string(),
+                "deploy@bastion.example.com".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn brackets_ipv6_targets() {
+        let settings = SshSettings {
+            known_hosts_path: Some("/keys/known_hosts".to_string()),
+            ..settings("bastion.example.com")
+        };
+        for host in ["::1", "[::1]"] {
+            let args = ssh_args(&settings, 40000, host, 5432);
+            assert_eq!(args[2], "127.0.0.1:40000:[::1]:5432");
+            assert!(args.contains(&"UserKnownHostsFile=/keys/known_hosts".to_string()));
+        }
+    }
+}
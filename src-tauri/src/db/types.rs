@@ -1,6 +1,7 @@
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
+
 /// Database query result
 #[taurpc::ipc_type]
 #[serde(rename_all = "camelCase")]
@@ -10,18 +11,267 @@ pub struct QueryResult {
     pub timestamp: u64,
     /// Query that was executed
     pub query: String,
-    /// Rows affected (for DML statements)
+    /// Leading SQL command of the query (e.g. `UPDATE`)
+    pub command: Option<String>,
+    /// Rows affected (for DML statements) or returned, as reported by the server
     pub rows_affected: Option<u64>,
-    /// Execution time in milliseconds
+    /// Time spent waiting on the server in milliseconds
     pub execution_time_ms: u64,
     /// Column definitions
     pub columns: Vec<ColumnDefinition>,
-    /// Result rows (for SELECT statements)
+    /// Result rows (for SELECT statements), positionally matching `columns`
     pub rows: Vec<Row>,
     /// Any warning messages
     pub warnings: Vec<String>,
     /// Sequential result number when multiple statements are executed
     pub result_index: usize,
+    /// Error message if the statement failed (only set when executing scripts)
+    pub error: Option<String>,
+}
+
+impl QueryResult {
+    /// Creates an empty result for a query, timestamped now
+    pub fn new(query: &str, result_index: usize) -> Self {
+        Self {
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            query: query.to_string(),
+            command: None,
+            rows_affected: None,
+            execution_time_ms: 0,
+            columns: Vec::new(),
+            rows: Vec::new(),
+            warnings: Vec::new(),
+            result_index,
+            error: None,
+        }
+    }
+}
+
+/// What to do when a statement in a script fails
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum ScriptErrorMode {
+    /// Stop executing at the first failed statement
+    Stop,
+    /// Keep executing the remaining statements
+    Continue,
+}
+
+/// A page of rows streamed from a cursor opened with `open_cursor`
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct RowBatch {
+    /// Query the rows belong to
+    pub query_id: String,
+    /// Rows in this batch, positionally matching the cursor's columns
+    pub rows: Vec<Row>,
+    /// Total number of rows fetched so far, including this batch
+    pub rows_fetched: u64,
+    /// Time spent waiting on the server for this batch in milliseconds
+    pub execution_time_ms: u64,
+    /// Whether there are no more rows to fetch
+    pub done: bool,
+    /// Whether fetching stopped because the project's row cap was reached
+    pub capped: bool,
+}
+
+/// State of a window's explicit transaction session
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Default)]
+pub struct TransactionState {
+    /// Whether a transaction is open on the pinned session connection
+    pub active: bool,
+    /// When the transaction was opened, in seconds since the epoch
+    pub started_at: Option<u64>,
+}
+
+/// A query plan captured with `EXPLAIN`
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct QueryPlan {
+    /// Query that was explained
+    pub query: String,
+    /// Whether the query was actually run (`EXPLAIN ANALYZE`), so actual values are set
+    pub analyzed: bool,
+    /// Time spent planning in milliseconds (only reported when analyzed)
+    pub planning_time_ms: Option<f64>,
+    /// Time spent executing in milliseconds (only reported when analyzed)
+    pub execution_time_ms: Option<f64>,
+    /// Root node of the plan
+    pub root: PlanNode,
+}
+
+/// A single node in a query plan tree
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct PlanNode {
+    /// Node type, e.g. `Seq Scan` or `Hash Join`
+    pub node_type: String,
+    /// Table the node reads from, for scans
+    pub relation_name: Option<String>,
+    /// Schema of `relation_name`
+    pub schema: Option<String>,
+    /// Alias the relation is referenced by in the query
+    pub alias: Option<String>,
+    /// Index used by index scans
+    pub index_name: Option<String>,
+    /// Join type for join nodes, e.g. `Inner` or `Left`
+    pub join_type: Option<String>,
+    /// Filter or join condition, if any
+    pub condition: Option<String>,
+    /// Estimated cost before the first row is returned
+    pub startup_cost: f64,
+    /// Estimated cost to return all rows
+    pub total_cost: f64,
+    /// Estimated rows returned per loop
+    pub plan_rows: f64,
+    /// Actual rows returned per loop
+    pub actual_rows: Option<f64>,
+    /// Number of times the node was executed
+    pub actual_loops: Option<f64>,
+    /// Actual time before the first row was returned, per loop in milliseconds
+    pub actual_startup_time_ms: Option<f64>,
+    /// Actual time to return all rows, per loop in milliseconds
+    pub actual_total_time_ms: Option<f64>,
+    /// Rows read and then discarded by the node's filter
+    pub rows_removed_by_filter: Option<f64>,
+    /// Buffer usage, only set when buffers were requested
+    pub buffers: Option<PlanBuffers>,
+    /// Problems detected on this node
+    pub warnings: Vec<PlanWarning>,
+    /// Child nodes
+    pub children: Vec<PlanNode>,
+}
+
+/// Shared buffer usage of a plan node, in blocks
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct PlanBuffers {
+    pub shared_hit: u64,
+    pub shared_read: u64,
+    pub shared_dirtied: u64,
+    pub shared_written: u64,
+    pub temp_read: u64,
+    pub temp_written: u64,
+}
+
+/// A common problem detected in a plan node
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct PlanWarning {
+    pub kind: PlanWarningKind,
+    /// Human readable description of the problem
+    pub message: String,
+}
+
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum PlanWarningKind {
+    /// A sequential scan reading a large number of rows
+    SeqScanOnLargeTable,
+    /// Actual rows differ from the planner's estimate by an order of magnitude or more
+    RowEstimateMismatch,
+}
+
+/// Structure of a table, view or other relation
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct RelationDetails {
+    pub id: String,
+    pub name: String,
+    pub schema: String,
+    /// Kind of relation, e.g. `table` or `materialized view`
+    pub kind: String,
+    pub comment: Option<String>,
+    /// Columns in table order
+    pub columns: Vec<RelationColumn>,
+    /// Primary key, unique, check and exclusion constraints
+    pub constraints: Vec<RelationConstraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// A column of a relation, as described by the catalog
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct RelationColumn {
+    /// Position of the column in the table, starting at 1
+    pub position: i32,
+    pub name: String,
+    /// Full type including modifiers, e.g. `character varying(255)` or `integer[]`
+    pub data_type: String,
+    /// Element type when the column is an array
+    pub element_type: Option<String>,
+    /// Underlying type when the column's type is a domain
+    pub domain_base_type: Option<String>,
+    /// Allowed labels in order when the column's type is an enum
+    pub enum_values: Option<Vec<String>>,
+    pub nullable: bool,
+    pub primary_key: bool,
+    /// Default expression, not set for generated columns
+    pub default_value: Option<String>,
+    /// Expression of a stored generated column
+    pub generated: Option<String>,
+    /// `ALWAYS` or `BY DEFAULT` for identity columns
+    pub identity: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// A constraint on a relation other than a foreign key
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct RelationConstraint {
+    pub name: String,
+    pub kind: ConstraintKind,
+    /// Columns the constraint covers, in key order
+    pub columns: Vec<String>,
+    /// Constraint as it would be written in `CREATE TABLE`, e.g. `CHECK (price > 0)`
+    pub definition: String,
+}
+
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+    PrimaryKey,
+    Unique,
+    Check,
+    Exclusion,
+}
+
+/// A foreign key from a relation to another
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct ForeignKey {
+    /// OID of the constraint
+    pub id: String,
+    pub name: String,
+    /// Entity ID of the referencing table
+    pub table_id: String,
+    /// Referencing columns, matching `referenced_columns` positionally
+    pub columns: Vec<String>,
+    pub referenced_table_id: String,
+    pub referenced_schema: String,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+    /// Action on update, e.g. `CASCADE` or `NO ACTION`
+    pub on_update: String,
+    /// Action on delete, e.g. `CASCADE` or `NO ACTION`
+    pub on_delete: String,
+    pub deferrable: bool,
+    pub initially_deferred: bool,
+    pub definition: String,
 }
 
 /// Column definition in a query result
@@ -41,35 +291,109 @@ pub struct ColumnDefinition {
     pub default_value: Option<String>,
 }
 
-/// A single row in a query result
-#[taurpc::ipc_type]
-#[derive(Debug)]
-pub struct Row {
-    /// Values indexed by column name
-    pub values: HashMap<String, String>,
+/// A single row in a query result, with values in column order
+pub type Row = Vec<CellValue>;
+
+/// A single typed value in a query result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum CellValue {
+    Null,
+    Bool(bool),
+    /// Sent as a string, like the bindings type it, so large ids keep their precision
+    Int(#[serde(with = "int_string")] i64),
+    Float(f64),
+    /// Arbitrary precision numbers, kept as strings so we don't lose precision
+    Numeric(String),
+    Text(String),
+    Uuid(String),
+    /// Hex encoded binary data (e.g. `\xdeadbeef`)
+    Bytes(String),
+    Json(serde_json::Value),
+    Date(String),
+    Time(String),
+    Timestamp(String),
+    TimestampTz(String),
+    Interval(String),
+    Array(Vec<CellValue>),
 }
 
-impl From<HashMap<String, serde_json::Value>> for Row {
-    fn from(values: HashMap<String, serde_json::Value>) -> Self {
-        Self {
-            values: values
-                .into_iter()
-                .map(|(k, v)| (k, v.to_string()))
-                .collect(),
+/// A value bound to a `$n` placeholder of a parameterized query. Values are
+/// converted to the parameter's type when bound, so e.g. a `Text` value can be
+/// bound to a `uuid` or `date` parameter.
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum BindValue {
+    Null,
+    Bool(bool),
+    /// A string like in `CellValue`, plain numbers are accepted too
+    Int(#[serde(with = "int_string")] i64),
+    Float(f64),
+    Text(String),
+    Json(serde_json::Value),
+}
+
+/// Serializes an `i64` as a string, which is what the TypeScript bindings export
+/// 64-bit integers as. JSON numbers past 2^53 lose precision in the webview.
+mod int_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Int {
+            String(String),
+            Number(i64),
+        }
+
+        match Int::deserialize(deserializer)? {
+            Int::String(value) => value.parse().map_err(D::Error::custom),
+            Int::Number(value) => Ok(value),
         }
     }
 }
 
-impl From<Row> for HashMap<String, serde_json::Value> {
-    fn from(row: Row) -> Self {
-        row.values
-            .into_iter()
-            .map(|(k, v)| (k, serde_json::Value::String(v)))
-            .collect()
+impl BindValue {
+    /// Name of the value's kind, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BindValue::Null => "null",
+            BindValue::Bool(_) => "a bool",
+            BindValue::Int(_) => "an integer",
+            BindValue::Float(_) => "a float",
+            BindValue::Text(_) => "text",
+            BindValue::Json(_) => "json",
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+/// Parameters and result columns of a prepared statement
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct StatementDescription {
+    /// Parameters in placeholder order (`$1` first)
+    pub parameters: Vec<ParameterDefinition>,
+    /// Columns the statement returns, empty if it doesn't return rows
+    pub columns: Vec<ColumnDefinition>,
+}
+
+/// A `$n` placeholder of a prepared statement
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct ParameterDefinition {
+    /// Placeholder number, starting at 1
+    pub index: usize,
+    /// Type Postgres inferred for the parameter
+    pub data_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 pub struct SchemaEntity {
     pub id: String,
@@ -79,7 +403,7 @@ pub struct SchemaEntity {
     pub children: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 pub struct SchemaLevelEntity {
     pub id: String,
@@ -89,32 +413,32 @@ pub struct SchemaLevelEntity {
     pub extension_name: Option<String>,
 }
 
-// #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
-// #[serde(rename_all = "camelCase")]
-// pub struct TableLevelEntity {
-//     pub id: String,
-//     pub name: String,
-//     pub is_system: bool,
-//     pub table_id: String,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
-// #[serde(rename_all = "camelCase")]
-// pub struct DbExtension {
-//     pub id: String,
-//     pub name: String,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
-// #[serde(rename_all = "camelCase")]
-// pub struct GlobalTrigger {
-//     pub id: String,
-//     pub name: String,
-//     pub is_system: bool,
-//     pub extension_name: Option<String>,
-// }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TableLevelEntity {
+    pub id: String,
+    pub name: String,
+    pub is_system: bool,
+    pub table_id: String,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DbExtension {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalTrigger {
+    pub id: String,
+    pub name: String,
+    pub is_system: bool,
+    pub extension_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
 #[serde(tag = "kind")]
 pub enum DbEntity {
     Schema(SchemaEntity),
@@ -122,12 +446,170 @@ pub enum DbEntity {
     View(SchemaLevelEntity),
     MaterializedView(SchemaLevelEntity),
     ForeignTable(SchemaLevelEntity),
-    // Procedure(SchemaLevelEntity),
-    // CustomType(SchemaLevelEntity),
-    // Function(SchemaLevelEntity),
-    // Sequence(SchemaLevelEntity),
-    // Trigger(TableLevelEntity),
-    // Index(TableLevelEntity),
-    // Extension(DbExtension),
-    // GlobalTrigger(GlobalTrigger),
+    Procedure(SchemaLevelEntity),
+    CustomType(SchemaLevelEntity),
+    Function(SchemaLevelEntity),
+    Sequence(SchemaLevelEntity),
+    Trigger(TableLevelEntity),
+    Index(TableLevelEntity),
+    Extension(DbExtension),
+    GlobalTrigger(GlobalTrigger),
+}
+
+/// Entities that changed since a previous schema fingerprint
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct EntityChanges {
+    /// Fingerprint of the schema these changes bring the caller up to date with
+    pub fingerprint: String,
+    /// Whether `changed` holds every entity, because the previous fingerprint was unknown
+    pub full: bool,
+    /// Entities that were added or changed, keyed by ID
+    pub changed: HashMap<String, DbEntity>,
+    /// IDs of entities that no longer exist
+    pub removed: Vec<String>,
+}
+
+/// SQL dialect a backend speaks
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    MySql,
+    Sqlite,
+    DuckDb,
+}
+
+/// How query parameters are written in SQL
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum PlaceholderStyle {
+    /// Numbered placeholders (`$1..$n`)
+    Numbered,
+    /// Positional question marks (`?`)
+    QuestionMark,
+}
+
+/// Output formats of `EXPLAIN`
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum ExplainFormat {
+    Text,
+    Json,
+    Xml,
+    Yaml,
+    Tree,
+}
+
+/// What a connection supports, so the editor and grid can adapt to it
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct DbCapabilities {
+    pub dialect: Dialect,
+    /// Whether explicit transaction sessions (`begin_transaction`) can be opened
+    pub transactions: bool,
+    /// Whether savepoints can be created in a transaction session
+    pub savepoints: bool,
+    /// Whether objects are grouped in schemas (or databases, for MySQL)
+    pub schemas: bool,
+    /// Whether running queries can be cancelled
+    pub cancellation: bool,
+    /// Whether query plans can be captured with `explain_query`
+    pub query_plans: bool,
+    /// Whether `explain_query` can run the query to report actual rows and timings
+    pub analyzed_plans: bool,
+    /// Formats `explain_query` reads plans in
+    pub explain_formats: Vec<ExplainFormat>,
+    /// Whether `INSERT`, `UPDATE` and `DELETE` accept a `RETURNING` clause
+    pub returning: bool,
+    /// Character identifiers are quoted with, e.g. `"` or a backtick
+    pub identifier_quote: char,
+    pub placeholder_style: PlaceholderStyle,
+    /// Keywords to suggest when autocompleting, in uppercase
+    pub keywords: Vec<String>,
+}
+
+/// Why connecting to a database failed
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum ConnectionErrorKind {
+    /// The connection string couldn't be parsed
+    InvalidUrl,
+    /// The host name couldn't be resolved
+    Dns,
+    /// Nothing is listening on the host and port
+    Refused,
+    /// The server didn't respond in time
+    Timeout,
+    /// The server rejected the user or password
+    AuthFailed,
+    /// The database doesn't exist on the server
+    DatabaseMissing,
+    /// The TLS handshake or certificate check failed
+    Tls,
+    /// The database type isn't supported
+    Unsupported,
+    Other,
+}
+
+/// What a connection reached, reported when testing a connection string
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct ServerInfo {
+    /// Version reported by the server (or library, for SQLite and DuckDB)
+    pub version: String,
+    /// User the connection is logged in as
+    pub user: Option<String>,
+    /// Database the connection is using
+    pub database: Option<String>,
+    /// Whether the connection is encrypted with TLS
+    pub tls: bool,
+    /// TLS protocol version, e.g. `TLSv1.3`
+    pub tls_version: Option<String>,
+    /// Cipher the TLS connection is encrypted with
+    pub tls_cipher: Option<String>,
+}
+
+/// Outcome of testing a connection string, which either has server info or an error
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct ConnectionTest {
+    /// Dialect of the connection string, if it's a supported database type
+    pub dialect: Option<Dialect>,
+    /// Time spent connecting and querying the server info in milliseconds
+    pub latency_ms: u64,
+    pub server: Option<ServerInfo>,
+    pub error_kind: Option<ConnectionErrorKind>,
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sends_ints_as_strings() {
+        let value = serde_json::to_value(CellValue::Int(9_007_199_254_740_993)).unwrap();
+        assert_eq!(value, json!({"type": "int", "value": "9007199254740993"}));
+
+        let cell: CellValue = serde_json::from_value(value).unwrap();
+        assert_eq!(cell, CellValue::Int(9_007_199_254_740_993));
+    }
+
+    #[test]
+    fn binds_ints_from_strings_or_numbers() {
+        for value in [json!("-42"), json!(-42)] {
+            let bound: BindValue =
+                serde_json::from_value(json!({"type": "int", "value": value})).unwrap();
+            assert!(matches!(bound, BindValue::Int(-42)));
+        }
+
+        let invalid = json!({"type": "int", "value": "4.2"});
+        assert!(serde_json::from_value::<BindValue>(invalid).is_err());
+    }
 }
@@ -32,6 +32,7 @@ pub enum AppError {
 enum ErrorKind {
     Io(String),
     Db(String),
+    Cancelled(String),
     Config(String),
     Other(String),
 }
@@ -45,6 +46,7 @@ impl serde::Serialize for AppError {
         let error_message = self.to_string();
         let error_kind = match self {
             AppError::Io(_) => ErrorKind::Io(error_message),
+            AppError::Db(DbError::Cancelled) => ErrorKind::Cancelled(error_message),
             AppError::Db(_) => ErrorKind::Db(error_message),
             AppError::Config(_) => ErrorKind::Config(error_message),
             AppError::Other(_) => ErrorKind::Other(error_message),
@@ -1,12 +1,15 @@
-use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, Window};
+use tauri::{
+    AppHandle, CloseRequestApi, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, Window,
+};
 
 use log;
 use std::env;
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
+use crate::commands::db::watch_schema;
 use crate::errors::AppError;
 use crate::project::{Project, ProjectHandle};
-use crate::state::{cleanup_window_state, init_project_window};
+use crate::state::{cleanup_window_state, get_window_client, init_project_window};
 
 // Window labels
 pub const LAUNCHER_LABEL: &str = "launcher";
@@ -59,6 +62,62 @@ pub fn close_window(window: &Window) {
     }
 }
 
+/// Asks before closing a project window that still has a transaction open, or
+/// may have one. If the user confirms, the transaction is rolled back before the
+/// window closes.
+pub fn confirm_close_window(window: &Window, api: &CloseRequestApi) {
+    if window.label() == LAUNCHER_LABEL {
+        return;
+    }
+    let message = match open_transaction(window) {
+        Some(false) => return,
+        Some(true) => "A transaction is still open. Closing the window will roll it back.",
+        None => "A transaction may still be open. Closing the window will roll it back.",
+    };
+
+    api.prevent_close();
+
+    let window = window.clone();
+    window
+        .dialog()
+        .message(message)
+        .title("Uncommitted transaction")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Roll back and close".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |confirmed| {
+            if !confirmed {
+                return;
+            }
+
+            tauri::async_runtime::spawn(async move {
+                if let Ok(client) = get_window_client(&window) {
+                    let client = client.read().await;
+                    if client.transaction_state().active {
+                        if let Err(e) = client.rollback(None).await {
+                            log::warn!("Failed to roll back transaction: {}", e);
+                        }
+                    }
+                }
+                let _ = window.destroy();
+            });
+        });
+}
+
+/// Whether the window's client has a transaction open. The close request can't
+/// wait for the client's lock, so this is `None` if it's taken by someone
+/// waiting to (dis)connect and the state is unknown.
+fn open_transaction(window: &Window) -> Option<bool> {
+    let Ok(client) = get_window_client(window) else {
+        return Some(false);
+    };
+
+    let client = client.try_read().ok()?;
+    Some(client.transaction_state().active)
+}
+
 fn launch_window(app: &AppHandle, args: Vec<String>, cwd: &str) -> Result<(), AppError> {
     #[cfg(all(windows, not(dev)))]
     attach_console();
@@ -112,6 +171,7 @@ fn open_project_window(app: &AppHandle, handle: &ProjectHandle) -> Result<(), Ap
 
     let title = project.name.clone();
     init_project_window(app, project)?;
+    watch_schema(app, &window_label);
 
     WebviewWindowBuilder::new(app, window_label, WebviewUrl::App(PROJECT_URL.into()))
         .title(title)
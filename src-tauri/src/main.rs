@@ -55,6 +55,9 @@ async fn main() {
             Ok(())
         })
         .on_window_event(|window, event| match event {
+            tauri::WindowEvent::CloseRequested { api, .. } => {
+                launch::confirm_close_window(window, api);
+            }
             tauri::WindowEvent::Destroyed => {
                 launch::close_window(window);
             }
@@ -1,18 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use super::jsonc::strip_jsonc;
 use super::ProjectHandle;
+use crate::db::{pool::PoolSettings, tls::TlsSettings, tunnel::SshSettings};
 
 /// Represents the user-defined configuration for a project
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProjectConfig {
     /// Optional project name; if not set, it will be inferred from the directory or database name.
     pub name: Option<String>,
     /// Database connection string or path to a .env file with a DATABASE_URL variable.
     /// Format for .env path can include an environment name, e.g., "../.env|ENV_NAME".
-    pub db: String,
+    /// Takes precedence over `connection_variable` and `connection_params`.
+    pub db: Option<String>,
+    /// Environment variable holding the connection string, looked up in the process
+    /// environment and then the project's env files. Defaults to `DATABASE_URL`.
+    pub connection_variable: Option<String>,
+    /// Environment variables to build a connection URL from, used when the
+    /// connection variable isn't set or is empty
+    pub connection_params: Option<ConnectionParams>,
+    /// Mode whose `.env.{mode}` and `.env.{mode}.local` files are loaded.
+    /// Defaults to `development`.
+    pub env_mode: Option<String>,
+    /// Hard cap on the number of rows streamed for a single query.
+    /// Defaults to `DEFAULT_MAX_ROWS` if not set.
+    pub max_rows: Option<u64>,
+    /// Connection pool and session settings, e.g. to stay under a small server's
+    /// connection limit
+    pub pool: Option<PoolSettings>,
+    /// SSH bastion to tunnel the database connection through (Postgres only)
+    pub ssh: Option<SshSettings>,
+    /// TLS mode and certificates, overriding the connection string's (Postgres and MySQL).
+    /// Relative certificate paths are resolved against the project directory.
+    pub tls: Option<TlsSettings>,
+}
+
+/// Names of the environment variables holding each part of a connection URL
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionParams {
+    /// URL scheme of the database, e.g. `mysql` (the scheme itself, not a variable).
+    /// Defaults to `postgres`.
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub database: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Default hard cap on the number of rows streamed for a single query
+pub const DEFAULT_MAX_ROWS: u64 = 100_000;
+
+/// Config file names in a project directory, in order of preference
+const CONFIG_FILE_NAMES: [&str; 2] = ["config.jsonc", "config.json"];
+
+/// Path of the config file in a project directory, if it has one
+pub fn config_file_path(project_dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| project_dir.join(name))
+        .find(|path| path.is_file())
 }
 
 impl ProjectConfig {
     /// Loads a project configuration given a ProjectRef.
-    /// Attempts to read a config.json file from the project directory.
+    /// Attempts to read a config.jsonc (or config.json) file from the project directory,
+    /// which may contain comments and trailing commas.
     /// If the file doesn't exist or can't be read, returns a default configuration.
     pub fn load(project_ref: &ProjectHandle) -> Result<Self, ConfigError> {
         // If the project is temporary, return a default configuration
@@ -26,19 +81,42 @@ impl ProjectConfig {
 
             return Ok(ProjectConfig {
                 name: Some(name),
-                db: url.to_string(),
+                db: Some(url.to_string()),
+                connection_variable: None,
+                connection_params: None,
+                env_mode: None,
+                max_rows: None,
+                pool: None,
+                ssh: None,
+                tls: None,
             });
         }
 
         // For non-temporary projects, attempt to read the config file
-        let config_path = project_ref.path.join("config.json");
+        let config_path = config_file_path(&project_ref.path)
+            .unwrap_or_else(|| project_ref.path.join(CONFIG_FILE_NAMES[0]));
         match std::fs::read_to_string(&config_path) {
-            Ok(content) => {
-                serde_json::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))
-            }
+            Ok(content) => Self::parse(&content, &config_path),
             Err(e) => Err(ConfigError::Io(e)),
         }
     }
+
+    /// Parses JSONC config, reporting errors with the file, line and column
+    fn parse(content: &str, path: &Path) -> Result<Self, ConfigError> {
+        serde_json::from_str(&strip_jsonc(content)).map_err(|e| {
+            // The position is reported separately, drop it from serde's message
+            let message = e.to_string();
+            let suffix = format!(" at line {} column {}", e.line(), e.column());
+            let message = message.strip_suffix(&suffix).unwrap_or(&message);
+            ConfigError::Parse(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.line(),
+                e.column(),
+                message
+            ))
+        })
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -0,0 +1,572 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::config::ConfigError;
+
+/// Mode whose `.env.{mode}` files are loaded when a project doesn't set `envMode`
+pub const DEFAULT_ENV_MODE: &str = "development";
+
+/// How deeply `${VAR}` references may nest before they're assumed to be a cycle
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// Where a variable's value came from
+#[taurpc::ipc_type]
+#[derive(Debug, Copy, PartialEq, Eq)]
+pub enum EnvOrigin {
+    /// An env file, see `file` and `line`
+    File,
+    /// The environment sqratch was started with
+    Process,
+    /// Neither, the variable isn't set
+    Unset,
+}
+
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct EnvVarSource {
+    pub name: String,
+    pub origin: EnvOrigin,
+    /// Env file that set the value last
+    pub file: Option<String>,
+    /// Line of the definition in `file`, starting at 1
+    pub line: Option<u32>,
+}
+
+/// Which env files a project loads and where its variables come from, for
+/// debugging a connection that picks up the wrong value
+#[taurpc::ipc_type]
+#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
+pub struct EnvDiagnostics {
+    pub mode: String,
+    /// Env files that exist, from lowest to highest precedence
+    pub files: Vec<String>,
+    /// Every variable defined in the files, by name
+    pub variables: Vec<EnvVarSource>,
+    /// Variables the project's config reads the connection from
+    pub connection: Vec<EnvVarSource>,
+}
+
+/// Part of a value as written, before `${VAR}` references are expanded
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Var {
+        name: String,
+        /// Used when the variable is unset or empty, from `${VAR:-default}`
+        default: Option<String>,
+    },
+}
+
+/// A parsed definition: the variable name, its value and its line
+type Entry = (String, Vec<Segment>, usize);
+
+#[derive(Debug, Clone)]
+struct Definition {
+    segments: Vec<Segment>,
+    file: PathBuf,
+    line: usize,
+}
+
+/// Variables from a chain of env files, with the process environment on top.
+///
+/// For a base file `.env` the chain is `.env`, `.env.local`, `.env.{mode}` and
+/// `.env.{mode}.local`, where later files override earlier ones. A variable set
+/// in the process environment overrides every file, like `DATABASE_URL=... sqratch`
+/// would for a single run. References are expanded after layering, so `${HOST}`
+/// in `.env` sees the `HOST` from `.env.local`. A variable that refers to itself,
+/// like `DATABASE_URL=${DATABASE_URL}?sslmode=require`, sees the definition it
+/// overrides instead.
+#[derive(Debug)]
+pub struct Env {
+    mode: String,
+    files: Vec<PathBuf>,
+    /// Every definition of each variable, from lowest to highest precedence
+    definitions: HashMap<String, Vec<Definition>>,
+}
+
+impl Env {
+    /// Loads the chain of env files for `base`. If `required` is set, `base`
+    /// itself has to exist, the other files are always optional.
+    pub fn load(base: &Path, mode: &str, required: bool) -> Result<Self, ConfigError> {
+        let mut env = Self {
+            mode: mode.to_string(),
+            files: Vec::new(),
+            definitions: HashMap::new(),
+        };
+
+        let base_name = base.to_string_lossy();
+        let chain = [
+            base.to_path_buf(),
+            PathBuf::from(format!("{}.local", base_name)),
+            PathBuf::from(format!("{}.{}", base_name, mode)),
+            PathBuf::from(format!("{}.{}.local", base_name, mode)),
+        ];
+        for (i, path) in chain.into_iter().enumerate() {
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && (i > 0 || !required) => {
+                    continue;
+                }
+                Err(e) => return Err(ConfigError::Io(e)),
+            };
+
+            let entries = parse(&content, &path).map_err(|(line, message)| {
+                ConfigError::Parse(format!("{}:{}: {}", path.display(), line, message))
+            })?;
+            for (name, segments, line) in entries {
+                let definition = Definition {
+                    segments,
+                    file: path.clone(),
+                    line,
+                };
+                env.definitions.entry(name).or_default().push(definition);
+            }
+            env.files.push(path);
+        }
+
+        Ok(env)
+    }
+
+    /// The expanded value of a variable, or `None` if it's unset or empty
+    pub fn get(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        let value = self.expand_var(name, None, 0)?;
+        Ok(value.filter(|value| !value.is_empty()))
+    }
+
+    /// Where a variable's value comes from
+    pub fn source(&self, name: &str) -> EnvVarSource {
+        let from_process = std::env::var_os(name).is_some();
+        let definition = self
+            .definitions
+            .get(name)
+            .and_then(|stack| stack.last())
+            .filter(|_| !from_process);
+        let origin = match definition {
+            Some(_) => EnvOrigin::File,
+            None if from_process => EnvOrigin::Process,
+            None => EnvOrigin::Unset,
+        };
+        EnvVarSource {
+            name: name.to_string(),
+            origin,
+            file: definition.map(|d| d.file.to_string_lossy().to_string()),
+            line: definition.map(|d| d.line as u32),
+        }
+    }
+
+    /// The loaded files and the sources of `names`, along with every variable the files define
+    pub fn diagnostics(&self, names: &[&str]) -> EnvDiagnostics {
+        let mut defined: Vec<&String> = self.definitions.keys().collect();
+        defined.sort();
+
+        EnvDiagnostics {
+            mode: self.mode.clone(),
+            files: self
+                .files
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            variables: defined.into_iter().map(|name| self.source(name)).collect(),
+            connection: names.iter().map(|name| self.source(name)).collect(),
+        }
+    }
+
+    /// Expands the highest definition of `name` below `below`. If `below` is
+    /// `None`, the process environment's value wins over every definition.
+    fn expand_var(
+        &self,
+        name: &str,
+        below: Option<usize>,
+        depth: usize,
+    ) -> Result<Option<String>, ConfigError> {
+        if below.is_none() {
+            if let Ok(value) = std::env::var(name) {
+                return Ok(Some(value));
+            }
+        }
+        let stack = self.definitions.get(name).map_or(&[][..], Vec::as_slice);
+        let index = match below.unwrap_or(stack.len()).checked_sub(1) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let definition = &stack[index];
+        if depth > MAX_EXPANSION_DEPTH {
+            return Err(ConfigError::Parse(format!(
+                "{}:{}: {} refers to itself through ${{...}} references",
+                definition.file.display(),
+                definition.line,
+                name
+            )));
+        }
+
+        let mut value = String::new();
+        for segment in &definition.segments {
+            match segment {
+                Segment::Text(text) => value.push_str(text),
+                Segment::Var { name: var, default } => {
+                    // A reference to the variable itself extends the definition it overrides
+                    let below = (var == name).then_some(index);
+                    let expanded = self.expand_var(var, below, depth + 1)?;
+                    match (expanded, default) {
+                        (Some(expanded), _) if !expanded.is_empty() => value.push_str(&expanded),
+                        (_, Some(default)) => value.push_str(default),
+                        (expanded, None) => value.push_str(&expanded.unwrap_or_default()),
+                    }
+                }
+            }
+        }
+        Ok(Some(value))
+    }
+}
+
+/// Parses the definitions in an env file, returning each variable's name, value
+/// and line. Errors are returned with the line they occur on.
+///
+/// Supports `export` prefixes, `#` comments (inline ones need a space before them),
+/// single-quoted literal values, double-quoted values with escapes, quoted values
+/// spanning several lines, and `$VAR`, `${VAR}` and `${VAR:-default}` references
+/// in unquoted and double-quoted values.
+///
+/// Lines without a `=` are skipped with a warning naming `path`.
+fn parse(content: &str, path: &Path) -> Result<Vec<Entry>, (usize, String)> {
+    let mut entries = Vec::new();
+    let mut lines = content.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.strip_prefix("export") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+            _ => line,
+        };
+
+        // The line isn't logged, it could be a stray secret
+        let Some((name, value)) = line.split_once('=') else {
+            log::warn!(
+                "{}:{}: skipping a line without NAME=value",
+                path.display(),
+                line_number
+            );
+            continue;
+        };
+        let name = name.trim();
+        if !is_var_name(name) {
+            return Err((line_number, format!("invalid variable name {:?}", name)));
+        }
+
+        let value = value.trim_start();
+        let segments = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                // Quoted values run until the closing quote, which may be on a later line
+                let mut raw = value[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&raw, quote) {
+                        break end;
+                    }
+                    match lines.next() {
+                        Some((_, next)) => {
+                            raw.push('\n');
+                            raw.push_str(next);
+                        }
+                        None => return Err((line_number, format!("unterminated {} quote", quote))),
+                    }
+                };
+                let rest = raw[end + 1..].trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err((
+                        line_number,
+                        format!("unexpected text after value: {}", rest),
+                    ));
+                }
+
+                let raw = &raw[..end];
+                if quote == '\'' {
+                    vec![Segment::Text(raw.to_string())]
+                } else {
+                    parse_segments(raw, true)
+                }
+            }
+            _ => {
+                let value = match value.find(" #").or_else(|| value.find("\t#")) {
+                    Some(comment) => &value[..comment],
+                    None => value,
+                };
+                parse_segments(value.trim_end(), false)
+            }
+        };
+
+        entries.push((name.to_string(), segments, line_number));
+    }
+
+    Ok(entries)
+}
+
+/// Byte index of the quote that closes a value, skipping escaped ones in double quotes
+fn closing_quote(raw: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in raw.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits a value into text and variable references, handling escapes in
+/// double-quoted values
+fn parse_segments(value: &str, escapes: bool) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escapes => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some(c @ ('\\' | '"' | '$')) => text.push(c),
+                Some(c) => {
+                    text.push('\\');
+                    text.push(c);
+                }
+                None => text.push('\\'),
+            },
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let mut inner = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    inner.push(c);
+                }
+                let (name, default) = match inner.split_once(":-") {
+                    Some((name, default)) => (name, Some(default.to_string())),
+                    None => (inner.as_str(), None),
+                };
+                if !closed || !is_var_name(name) {
+                    // Not a reference, keep it as written
+                    text.push_str("${");
+                    text.push_str(&inner);
+                    if closed {
+                        text.push('}');
+                    }
+                    continue;
+                }
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+                segments.push(Segment::Var {
+                    name: name.to_string(),
+                    default,
+                });
+            }
+            '$' if chars
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphabetic() || *c == '_') =>
+            {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+                segments.push(Segment::Var {
+                    name,
+                    default: None,
+                });
+            }
+            _ => text.push(c),
+        }
+    }
+
+    segments.push(Segment::Text(text));
+    segments
+}
+
+/// Whether a name can be used as a variable, like `DATABASE_URL` or `db.host`
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes env files to a directory of their own and loads the chain for `.env`
+    fn load(test: &str, files: &[(&str, &str)]) -> Env {
+        let dir =
+            std::env::temp_dir().join(format!("sqratch-dotenv-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+
+        let env = Env::load(&dir.join(".env"), DEFAULT_ENV_MODE, true).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        env
+    }
+
+    fn values(test: &str, content: &str) -> Vec<(String, String)> {
+        let env = load(test, &[(".env", content)]);
+        let mut names: Vec<&String> = env.definitions.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| (name.clone(), env.get(name).unwrap().unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_quotes_comments_and_exports() {
+        let content = r#"
+# comment
+export A=plain value # inline comment
+B='single ${A} \n'
+C="double\t\"quoted\" ${A}"
+D="first
+second" # after
+E=a#b
+"#;
+        assert_eq!(
+            values("quotes", content),
+            vec![
+                ("A".to_string(), "plain value".to_string()),
+                ("B".to_string(), "single ${A} \\n".to_string()),
+                (
+                    "C".to_string(),
+                    "double\t\"quoted\" plain value".to_string()
+                ),
+                ("D".to_string(), "first\nsecond".to_string()),
+                ("E".to_string(), "a#b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_lines_without_a_value() {
+        assert_eq!(
+            values("no-value", "A=1\nnot a definition\nB=2"),
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_quotes_with_their_line() {
+        let error = parse("A=1\nB=\"open\nC=3", Path::new(".env")).unwrap_err();
+        assert_eq!(error, (2, "unterminated \" quote".to_string()));
+    }
+
+    #[test]
+    fn expands_references_after_layering() {
+        let env = load(
+            "layering",
+            &[
+                (".env", "HOST=base\nURL=postgres://${HOST}/${DB:-app}"),
+                (".env.local", "HOST=local"),
+                (".env.development", "PORT=5432"),
+            ],
+        );
+
+        assert_eq!(
+            env.get("URL").unwrap().as_deref(),
+            Some("postgres://local/app")
+        );
+        assert_eq!(env.source("HOST").line, Some(1));
+        assert!(env.source("HOST").file.unwrap().ends_with(".env.local"));
+        assert_eq!(env.diagnostics(&[]).files.len(), 3);
+    }
+
+    #[test]
+    fn self_references_extend_the_overridden_definition() {
+        let env = load(
+            "self-reference",
+            &[
+                (".env", "DATABASE_URL=postgres://db/app"),
+                (".env.local", "DATABASE_URL=${DATABASE_URL}?sslmode=require"),
+                (
+                    ".env.development.local",
+                    "DATABASE_URL=${DATABASE_URL}&application_name=dev",
+                ),
+            ],
+        );
+
+        assert_eq!(
+            env.get("DATABASE_URL").unwrap().as_deref(),
+            Some("postgres://db/app?sslmode=require&application_name=dev")
+        );
+    }
+
+    #[test]
+    fn process_environment_overrides_the_files() {
+        let name = "SQRATCH_DOTENV_TEST_URL";
+        std::env::set_var(name, "postgres://process/app");
+        let env = load(
+            "process",
+            &[
+                (
+                    ".env",
+                    &format!("{}=postgres://file/app\nOTHER=${{{}}}", name, name),
+                ),
+                (
+                    ".env.local",
+                    &format!("{}=${{{}}}?sslmode=require", name, name),
+                ),
+            ],
+        );
+
+        assert_eq!(
+            env.get(name).unwrap().as_deref(),
+            Some("postgres://process/app")
+        );
+        assert_eq!(
+            env.get("OTHER").unwrap().as_deref(),
+            Some("postgres://process/app")
+        );
+        let source = env.source(name);
+        assert_eq!(source.origin, EnvOrigin::Process);
+        assert_eq!(source.file, None);
+        std::env::remove_var(name);
+    }
+
+    #[test]
+    fn self_references_without_an_overridden_definition_are_empty() {
+        let env = load(
+            "self-reference-bottom",
+            &[(
+                ".env",
+                "SQRATCH_DOTENV_TEST_PATH=/opt/bin:${SQRATCH_DOTENV_TEST_PATH}",
+            )],
+        );
+        assert_eq!(
+            env.get("SQRATCH_DOTENV_TEST_PATH").unwrap().as_deref(),
+            Some("/opt/bin:")
+        );
+    }
+
+    #[test]
+    fn rejects_reference_cycles() {
+        let env = load("cycle", &[(".env", "A=${B}\nB=${A}")]);
+        assert!(env.get("A").is_err());
+    }
+}
@@ -1,10 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use url::Url;
 
 use crate::errors::AppError;
 use crate::utils;
 
+use super::config::config_file_path;
 use super::helpers::hash_str;
 
 #[taurpc::ipc_type]
@@ -26,12 +27,27 @@ impl ProjectHandle {
         format!("prj-{}", self.id)
     }
 
+    /// A project for a connection string, kept in a directory in app data hashed from `key`
+    fn from_url(app_data_dir: &Path, key: &str, url: Url) -> Self {
+        let id = hash_str(key);
+        let path = app_data_dir.join("projects").join(&id);
+        let is_temp = !path.exists();
+        Self {
+            id,
+            path,
+            is_temp,
+            url: Some(url),
+        }
+    }
+
     /// Resolves a project path from CLI input to a sqratch project directory
     /// It's possible that this dir does not yet exist or does not contain a config yet.
     /// We don't load the config here yet so we can open existing project windows first.
     ///
     /// The path may be:
     /// - A connection string (converted to a hashed directory in app data)
+    /// - A SQLite database file (.db, .sqlite), opened like a `sqlite:` connection string
+    /// - A DuckDB database or a CSV/Parquet file (.duckdb, .csv, .parquet), opened with DuckDB
     /// - An absolute path
     /// - A relative path (resolved against current working directory)
     ///
@@ -39,17 +55,14 @@ impl ProjectHandle {
     pub fn from_cli_input(input: &str, cwd: &str) -> Result<Self, AppError> {
         let app_data_dir = utils::paths::app_data_dir()?;
 
-        // First, check if the input is a valid URL (connection string)
+        // Database files are checked first, a Windows path would also parse as a URL
+        if let Some(url) = database_file_url(input, cwd) {
+            return Ok(Self::from_url(&app_data_dir, url.as_str(), url.clone()));
+        }
+
+        // Check if the input is a valid URL (connection string)
         if let Ok(url) = Url::parse(input) {
-            let id = hash_str(input);
-            let path = app_data_dir.join("projects").join(&id);
-            let is_temp = !path.exists();
-            return Ok(Self {
-                id,
-                path,
-                is_temp,
-                url: Some(url),
-            });
+            return Ok(Self::from_url(&app_data_dir, input, url));
         }
 
         // Handle file paths, resolving .. and . segments
@@ -99,9 +112,9 @@ impl ProjectHandle {
                 })?,
         };
 
-        if !project_path.join("config.json").exists() {
+        if config_file_path(&project_path).is_none() {
             return Err(AppError::Other(format!(
-                "No config.json found in: {}",
+                "No config.jsonc or config.json found in: {}",
                 project_path.display()
             )));
         }
@@ -116,3 +129,31 @@ impl ProjectHandle {
         })
     }
 }
+
+/// File extensions opened as SQLite databases
+const SQLITE_EXTENSIONS: [&str; 4] = ["db", "sqlite", "sqlite3", "db3"];
+
+/// File extensions opened with DuckDB, data files are queried as tables
+const DUCKDB_EXTENSIONS: [&str; 5] = ["duckdb", "ddb", "csv", "tsv", "parquet"];
+
+/// Converts the path of an existing database or data file to a URL for the
+/// backend that opens it, e.g. `sqlite://` for SQLite databases
+fn database_file_url(input: &str, cwd: &str) -> Option<Url> {
+    let path = PathBuf::from(cwd).join(input).canonicalize().ok()?;
+    if !path.is_file() {
+        return None;
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let scheme = if SQLITE_EXTENSIONS.contains(&extension.as_str()) {
+        "sqlite"
+    } else if DUCKDB_EXTENSIONS.contains(&extension.as_str()) {
+        "duckdb"
+    } else {
+        return None;
+    };
+
+    let mut url = Url::parse(&format!("{}://", scheme)).ok()?;
+    url.set_path(&path.to_string_lossy());
+    Some(url)
+}
@@ -0,0 +1,145 @@
+/// Turns JSONC (JSON with comments and trailing commas) into plain JSON.
+/// Comments and trailing commas are replaced with spaces rather than removed, so
+/// every character keeps its line and column and parse errors point into the original.
+pub fn strip_jsonc(input: &str) -> String {
+    strip_trailing_commas(&strip_comments(input))
+}
+
+/// Blanks out `//` and `/* */` comments, keeping their line breaks. An unclosed
+/// block comment is left as is for the JSON parser to report.
+fn strip_comments(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                output.push(c);
+                copy_string(&mut chars, &mut output);
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '/') => {
+                let end = input[start..]
+                    .find('\n')
+                    .map_or(input.len(), |offset| start + offset);
+                blank(&input[start..end], &mut output);
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '*') => {
+                match input[start + 2..].find("*/") {
+                    Some(offset) => {
+                        let end = start + 2 + offset + 2;
+                        blank(&input[start..end], &mut output);
+                        while chars.peek().is_some_and(|&(i, _)| i < end) {
+                            chars.next();
+                        }
+                    }
+                    None => output.push(c),
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Blanks out commas that are followed only by whitespace before a `}` or `]`
+fn strip_trailing_commas(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                output.push(c);
+                copy_string(&mut chars, &mut output);
+            }
+            ',' => {
+                let trailing = input[start + 1..].trim_start().starts_with(['}', ']']);
+                output.push(if trailing { ' ' } else { ',' });
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Copies the rest of a string literal after its opening quote, so comment and
+/// comma characters inside it are kept
+fn copy_string(chars: &mut impl Iterator<Item = (usize, char)>, output: &mut String) {
+    let mut escaped = false;
+    for (_, c) in chars {
+        output.push(c);
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return,
+            _ => {}
+        }
+    }
+}
+
+/// Replaces text with spaces, character for character, keeping its line breaks
+fn blank(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '\n' | '\r' => output.push(c),
+            _ => output.push(' '),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blanks_comments_in_place() {
+        let input = "{\n  // line\n  \"a\": 1, /* block\n spans */ \"b\": 2\n}";
+        let output = strip_jsonc(input);
+
+        assert_eq!(
+            output,
+            "{\n         \n  \"a\": 1,         \n          \"b\": 2\n}"
+        );
+        assert_eq!(output.lines().count(), input.lines().count());
+    }
+
+    #[test]
+    fn keeps_columns_after_multibyte_comments() {
+        let input = "/* héllo ✓ */ {\"a\": 1}";
+        let output = strip_jsonc(input);
+
+        assert_eq!(output.chars().count(), input.chars().count());
+        assert_eq!(output.trim_start(), "{\"a\": 1}");
+    }
+
+    #[test]
+    fn removes_trailing_commas() {
+        let output = strip_jsonc("{\"a\": [1, 2,\n], \"b\": {\"c\": 3, },}");
+        let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(value, serde_json::json!({"a": [1, 2], "b": {"c": 3}}));
+    }
+
+    #[test]
+    fn leaves_strings_alone() {
+        let input = r#"{"url": "http://host/*x*/", "s": "a, }", "q": "\\"} // x"#;
+        let output = strip_jsonc(input);
+
+        assert_eq!(
+            output,
+            r#"{"url": "http://host/*x*/", "s": "a, }", "q": "\\"}     "#
+        );
+    }
+
+    #[test]
+    fn leaves_unclosed_block_comments_for_the_parser() {
+        let output = strip_jsonc("{} /* open");
+
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_err());
+    }
+}
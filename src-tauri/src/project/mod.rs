@@ -1,14 +1,18 @@
 mod config;
+mod dotenv;
 mod handle;
 mod helpers;
+mod jsonc;
 
 use url::Url;
 
+use crate::db::{pool::PoolSettings, tls::TlsSettings, tunnel::SshSettings};
 use crate::errors::AppError;
 
-pub use self::config::{ConfigError, ProjectConfig};
+pub use self::config::{ConfigError, ProjectConfig, DEFAULT_MAX_ROWS};
+pub use self::dotenv::EnvDiagnostics;
 pub use self::handle::ProjectHandle;
-use self::helpers::{infer_project_name, resolve_db_url};
+use self::helpers::{infer_project_name, project_env_diagnostics, resolve_project_db_url};
 
 /// Runtime reference to a project
 #[taurpc::ipc_type]
@@ -22,6 +26,14 @@ pub struct Project {
     /// Database connection string
     #[specta(type = String)]
     pub db_url: Url,
+    /// Hard cap on the number of rows streamed for a single query
+    pub max_rows: u64,
+    /// Connection pool and session settings for the project's database
+    pub pool: PoolSettings,
+    /// SSH bastion the database is reached through
+    pub ssh: Option<SshSettings>,
+    /// TLS settings for the database connection, with absolute certificate paths
+    pub tls: Option<TlsSettings>,
 }
 
 impl Project {
@@ -30,7 +42,18 @@ impl Project {
         // Try to load config from the directory
         let config = ProjectConfig::load(handle)?;
 
-        let db_url = resolve_db_url(&config.db, &handle.path)?;
+        let db_url = resolve_project_db_url(&config, &handle.path)?;
+
+        let pool = config.pool.unwrap_or_default();
+        pool.validate().map_err(ConfigError::Other)?;
+        if let Some(ssh) = &config.ssh {
+            ssh.validate().map_err(ConfigError::Other)?;
+        }
+
+        let mut tls = config.tls;
+        if let Some(tls) = &mut tls {
+            tls.resolve_paths(&handle.path);
+        }
 
         // Determine the project name if not provided in the config
         let name = match config.name {
@@ -42,9 +65,19 @@ impl Project {
             name,
             handle: handle.clone(),
             db_url,
+            max_rows: config.max_rows.unwrap_or(DEFAULT_MAX_ROWS),
+            pool,
+            ssh: config.ssh,
+            tls,
         })
     }
 
+    /// Re-reads the project's env files and reports where its connection variables come from
+    pub fn env_diagnostics(&self) -> Result<EnvDiagnostics, AppError> {
+        let config = ProjectConfig::load(&self.handle)?;
+        project_env_diagnostics(&config, &self.handle.path)
+    }
+
     pub fn window_label(&self) -> String {
         self.handle.to_window_label()
     }
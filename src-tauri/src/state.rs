@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use tauri::{AppHandle, Manager, Runtime, Window};
-use tokio::sync::Mutex;
+use tokio::sync::RwLock as AsyncRwLock;
 
 use crate::db::client::{create_client, DatabaseClient};
 use crate::errors::AppError;
@@ -10,7 +10,8 @@ use crate::project::Project;
 
 pub struct WindowState {
     project: Arc<Project>,
-    client: Arc<Mutex<dyn DatabaseClient>>,
+    /// Queries only need a read lock, so they can run (and be cancelled) concurrently
+    client: Arc<AsyncRwLock<dyn DatabaseClient>>,
 }
 
 pub struct AppState {
@@ -28,13 +29,20 @@ impl AppState {
 
 pub fn get_window_client(
     window: &Window<impl Runtime>,
-) -> Result<Arc<Mutex<dyn DatabaseClient>>, AppError> {
-    let app = window.app_handle();
+) -> Result<Arc<AsyncRwLock<dyn DatabaseClient>>, AppError> {
+    get_client_by_label(window.app_handle(), window.label())
+}
+
+/// Gets a window's client from its label, for tasks that outlive a single command
+pub fn get_client_by_label(
+    app: &AppHandle<impl Runtime>,
+    label: &str,
+) -> Result<Arc<AsyncRwLock<dyn DatabaseClient>>, AppError> {
     let state = app.state::<AppState>();
     let windows = state.windows.read().unwrap();
 
     let window_state = windows
-        .get(window.label())
+        .get(label)
         .ok_or(AppError::Other("Window not found".to_string()))?;
 
     return Ok(window_state.client.clone());
@@ -55,12 +63,17 @@ pub fn get_window_project(window: &Window<impl Runtime>) -> Result<Arc<Project>,
 pub fn init_project_window(app: &AppHandle, project: Project) -> Result<(), AppError> {
     let state = app.state::<AppState>();
 
-    let client = create_client(&project.db_url)?;
+    let client = create_client(
+        &project.db_url,
+        &project.pool,
+        project.ssh.as_ref(),
+        project.tls.as_ref(),
+    )?;
 
     let window_label = project.window_label();
     let window_state = WindowState {
         project: Arc::new(project),
-        client: Arc::new(Mutex::new(client)),
+        client,
     };
 
     state